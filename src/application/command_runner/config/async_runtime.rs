@@ -0,0 +1,50 @@
+use serde_derive::Deserialize;
+
+/// settings of the async crawl pipeline.
+///
+/// crawled responses are passed to the persist stage through a channel of `channel_size`,
+/// so crawling slows down when persisting cannot keep up.
+/// warc, json_lines, sqlite, postgres, archive and stdout outputs are written
+/// on the blocking sections of the runtime.
+/// with stream_body, each task is crawled and streamed to its outputs on a blocking section,
+/// since the body is streamed by the blocking http client. max_concurrency limits those tasks,
+/// and persist_concurrency and channel_size are not used.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct AsyncRuntimeConfig {
+    #[serde(default = "default_max_concurrency")]
+    pub max_concurrency: usize,
+
+    #[serde(default = "default_persist_concurrency")]
+    pub persist_concurrency: usize,
+
+    #[serde(default = "default_channel_size")]
+    pub channel_size: usize,
+}
+
+fn default_max_concurrency() -> usize {
+    100
+}
+
+fn default_persist_concurrency() -> usize {
+    10
+}
+
+fn default_channel_size() -> usize {
+    100
+}
+
+impl AsyncRuntimeConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = vec![];
+        if self.max_concurrency == 0 {
+            errors.push("max_concurrency of async_runtime must be positive".to_owned());
+        }
+        if self.persist_concurrency == 0 {
+            errors.push("persist_concurrency of async_runtime must be positive".to_owned());
+        }
+        if self.channel_size == 0 {
+            errors.push("channel_size of async_runtime must be positive".to_owned());
+        }
+        errors
+    }
+}
@@ -1,4 +1,5 @@
 use super::{
+    async_runtime::AsyncRuntimeConfig,
     log::LogConfig,
     notify_method::NotifyMethod,
     parser::{product_list, try_expand_numeric_list, TemplateBuilder},
@@ -7,9 +8,11 @@ use super::{
 use crate::application::{
     core_types::{
         crawler::request::{CrawlerRequest, Method},
-        persist::PersistMethod,
+        persist::{sidecar::MetadataSidecar, sqlite::is_valid_table_name, PersistMethod},
+    },
+    raven_crawl_task::{
+        late_bound_vars::late_bound_placeholders, persist_sink::stream_body_limitations, *,
     },
-    raven_crawl_task::*,
 };
 use chrono::{DateTime, Local};
 use serde_derive::Deserialize;
@@ -32,6 +35,9 @@ pub struct RavenConfig {
     pub sleep: Option<u16>,
 
     pub log: LogConfig,
+
+    /// crawl with the async pipeline instead of the thread pool if configured.
+    pub async_runtime: Option<AsyncRuntimeConfig>,
 }
 
 fn default_max_threads() -> u16 {
@@ -43,6 +49,108 @@ pub trait HasConfig {
 }
 
 impl RavenConfig {
+    /// check settings which cannot be checked by deserializing.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = self.request.validate();
+        if let Some(async_runtime) = &self.async_runtime {
+            errors.extend(async_runtime.validate());
+        }
+        if self.request.stream_body {
+            errors.extend(stream_body_limitations(&self.output));
+        }
+        errors.extend(
+            self.output
+                .iter()
+                .flat_map(|output| output.get_compression())
+                .flat_map(|compression| compression.validate()),
+        );
+        errors.extend(
+            self.output
+                .iter()
+                .flat_map(|output| output.get_s3_client_config())
+                .flat_map(|client_config| client_config.validate()),
+        );
+        for output in &self.output {
+            match output {
+                PersistMethod::LocalFile {
+                    file_options,
+                    sidecar,
+                    content_addressed,
+                    ..
+                } => {
+                    errors.extend(file_options.validate());
+                    if sidecar == &Some(MetadataSidecar::ObjectMetadata) {
+                        errors.push(
+                            "object_metadata sidecar is only supported by amazon_s3 output"
+                                .to_owned(),
+                        );
+                    }
+                    if content_addressed
+                        .as_ref()
+                        .and_then(|addressing| addressing.blob_bucket.as_ref())
+                        .is_some()
+                    {
+                        errors.push(
+                            "blob_bucket of content_addressed is only supported by amazon_s3 output"
+                                .to_owned(),
+                        );
+                    }
+                }
+                PersistMethod::AmazonS3 {
+                    object_options,
+                    upload,
+                    ..
+                } => {
+                    errors.extend(object_options.validate());
+                    errors.extend(upload.validate());
+                }
+                _ => {}
+            }
+        }
+        // a blob is shared by crawls, so it cannot have the metadata of a crawl.
+        if self.output.iter().any(|output| {
+            output.get_content_addressing().is_some()
+                && output.get_sidecar() == Some(&MetadataSidecar::ObjectMetadata)
+        }) {
+            errors.push(
+                "object_metadata sidecar is not supported with content_addressed, use json sidecar"
+                    .to_owned(),
+            );
+        }
+        for output in &self.output {
+            match output {
+                PersistMethod::Sqlite { table, .. } if !is_valid_table_name(table) => {
+                    errors.push(format!("invalid sqlite table name: {}", table));
+                }
+                PersistMethod::Postgres {
+                    table, batch_size, ..
+                } => {
+                    if !is_valid_table_name(table) {
+                        errors.push(format!("invalid postgres table name: {}", table));
+                    }
+                    // postgres accepts 65535 parameters at most, and a row has 8 parameters.
+                    if *batch_size == 0 || *batch_size > 1000 {
+                        errors.push("postgres batch_size must be between 1 and 1000".to_owned());
+                    }
+                }
+                _ => {}
+            }
+        }
+        // the log is the only place for messages when stdout is used for crawled data.
+        let uses_stdout = self
+            .output
+            .iter()
+            .any(|output| matches!(output, PersistMethod::Stdout { .. }));
+        if uses_stdout && self.log.file.is_stdout() {
+            errors.push("log file must not be stdout with stdout output".to_owned());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+
     pub fn create_crawler_tasks(&self) -> Result<Vec<RavenCrawlTask>, String> {
         // template builders
         let now = Local::now();
@@ -50,12 +158,16 @@ impl RavenConfig {
         let time_formatted_url = now.format(&self.request.url).to_string();
         let url_template_builder = TemplateBuilder::new(&time_formatted_url);
 
-        let mut persist_method_template_builders: Vec<(&PersistMethod, TemplateBuilder)> =
+        let mut persist_method_template_builders: Vec<(PersistMethod, TemplateBuilder)> =
             Vec::with_capacity(self.output.len());
         for persist_method in &self.output {
             let time_formatted_file_name = now.format(persist_method.get_file_name()).to_string();
+            let mut time_formatted_method = persist_method.clone();
+            for value in time_formatted_method.get_templated_values_mut() {
+                *value = now.format(value).to_string();
+            }
             persist_method_template_builders.push((
-                persist_method,
+                time_formatted_method,
                 TemplateBuilder::new(&time_formatted_file_name),
             ));
         }
@@ -104,19 +216,29 @@ impl RavenConfig {
         var_map: HashMap<String, String>,
         param_map: HashMap<String, String>,
         url_builder: &TemplateBuilder,
-        persist_method_with_template_builders: &[(&PersistMethod, TemplateBuilder)],
+        persist_method_with_template_builders: &[(PersistMethod, TemplateBuilder)],
     ) -> Result<RavenCrawlTask, String> {
         let mut all_val_map: HashMap<&str, &str> = HashMap::new();
         copy_ref_to_other_map!(var_map, all_val_map);
         copy_ref_to_other_map!(param_map, all_val_map);
 
         let url = url_builder.build_string(&all_val_map)?;
-        let mut persist_method_list: Vec<PersistMethod> = Vec::new();
 
+        // late bound vars are embedded after crawling, unless vars of the same name exist.
+        let late_bound_placeholders = late_bound_placeholders();
+        let mut file_path_val_map = all_val_map.clone();
+        for (key, val) in late_bound_placeholders.iter() {
+            file_path_val_map.entry(key).or_insert(val);
+        }
+
+        let mut persist_method_list: Vec<PersistMethod> = Vec::new();
         for (persist_method, file_path_builder) in persist_method_with_template_builders.iter() {
-            let embedded_val_file_name = file_path_builder.build_string(&all_val_map)?;
-            let mut cloned_method = (*persist_method).clone();
+            let embedded_val_file_name = file_path_builder.build_string(&file_path_val_map)?;
+            let mut cloned_method = persist_method.clone();
             cloned_method.update_file_path(embedded_val_file_name);
+            for value in cloned_method.get_templated_values_mut() {
+                *value = TemplateBuilder::new(value).build_string(&file_path_val_map)?;
+            }
             persist_method_list.push(cloned_method);
         }
 
@@ -135,6 +257,13 @@ impl RavenConfig {
             query_params: query_map,
             body_params: body_map,
             sleep: self.sleep.clone(),
+            proxy: self.request.proxy.clone(),
+            tls: self.request.tls.clone(),
+            max_body_size: self.request.max_body_size,
+            stream_body: self.request.stream_body,
+            compression: self.request.compression.clone(),
+            redirect: self.request.redirect.clone(),
+            content_sniffing: self.request.content_sniffing.clone(),
         };
 
         let task = RavenCrawlTask {
@@ -149,7 +278,17 @@ impl RavenConfig {
 #[test]
 fn create_request_from_config_test() {
     use crate::application::command_runner::config::log::FileLogConfig;
+    use crate::application::core_types::crawler::content_sniffing::ContentSniffing;
     use crate::application::core_types::logger::LogLevel;
+    use crate::application::core_types::persist::s3::S3ObjectOptions;
+    use std::collections::BTreeMap;
+
+    let btreemap = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    };
     let var: HashMap<String, Vec<String>> = hashmap![
         "id".to_owned() => vec!["1".to_owned(), "2".to_owned()]
     ];
@@ -175,13 +314,40 @@ fn create_request_from_config_test() {
             timeout_in_seconds: 5,
             max_retry: 1,
             params: vec![param_1, param_2],
+            proxy: None,
+            tls: None,
+            max_body_size: None,
+            stream_body: false,
+            compression: None,
+            redirect: None,
+            content_sniffing: ContentSniffing::Fallback,
         },
         notify: Vec::new(),
-        output: vec![PersistMethod::AmazonS3 {
-            region: "ap-northeast-1".to_owned(),
-            bucket_name: "application".to_owned(),
-            object_key: "test/%Y%m%d/{{id}}_{{offset}}_{{limit}}.html".to_owned(),
-        }],
+        output: vec![
+            PersistMethod::AmazonS3 {
+                region: "ap-northeast-1".to_owned(),
+                bucket_name: "application".to_owned(),
+                object_key: "test/%Y%m%d/{{id}}_{{offset}}_{{limit}}.html".to_owned(),
+                endpoint: None,
+                credentials: None,
+                object_options: S3ObjectOptions {
+                    tags: btreemap(&[("run", "%Y%m%d"), ("id", "{{id}}")]),
+                    metadata: btreemap(&[("source", "{{final_url}}")]),
+                    ..Default::default()
+                },
+                upload: Default::default(),
+                compression: None,
+                sidecar: None,
+                content_addressed: None,
+            },
+            PersistMethod::LocalFile {
+                file_path: "/var/tmp/{{id}}/{{final_url}}.html".to_owned(),
+                file_options: Default::default(),
+                compression: None,
+                sidecar: None,
+                content_addressed: None,
+            },
+        ],
         max_threads: 1,
         sleep: None,
         log: LogConfig {
@@ -191,6 +357,7 @@ fn create_request_from_config_test() {
             },
             elasticsearch: None,
         },
+        async_runtime: None,
     };
 
     let expected_url = vec![
@@ -221,11 +388,27 @@ fn create_request_from_config_test() {
         assert_eq!(task.request.max_retry, 1);
         assert_eq!(task.request.body_params.len(), 0);
         assert_eq!(expected_url.contains(&task.request.url), true);
-        let file_name = match &task.persist_methods[0] {
-            PersistMethod::LocalFile { .. } => panic!("must not be LocalFile."),
-            PersistMethod::AmazonS3 { object_key, .. } => object_key,
+        let (file_name, object_options) = match &task.persist_methods[0] {
+            PersistMethod::AmazonS3 {
+                object_key,
+                object_options,
+                ..
+            } => (object_key, object_options),
+            _ => panic!("must be AmazonS3."),
         };
         assert_eq!(expected_object_keys.contains(&file_name), true);
+        // tags and metadata are templated like the object key.
+        assert_eq!(object_options.tags["run"], now_y_m_d);
+        assert_eq!(object_options.tags["id"], task.request.url[16..]);
+        assert_eq!(object_options.metadata["source"], "{{final_url}}");
+        // late bound vars are kept until crawled.
+        assert_eq!(
+            task.persist_methods[1].get_file_name(),
+            format!(
+                "/var/tmp/{}/{{{{final_url}}}}.html",
+                &task.request.url[16..]
+            )
+        );
     }
 }
 
@@ -13,6 +13,13 @@ pub struct FileLogConfig {
     pub level: LogLevel,
 }
 
+impl FileLogConfig {
+    /// true if the log is written to the standard output.
+    pub fn is_stdout(&self) -> bool {
+        ["/dev/stdout", "/dev/fd/1", "/proc/self/fd/1"].contains(&self.path.as_str())
+    }
+}
+
 #[derive(Debug, PartialEq, Deserialize)]
 pub struct EsConfig {
     pub endpoint: String,
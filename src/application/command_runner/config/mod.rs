@@ -1,3 +1,4 @@
+pub mod async_runtime;
 pub mod config;
 pub mod log;
 pub mod notify_method;
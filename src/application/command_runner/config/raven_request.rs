@@ -1,5 +1,10 @@
+use crate::application::core_types::crawler::compression::CompressionSetting;
+use crate::application::core_types::crawler::content_sniffing::ContentSniffing;
 use crate::application::core_types::crawler::encoding::Encoding;
+use crate::application::core_types::crawler::proxy::ProxySetting;
+use crate::application::core_types::crawler::redirect::RedirectSetting;
 use crate::application::core_types::crawler::request::Method;
+use crate::application::core_types::crawler::tls::TlsSetting;
 use serde::de::Error;
 use serde::{Deserialize, Deserializer};
 use serde_json::{map::Map, Value};
@@ -27,6 +32,53 @@ pub struct RavenRequest {
 
     #[serde(default, deserialize_with = "deserialize_params")]
     pub params: Vec<HashMap<String, Vec<String>>>,
+
+    pub proxy: Option<ProxySetting>,
+
+    pub tls: Option<TlsSetting>,
+
+    /// in bytes. a response larger than this is aborted.
+    pub max_body_size: Option<u64>,
+
+    /// write the response body to outputs while downloading, instead of buffering it in memory.
+    #[serde(default)]
+    pub stream_body: bool,
+
+    /// request compressed responses and decode them explicitly.
+    /// if not configured, the http client requests and decodes gzip transparently.
+    pub compression: Option<CompressionSetting>,
+
+    /// follow all redirects up to 10 hops if not configured.
+    pub redirect: Option<RedirectSetting>,
+
+    /// detect content type from the body. Content-Type header is trusted as it is by default.
+    #[serde(default)]
+    pub content_sniffing: ContentSniffing,
+}
+
+impl RavenRequest {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = vec![];
+        if let Some(proxy) = &self.proxy {
+            errors.append(&mut proxy.validate());
+        }
+        if let Some(tls) = &self.tls {
+            errors.append(&mut tls.validate());
+        }
+        if let Some(redirect) = &self.redirect {
+            errors.append(&mut redirect.validate());
+        }
+        if let (
+            Some(CompressionSetting {
+                store_raw: true, ..
+            }),
+            Some(_),
+        ) = (&self.compression, &self.encoding)
+        {
+            errors.push("compression.store_raw can not be used with encoding".to_owned());
+        }
+        errors
+    }
 }
 
 fn default_timeout() -> u8 {
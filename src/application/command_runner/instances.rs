@@ -2,10 +2,19 @@ use super::boundary::CommandLineRaven;
 use crate::application::{
     command_runner::{config::config::RavenConfig, config::log::LogConfig},
     core_types::{
-        crawler::Crawler,
+        crawler::{
+            async_crawler::{
+                async_crawler_cached_client_impl, AsyncHttpClientCache, CrawlerFuture,
+            },
+            body::BodySink,
+            crawler::{
+                crawler_cached_client_impl, crawler_streaming_cached_client_impl, HttpClientCache,
+            },
+            AsyncCrawler, Crawler, CrawlerError, CrawlerRequest, CrawlerResult,
+        },
         logger::LogLevel,
         notify::{Notify, NotifyError},
-        persist::Persist,
+        persist::{AsyncPersist, Persist},
     },
 };
 
@@ -24,15 +33,22 @@ use rs_es::error::EsError;
 use rs_es::operations::bulk::Action;
 use rs_es::Client;
 use std::io::{Error, ErrorKind};
+use std::sync::Arc;
 use uuid::Uuid;
 
 pub struct Prd {
     config: RavenConfig,
+    client_cache: HttpClientCache,
+    async_client_cache: Arc<AsyncHttpClientCache>,
 }
 
 impl Prd {
     pub fn init(config: RavenConfig) -> Prd {
-        let prd = Prd { config };
+        let prd = Prd {
+            config,
+            client_cache: HttpClientCache::new(),
+            async_client_cache: Arc::new(AsyncHttpClientCache::new()),
+        };
         let log_config = log_config(&prd.config.log);
         log4rs::init_config(log_config).unwrap();
         prd
@@ -76,6 +92,8 @@ impl HasConfig for Prd {
 
 impl Persist for Prd {}
 
+impl AsyncPersist for Prd {}
+
 impl Notify for Prd {
     fn notify(
         &self,
@@ -119,7 +137,25 @@ impl Notify for Prd {
     }
 }
 
-impl Crawler for Prd {}
+impl Crawler for Prd {
+    fn crawl(&self, request: &CrawlerRequest) -> Result<CrawlerResult, CrawlerError> {
+        crawler_cached_client_impl(request, &self.client_cache)
+    }
+
+    fn crawl_streaming(
+        &self,
+        request: &CrawlerRequest,
+        body_sink: &mut dyn BodySink,
+    ) -> Result<CrawlerResult, CrawlerError> {
+        crawler_streaming_cached_client_impl(request, &self.client_cache, body_sink)
+    }
+}
+
+impl AsyncCrawler for Prd {
+    fn crawl_async(&self, request: CrawlerRequest) -> CrawlerFuture {
+        async_crawler_cached_client_impl(request, self.async_client_cache.clone())
+    }
+}
 
 impl BulkInsertToEs for Prd {
     fn bulk_insert<'a, T>(&self, documents: &[T]) -> Result<(), EsError>
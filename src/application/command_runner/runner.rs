@@ -1,26 +1,32 @@
 use chrono::{DateTime, Local};
 use futures::future::Future;
+use futures::sync::mpsc::channel;
+use futures::{stream, Sink, Stream};
 use futures_cpupool::{CpuFuture, CpuPool};
 use hostname::get_hostname;
 use std::sync::Arc;
+use tokio::runtime::Runtime;
 
 use crate::application::{
-    command_runner::config::config::HasConfig,
+    command_runner::config::{async_runtime::AsyncRuntimeConfig, config::HasConfig},
     core_types::{
         crawler::metrics::CrawlerMetrics,
-        crawler::Crawler,
+        crawler::{AsyncCrawler, Crawler},
         log::elastic_search::{BulkInsertToEs, EsDocument},
         logger::write_error_log_if_err,
         notify::Notify,
-        persist::Persist,
+        persist::{archive::finish_archives, json_lines::finish_json_lines, AsyncPersist, Persist},
     },
     raven_crawl_task::{
-        task_result::to_crawler_metrics, RavenCrawlTask, RavenCrawlTaskMetrics, TaskFailed,
-        TaskSuccess,
+        task_result::to_crawler_metrics, CrawledTask, RavenCrawlTask, RavenCrawlTaskMetrics,
+        TaskFailed, TaskSuccess,
     },
 };
 
-pub trait CommandLineRaven: HasConfig + Crawler + Persist + Notify + BulkInsertToEs {}
+pub trait CommandLineRaven:
+    HasConfig + Crawler + Persist + AsyncCrawler + AsyncPersist + Notify + BulkInsertToEs
+{
+}
 
 pub fn run_raven_application<App>(app: App)
 where
@@ -30,6 +36,12 @@ where
     info!("raven application start: {}", app.get_config().name);
     debug!("raven config: {:?}", app.get_config());
 
+    if let Err(err) = app.get_config().validate() {
+        error!("invalid config: {}", err);
+        let _ = app.notify_error("invalid config", &err);
+        return;
+    }
+
     let _ = write_error_log_if_err(
         "failed to create elasticsearch crawler-metrics index template",
         app.create_index_template::<CrawlerMetrics>(CrawlerMetrics::elastic_search_typename()),
@@ -47,7 +59,23 @@ where
         Ok(tasks) => {
             let app_arc = Arc::new(app);
             let task_result: Vec<Result<TaskSuccess, TaskFailed>> =
-                crawl_in_parallel(app_arc.clone(), thread_size, tasks);
+                match &app_arc.get_config().async_runtime {
+                    Some(runtime_config) => {
+                        crawl_in_async_pipeline(app_arc.clone(), runtime_config, tasks)
+                    }
+                    None => crawl_in_parallel(app_arc.clone(), thread_size, tasks),
+                };
+
+            // all tasks are finished, so archives and json lines of the run have all entries.
+            if let Err(err) = write_error_log_if_err("failed to finish archives", finish_archives())
+            {
+                let _ = app_arc.notify_error("failed to finish archives", &err.to_string());
+            }
+            if let Err(err) =
+                write_error_log_if_err("failed to finish json lines", finish_json_lines())
+            {
+                let _ = app_arc.notify_error("failed to finish json lines", &err.to_string());
+            }
 
             let total_duration = Local::now().timestamp_millis() - start_time.timestamp_millis();
 
@@ -110,6 +138,57 @@ where
     task_results
 }
 
+/// crawl and persist tasks on the tokio runtime.
+/// crawled tasks are sent to the persist stage through a bounded channel,
+/// so the crawl stage waits when the persist stage is behind.
+pub fn crawl_in_async_pipeline<App>(
+    app_arc: Arc<App>,
+    runtime_config: &AsyncRuntimeConfig,
+    tasks: Vec<RavenCrawlTask>,
+) -> Vec<Result<TaskSuccess, TaskFailed>>
+where
+    App: CommandLineRaven + Sync + Send + 'static,
+{
+    info!("num of crawler tasks: {}", tasks.len());
+    info!("async runtime: {:?}", runtime_config);
+    debug!("tasks detail: {:?}", &tasks);
+
+    let mut runtime = Runtime::new().expect("failed to start async runtime");
+    if app_arc.get_config().request.stream_body {
+        let streaming_stage = stream::iter_ok::<_, ()>(tasks)
+            .map(move |task| task.execute_streaming_async(app_arc.clone()))
+            .buffer_unordered(runtime_config.max_concurrency)
+            .collect();
+        let task_results = runtime.block_on(streaming_stage).unwrap_or_default();
+
+        let _ = runtime.shutdown_on_idle().wait();
+        info!("complete all crawler tasks");
+        return task_results;
+    }
+
+    let (sender, receiver) = channel::<CrawledTask>(runtime_config.channel_size);
+
+    let crawl_app = app_arc.clone();
+    let crawl_stage = stream::iter_ok::<_, ()>(tasks)
+        .map(move |task| task.crawl_async(crawl_app.as_ref()))
+        .buffer_unordered(runtime_config.max_concurrency)
+        .forward(sender.sink_map_err(|err| error!("persist stage is closed: {}", err)))
+        .map(|_| ());
+    runtime.spawn(crawl_stage);
+
+    let persist_app = app_arc.clone();
+    let persist_stage = receiver
+        .map(move |crawled_task| crawled_task.persist_async(persist_app.clone()))
+        .buffer_unordered(runtime_config.persist_concurrency)
+        .collect();
+    let task_results = runtime.block_on(persist_stage).unwrap_or_default();
+
+    let _ = runtime.shutdown_on_idle().wait();
+    info!("complete all crawler tasks");
+
+    task_results
+}
+
 fn notify_result<App>(
     app: &App,
     start_time: &DateTime<Local>,
@@ -151,5 +230,6 @@ fn notify_result<App>(
         persist_errors_num,
     );
 
+    info!("raven command is completed.{}", notify_message);
     let _ = app.notify_info("raven command is completed.", &notify_message);
 }
@@ -0,0 +1,189 @@
+use super::{
+    body::{check_body_size, check_content_length},
+    crawler::{create_default_headers, create_proxy, ClientCache, CrawlAttempts},
+    proxy::ProxySetting,
+    request::Method,
+    CrawlerError, CrawlerRequest, CrawlerResult,
+};
+use futures::future::{self, Future, Loop};
+use futures::Stream;
+use reqwest::r#async::{Client, Response};
+use reqwest::{Error, RedirectPolicy};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::timer::Delay;
+
+pub type CrawlerFuture = Box<dyn Future<Item = CrawlerResult, Error = CrawlerError> + Send>;
+
+type AttemptFuture =
+    Box<dyn Future<Item = Loop<CrawlerResult, CrawlAttempts>, Error = CrawlerError> + Send>;
+
+pub type AsyncHttpClientCache = ClientCache<Client>;
+
+/// non-blocking counterpart of Crawler.
+/// the returned future must be executed on a tokio runtime.
+pub trait AsyncCrawler {
+    fn crawl_async(&self, request: CrawlerRequest) -> CrawlerFuture {
+        async_crawler_default_impl(request)
+    }
+}
+
+/// execute crawl using the async client of reqwest.
+/// retries are decided in the same way as crawler_default_impl.
+pub fn async_crawler_default_impl(request: CrawlerRequest) -> CrawlerFuture {
+    crawl_async_with_client(request, |attempts: &CrawlAttempts| {
+        build_async_client(
+            attempts.request(),
+            attempts.proxy_setting(),
+            attempts.proxy_url(),
+        )
+    })
+}
+
+/// same as async_crawler_default_impl, but reuses http clients in the cache.
+pub fn async_crawler_cached_client_impl(
+    request: CrawlerRequest,
+    client_cache: Arc<AsyncHttpClientCache>,
+) -> CrawlerFuture {
+    crawl_async_with_client(request, move |attempts: &CrawlAttempts| {
+        client_cache.get_or_build(
+            attempts.request(),
+            attempts.proxy_setting(),
+            attempts.proxy_url(),
+            || {
+                build_async_client(
+                    attempts.request(),
+                    attempts.proxy_setting(),
+                    attempts.proxy_url(),
+                )
+            },
+        )
+    })
+}
+
+fn crawl_async_with_client<F>(request: CrawlerRequest, get_client: F) -> CrawlerFuture
+where
+    F: Fn(&CrawlAttempts) -> Result<Client, CrawlerError> + Send + Sync + 'static,
+{
+    let get_client = Arc::new(get_client);
+
+    Box::new(future::loop_fn(
+        CrawlAttempts::new(request),
+        move |attempts: CrawlAttempts| {
+            let get_client = get_client.clone();
+            sleep_async(attempts.sleep_duration())
+                .and_then(move |_| attempt_async(attempts, get_client.as_ref()))
+        },
+    ))
+}
+
+fn sleep_async(
+    duration: Option<Duration>,
+) -> Box<dyn Future<Item = (), Error = CrawlerError> + Send> {
+    match duration {
+        Some(duration) => Box::new(
+            Delay::new(Instant::now() + duration)
+                .map_err(|err| other_error!("failed to sleep: {}", err)),
+        ),
+        None => Box::new(future::ok(())),
+    }
+}
+
+fn attempt_async<F>(mut attempts: CrawlAttempts, get_client: &F) -> AttemptFuture
+where
+    F: Fn(&CrawlAttempts) -> Result<Client, CrawlerError>,
+{
+    let client = match attempts.start_attempt().and_then(|_| get_client(&attempts)) {
+        Ok(client) => client,
+        Err(err) => return Box::new(future::err(err)),
+    };
+
+    let request_builder = match attempts.method() {
+        Method::Get => client.get(attempts.url()),
+        Method::Post => client
+            .post(attempts.url())
+            .form(&attempts.request().body_params),
+    };
+
+    Box::new(
+        request_builder
+            .send()
+            .then(move |response_result| -> AttemptFuture {
+                match response_result {
+                    Ok(response) => read_response(attempts, response),
+                    Err(error) => {
+                        let attempt_result = attempts.on_error(error);
+                        Box::new(future::result(next_loop(attempts, attempt_result)))
+                    }
+                }
+            }),
+    )
+}
+
+fn read_response(mut attempts: CrawlAttempts, response: Response) -> AttemptFuture {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let max_body_size = attempts.request().max_body_size;
+
+    match attempts.follow_redirect(status, &headers) {
+        Ok(true) => return Box::new(future::ok(Loop::Continue(attempts))),
+        Ok(false) => (),
+        Err(err) => return Box::new(future::err(err)),
+    }
+
+    if let Err(err) = check_content_length(&headers, max_body_size) {
+        return Box::new(future::err(err));
+    }
+
+    Box::new(
+        response
+            .into_body()
+            .map_err(|e: Error| CrawlerError::OtherError {
+                error_detail: e.to_string(),
+            })
+            .fold(vec![], move |mut body: Vec<u8>, chunk| {
+                body.extend_from_slice(&chunk);
+                check_body_size(body.len() as u64, max_body_size).map(|_| body)
+            })
+            .and_then(move |body| {
+                let attempt_result = attempts.on_response(status, &headers, body);
+                next_loop(attempts, attempt_result)
+            }),
+    )
+}
+
+fn next_loop(
+    attempts: CrawlAttempts,
+    attempt_result: Option<Result<CrawlerResult, CrawlerError>>,
+) -> Result<Loop<CrawlerResult, CrawlAttempts>, CrawlerError> {
+    match attempt_result {
+        Some(result) => result.map(Loop::Break),
+        None => Ok(Loop::Continue(attempts)),
+    }
+}
+
+fn build_async_client(
+    request: &CrawlerRequest,
+    proxy_setting: Option<&ProxySetting>,
+    proxy_url: Option<&String>,
+) -> Result<Client, CrawlerError> {
+    let mut builder = Client::builder()
+        .timeout(Duration::from_secs(request.timeout.into()))
+        .default_headers(create_default_headers(request)?)
+        .gzip(request.compression.is_none())
+        .redirect(RedirectPolicy::none());
+
+    if let Some(proxy_url) = proxy_url {
+        builder = builder.proxy(create_proxy(proxy_url, proxy_setting)?);
+    }
+
+    if let Some(tls_setting) = &request.tls {
+        builder = tls_setting
+            .apply_to_async(builder)
+            .map_err(|err| other_error!("failed to apply tls setting: {}", err))?;
+    }
+
+    builder
+        .build()
+        .map_err(|err| other_error!("failed to build http client: {}", err))
+}
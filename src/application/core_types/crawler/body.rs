@@ -0,0 +1,165 @@
+use super::CrawlerError;
+use crate::charset::{Charset, CharsetConverter, ConversionMode};
+use crate::mime::Mime;
+use reqwest::header::{HeaderMap, CONTENT_LENGTH};
+use std::io::{ErrorKind, Read, Result as IOResult, Write};
+
+/// what is known about a response before its body is written.
+#[derive(Debug, PartialEq)]
+pub struct ResponseHead<'a> {
+    pub status: u16,
+    /// content type of the written body, whose charset is the converted one.
+    pub mime: &'a Mime,
+    /// url of the response after redirects.
+    pub final_url: &'a str,
+}
+
+/// receives a response body which is streamed instead of kept in CrawlerResult.
+pub trait BodySink: Write {
+    /// called once before the body is written.
+    fn start(&mut self, head: &ResponseHead) -> IOResult<()>;
+}
+
+/// fails before downloading if Content-Length already exceeds max_body_size.
+pub fn check_content_length(
+    headers: &HeaderMap,
+    max_body_size: Option<u64>,
+) -> Result<(), CrawlerError> {
+    let content_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+
+    match content_length {
+        Some(content_length) => check_body_size(content_length, max_body_size),
+        None => Ok(()),
+    }
+}
+
+pub fn check_body_size(received_size: u64, max_body_size: Option<u64>) -> Result<(), CrawlerError> {
+    match max_body_size {
+        Some(max_body_size) if received_size > max_body_size => Err(CrawlerError::BodyTooLarge {
+            max_body_size,
+            received_size,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// copy body to writer chunk by chunk, and abort as soon as it exceeds max_body_size.
+/// returns the size of copied body.
+pub fn copy_body<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    max_body_size: Option<u64>,
+) -> Result<u64, CrawlerError>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+{
+    let mut buffer = [0u8; 64 * 1024];
+    let mut copied_size: u64 = 0;
+    loop {
+        let read_size = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(copied_size),
+            Ok(read_size) => read_size,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(other_error!("failed to read response body: {}", e)),
+        };
+
+        copied_size += read_size as u64;
+        check_body_size(copied_size, max_body_size)?;
+
+        writer
+            .write_all(&buffer[..read_size])
+            .map_err(|e| other_error!("failed to write response body: {}", e))?;
+    }
+}
+
+/// converts charset of written bytes in streaming fashion.
+/// `finish` must be called after the last write to flush bytes kept in the converter.
+pub struct TranscodingWriter<W: Write> {
+    converter: CharsetConverter,
+    inner: W,
+}
+
+impl<W: Write> TranscodingWriter<W> {
+    pub fn new(
+        from: &Charset,
+        to: &Charset,
+        mode: &ConversionMode,
+        inner: W,
+    ) -> TranscodingWriter<W> {
+        TranscodingWriter {
+            converter: CharsetConverter::new(from, to, mode),
+            inner,
+        }
+    }
+
+    /// returns the inner writer, and byte offsets of sequences which could not be converted.
+    pub fn finish(mut self) -> IOResult<(W, Vec<usize>)> {
+        self.convert(&[], true)?;
+        self.inner.flush()?;
+        Ok((self.inner, self.converter.invalid_offsets().to_vec()))
+    }
+
+    fn convert(&mut self, src: &[u8], last: bool) -> IOResult<()> {
+        let converted = self.converter.convert(src, last);
+        self.inner.write_all(&converted)
+    }
+}
+
+impl<W: Write> Write for TranscodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.convert(buf, false)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn transcoding_writer_test() {
+    // arrange
+    let text = "ストリーミングで文字コードを変換する";
+    let (sjis, _, _) = Charset::ShiftJis.get_encoding().encode(text);
+
+    // act: split in the middle of multibyte characters
+    let mut writer = TranscodingWriter::new(
+        &Charset::ShiftJis,
+        &Charset::Utf8,
+        &ConversionMode::Strict,
+        vec![],
+    );
+    for chunk in sjis.chunks(3) {
+        writer.write_all(chunk).unwrap();
+    }
+    let (converted, invalid_offsets) = writer.finish().unwrap();
+
+    // assert
+    assert_eq!(String::from_utf8(converted).unwrap(), text);
+    assert_eq!(invalid_offsets, Vec::<usize>::new());
+}
+
+#[test]
+fn copy_body_test() {
+    let body = vec![1u8; 100 * 1024];
+
+    let mut copied: Vec<u8> = vec![];
+    assert_eq!(
+        copy_body(&mut body.as_slice(), &mut copied, None),
+        Ok(102400)
+    );
+    assert_eq!(copied, body);
+
+    let mut copied: Vec<u8> = vec![];
+    assert_eq!(
+        copy_body(&mut body.as_slice(), &mut copied, Some(1000)),
+        Err(CrawlerError::BodyTooLarge {
+            max_body_size: 1000,
+            received_size: 64 * 1024,
+        })
+    );
+}
@@ -0,0 +1,199 @@
+use super::body::copy_body;
+use super::CrawlerError;
+use brotli_decompressor::Decompressor;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use reqwest::header::{HeaderMap, CONTENT_ENCODING};
+use serde_derive::{Deserialize, Serialize};
+use std::fmt::{Display, Error, Formatter};
+use std::io::{Read, Result as IOResult};
+use std::str::FromStr;
+
+/// compression settings of a request.
+///
+/// `accept_encoding` is sent as Accept-Encoding header (unless the header is configured),
+/// and the response body is decoded according to Content-Encoding before charset conversion.
+/// `store_raw` keeps the body as received, so that outputs get the compressed bytes.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Serialize, Hash)]
+pub struct CompressionSetting {
+    #[serde(default = "default_accept_encoding")]
+    pub accept_encoding: Vec<ContentCoding>,
+
+    #[serde(default)]
+    pub store_raw: bool,
+}
+
+fn default_accept_encoding() -> Vec<ContentCoding> {
+    vec![
+        ContentCoding::Gzip,
+        ContentCoding::Deflate,
+        ContentCoding::Br,
+        ContentCoding::Zstd,
+    ]
+}
+
+impl CompressionSetting {
+    /// value of Accept-Encoding header. "identity" if no coding is accepted.
+    pub fn accept_encoding_header(&self) -> String {
+        if self.accept_encoding.is_empty() {
+            return "identity".to_owned();
+        }
+
+        self.accept_encoding
+            .iter()
+            .map(|coding| coding.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Serialize, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentCoding {
+    Gzip,
+    Deflate,
+    Br,
+    Zstd,
+}
+
+impl Display for ContentCoding {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        let token = match self {
+            ContentCoding::Gzip => "gzip",
+            ContentCoding::Deflate => "deflate",
+            ContentCoding::Br => "br",
+            ContentCoding::Zstd => "zstd",
+        };
+        write!(f, "{}", token)
+    }
+}
+
+impl FromStr for ContentCoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "gzip" | "x-gzip" => Ok(ContentCoding::Gzip),
+            "deflate" => Ok(ContentCoding::Deflate),
+            "br" => Ok(ContentCoding::Br),
+            "zstd" => Ok(ContentCoding::Zstd),
+            other => Err(format!("unsupported content encoding: {}", other)),
+        }
+    }
+}
+
+/// content codings of the response, in the order they were applied.
+/// "identity" is ignored.
+pub fn content_codings(headers: &HeaderMap) -> Result<Vec<ContentCoding>, CrawlerError> {
+    let mut codings = vec![];
+    for header_value in headers.get_all(CONTENT_ENCODING).iter() {
+        let header_value = header_value
+            .to_str()
+            .map_err(|e| other_error!("invalid content encoding header: {}", e))?;
+        for token in header_value.split(',').map(|token| token.trim()) {
+            if token.is_empty() || token.eq_ignore_ascii_case("identity") {
+                continue;
+            }
+            let coding = ContentCoding::from_str(token)
+                .map_err(|e| CrawlerError::OtherError { error_detail: e })?;
+            codings.push(coding);
+        }
+    }
+    Ok(codings)
+}
+
+/// wrap the reader with decoders, so that reading it returns the decoded body.
+pub fn decoding_reader<'a, R: Read + 'a>(
+    reader: R,
+    codings: &[ContentCoding],
+) -> Result<Box<dyn Read + 'a>, CrawlerError> {
+    // the last applied coding has to be decoded first.
+    let mut reader: Box<dyn Read + 'a> = Box::new(reader);
+    for coding in codings.iter().rev() {
+        reader = match coding {
+            ContentCoding::Gzip => Box::new(GzDecoder::new(reader)),
+            ContentCoding::Deflate => Box::new(ZlibDecoder::new(reader)),
+            ContentCoding::Br => Box::new(Decompressor::new(reader, 64 * 1024)),
+            ContentCoding::Zstd => Box::new(
+                zstd::stream::read::Decoder::new(reader)
+                    .map_err(|e| other_error!("failed to start zstd decoding: {}", e))?,
+            ),
+        };
+    }
+    Ok(reader)
+}
+
+/// decode the whole body.
+/// the decoded size is limited by max_body_size so that a small compressed body
+/// can not expand without limit.
+pub fn decode_body(
+    body: &[u8],
+    codings: &[ContentCoding],
+    max_body_size: Option<u64>,
+) -> Result<Vec<u8>, CrawlerError> {
+    let mut decoded: Vec<u8> = Vec::with_capacity(body.len());
+    let mut reader = decoding_reader(body, codings)?;
+    copy_body(&mut reader, &mut decoded, max_body_size)?;
+    Ok(decoded)
+}
+
+/// counts bytes read from the inner reader, to know the size of the body before decoding.
+pub struct CountingReader<R: Read> {
+    inner: R,
+    read_size: u64,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(inner: R) -> CountingReader<R> {
+        CountingReader {
+            inner,
+            read_size: 0,
+        }
+    }
+
+    pub fn read_size(&self) -> u64 {
+        self.read_size
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        let read_size = self.inner.read(buf)?;
+        self.read_size += read_size as u64;
+        Ok(read_size)
+    }
+}
+
+#[test]
+fn decode_body_test() {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use reqwest::header::HeaderValue;
+    use std::io::Write;
+
+    let original = "レイヴン raven ".repeat(1000).into_bytes();
+
+    let mut gzip_encoder = GzEncoder::new(vec![], Compression::default());
+    gzip_encoder.write_all(&original).unwrap();
+    let gzipped = gzip_encoder.finish().unwrap();
+    let gzipped_then_zstd = zstd::stream::encode_all(&gzipped[..], 3).unwrap();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip, zstd"));
+    let codings = content_codings(&headers).unwrap();
+    assert_eq!(codings, vec![ContentCoding::Gzip, ContentCoding::Zstd]);
+
+    assert_eq!(
+        decode_body(&gzipped_then_zstd, &codings, None).unwrap(),
+        original
+    );
+    assert!(matches!(
+        decode_body(&gzipped, &[ContentCoding::Gzip], Some(1000)),
+        Err(CrawlerError::BodyTooLarge {
+            max_body_size: 1000,
+            ..
+        })
+    ));
+
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("compress"));
+    assert!(content_codings(&headers).is_err());
+}
@@ -0,0 +1,16 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// when the content type is detected from the body instead of Content-Type header.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentSniffing {
+    /// always trust Content-Type header.
+    #[default]
+    Off,
+
+    /// sniff only when Content-Type header is missing or `application/octet-stream`.
+    Fallback,
+
+    /// sniff every response, and replace Content-Type header if they differ.
+    Always,
+}
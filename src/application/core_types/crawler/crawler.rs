@@ -1,14 +1,34 @@
-use super::{encoding::Encoding, request::Method, CrawlerError, CrawlerRequest, CrawlerResult};
-use crate::charset::Charset;
+use super::{
+    body::{check_content_length, copy_body, BodySink, ResponseHead, TranscodingWriter},
+    compression::{
+        content_codings, decoding_reader, CompressionSetting, ContentCoding, CountingReader,
+    },
+    content_sniffing::ContentSniffing,
+    encoding::Encoding,
+    proxy::{get_proxy_pool, mask_credentials, ProxyPool, ProxySetting},
+    redirect::RedirectPolicy,
+    request::Method,
+    tls::TlsSetting,
+    CrawlerError, CrawlerRequest, CrawlerResult,
+};
+use crate::charset::{Charset, ConversionMode};
+use crate::charset_sniffing::{sniff_charset, CharsetSource, SNIFF_LENGTH};
 use crate::mime::{Mime, TextMime};
-use chrono::Local;
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use reqwest::{Client, Error, Response};
+use crate::mime_sniffing::sniff_mime;
+use chrono::{DateTime, Local};
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCEPT_ENCODING, LOCATION, PROXY_AUTHORIZATION, RANGE,
+};
+use reqwest::{
+    Client, Error, Proxy, RedirectPolicy as ClientRedirectPolicy, Response, StatusCode, Url,
+};
+use std::mem::replace;
+use std::sync::{Arc, Mutex};
 use std::thread::sleep;
 use std::{
     collections::HashMap,
     hash::Hash,
-    io::{Error as IOError, ErrorKind},
+    io::{Error as IOError, ErrorKind, Read},
     str::FromStr,
     time::Duration,
 };
@@ -17,134 +37,911 @@ pub trait Crawler {
     fn crawl(&self, request: &CrawlerRequest) -> Result<CrawlerResult, CrawlerError> {
         crawler_default_impl(request)
     }
+
+    /// crawl and write the response body to the sink while downloading.
+    /// the body of the returned CrawlerResult is empty if the request succeeded.
+    fn crawl_streaming(
+        &self,
+        request: &CrawlerRequest,
+        body_sink: &mut dyn BodySink,
+    ) -> Result<CrawlerResult, CrawlerError> {
+        crawler_streaming_default_impl(request, body_sink)
+    }
 }
 
 /// execute crawl using reqwest.
 /// if request ends up server error or timeout,
 /// this function retries to request up to request.retry_max
 pub fn crawler_default_impl(request: &CrawlerRequest) -> Result<CrawlerResult, CrawlerError> {
-    let header_maps = create_header_map(&request.header)?;
+    crawl_with_client(
+        request,
+        |attempts: &CrawlAttempts| {
+            build_client(
+                attempts.request(),
+                attempts.proxy_setting(),
+                attempts.proxy_url(),
+            )
+        },
+        None,
+    )
+}
 
-    let client = Client::builder()
-        .timeout(Duration::from_secs(request.timeout.into()))
-        .default_headers(header_maps)
-        .build()
-        .map_err(|err| other_error!("failed to build http client: {}", err))?;
+/// same as crawler_default_impl, but reuses http clients in the cache
+/// so that connections and tls sessions are kept alive between requests.
+pub fn crawler_cached_client_impl(
+    request: &CrawlerRequest,
+    client_cache: &HttpClientCache,
+) -> Result<CrawlerResult, CrawlerError> {
+    crawl_with_client(
+        request,
+        |attempts: &CrawlAttempts| {
+            client_cache.get_or_build(
+                attempts.request(),
+                attempts.proxy_setting(),
+                attempts.proxy_url(),
+                || {
+                    build_client(
+                        attempts.request(),
+                        attempts.proxy_setting(),
+                        attempts.proxy_url(),
+                    )
+                },
+            )
+        },
+        None,
+    )
+}
 
-    let url = match create_query_strings(&request.query_params) {
-        Some(query_strings) => format!("{}?{}", &request.url, query_strings),
-        None => request.url.to_owned(),
-    };
+/// streaming counterpart of crawler_default_impl.
+/// only the body of a successful response is streamed.
+/// bodies of error responses are kept in CrawlerResult as usual.
+pub fn crawler_streaming_default_impl(
+    request: &CrawlerRequest,
+    body_sink: &mut dyn BodySink,
+) -> Result<CrawlerResult, CrawlerError> {
+    crawl_with_client(
+        request,
+        |attempts: &CrawlAttempts| {
+            build_client(
+                attempts.request(),
+                attempts.proxy_setting(),
+                attempts.proxy_url(),
+            )
+        },
+        Some(body_sink),
+    )
+}
 
-    let mut retry_count: u8 = 0;
-    let start_datetime = Local::now();
+pub fn crawler_streaming_cached_client_impl(
+    request: &CrawlerRequest,
+    client_cache: &HttpClientCache,
+    body_sink: &mut dyn BodySink,
+) -> Result<CrawlerResult, CrawlerError> {
+    crawl_with_client(
+        request,
+        |attempts: &CrawlAttempts| {
+            client_cache.get_or_build(
+                attempts.request(),
+                attempts.proxy_setting(),
+                attempts.proxy_url(),
+                || {
+                    build_client(
+                        attempts.request(),
+                        attempts.proxy_setting(),
+                        attempts.proxy_url(),
+                    )
+                },
+            )
+        },
+        Some(body_sink),
+    )
+}
+
+fn crawl_with_client<F>(
+    request: &CrawlerRequest,
+    get_client: F,
+    mut body_sink: Option<&mut dyn BodySink>,
+) -> Result<CrawlerResult, CrawlerError>
+where
+    F: Fn(&CrawlAttempts) -> Result<Client, CrawlerError>,
+{
+    let mut attempts = CrawlAttempts::new(request.clone());
 
     loop {
-        if let Some(sleep_sec) = request.sleep {
-            sleep(Duration::from_secs(sleep_sec.into()))
+        if let Some(sleep_duration) = attempts.sleep_duration() {
+            sleep(sleep_duration)
         }
 
-        let mut response_result: Result<Response, Error> = match &request.method {
-            Method::Get => client.get(&url).send(),
-            Method::Post => client.post(&url).form(&request.body_params).send(),
+        attempts.start_attempt()?;
+        let client = get_client(&attempts)?;
+
+        let response_result: Result<Response, Error> = match attempts.method() {
+            Method::Get => client.get(attempts.url()).send(),
+            Method::Post => client
+                .post(attempts.url())
+                .form(&request.body_params)
+                .send(),
         };
 
-        match response_result {
-            Ok(ref mut response) => {
-                let end_datetime = Local::now().timestamp_millis();
-                let mut response_body: Vec<u8> = vec![];
-                response.copy_to(&mut response_body).map_err(|e: Error| {
-                    CrawlerError::OtherError {
-                        error_detail: e.to_string(),
+        let attempt_result = match response_result {
+            Ok(mut response) => {
+                if attempts.follow_redirect(response.status(), response.headers())? {
+                    continue;
+                }
+                check_content_length(response.headers(), request.max_body_size)?;
+                match body_sink.as_mut() {
+                    Some(body_sink) if is_result_status(response.status()) => {
+                        let streamed_body =
+                            stream_body(&attempts, &mut response, &mut **body_sink)?;
+                        Some(attempts.on_streamed_response(
+                            response.status(),
+                            response.headers(),
+                            streamed_body,
+                        ))
                     }
-                })?;
-
-                let response_content_type = response
-                    .headers()
-                    .get("Content-Type")
-                    .and_then(|header_value: &HeaderValue| header_value.to_str().ok())
-                    .and_then(|mime_str| Mime::from_str(mime_str).ok())
-                    .map(|mime| {
-                        overwrite_input_charset_if_configured(mime, &request.encoding_setting)
-                    })
-                    .or_else(|| {
-                        text_plain_if_input_charset_setting_exists(&request.encoding_setting)
-                    })
-                    .unwrap_or(Mime::ApplicationOctetStream);
-
-                let mut raven_response = CrawlerResult {
-                    response_status: response.status().as_u16(),
-                    response_header: header_map_to_hash_map(response.headers()),
-                    response_body,
-                    mills_takes_to_complete_to_request: end_datetime
-                        - start_datetime.timestamp_millis(),
-                    retry_count,
-                    response_content_type,
-                    crawl_date: start_datetime,
-                };
-
-                if response.status().is_success() {
-                    if let Some(Encoding { output, .. }) = &request.encoding_setting {
-                        raven_response
-                            .convert_response_encoding_if_has_text_mime_type(output.clone());
-
-                        if !raven_response.has_same_charset(output) {
-                            error!(
-                                "conflict configured output charset({}) and actually converted({}): {:?}",
-                                output,
-                                raven_response.response_content_type.get_charset()
-                                    .map(|c| c.to_string())
-                                    .unwrap_or("".to_owned()),
-                                raven_response
-                            );
-
-                            return Err(CrawlerError::CharsetConversionError {
-                                error_detail:
-                                    "conflict configured output charset and actually converted"
-                                        .to_owned(),
-                                crawler_result: raven_response,
-                            });
-                        }
+                    _ => {
+                        let mut response_body: Vec<u8> = vec![];
+                        copy_body(&mut response, &mut response_body, request.max_body_size)?;
+                        attempts.on_response(response.status(), response.headers(), response_body)
                     }
-                    return Ok(raven_response);
-                } else if response.status().is_client_error() {
-                    raven_response.convert_response_encoding_if_has_text_mime_type(Charset::Utf8);
-                    return Err(CrawlerError::ClientError(raven_response));
-                } else if response.status().is_server_error() && retry_count >= request.max_retry {
-                    raven_response.convert_response_encoding_if_has_text_mime_type(Charset::Utf8);
-                    return Err(CrawlerError::ServerError(raven_response));
-                } else {
-                    retry_count += 1;
-                    continue;
                 }
             }
-            Err(error) => {
-                let cast_to_hyper_error = error
-                    .get_ref()
-                    .and_then(|e| e.downcast_ref::<IOError>())
-                    .map(|e: &IOError| e.kind());
-
-                match cast_to_hyper_error {
-                    Some(ErrorKind::TimedOut) | Some(ErrorKind::WouldBlock) => {
-                        if retry_count >= request.max_retry {
-                            return Err(CrawlerError::TimeoutError {
-                                timeout_second: request.timeout,
-                                retry_count,
-                            });
-                        } else {
-                            retry_count += 1;
-                            continue;
-                        }
-                    }
+            Err(error) => attempts.on_error(error),
+        };
 
-                    _ => {
-                        error!("unexpected request error: {}", error);
-                        return Err(other_error!("request error: {}", error));
+        if let Some(result) = attempt_result {
+            return result;
+        }
+    }
+}
+
+/// a response with this status is returned as the result without retry.
+/// redirects reach here only when they are not followed.
+fn is_result_status(status: StatusCode) -> bool {
+    status.is_success() || status.is_redirection()
+}
+
+/// the response body which is written to a sink.
+#[derive(Debug)]
+pub struct StreamedBody {
+    /// content type of the written body, whose charset is the converted one.
+    pub written_mime: Mime,
+    pub detected_charset: Option<Charset>,
+    pub charset_source: Option<CharsetSource>,
+    /// byte offsets of the sequences which could not be converted.
+    pub invalid_offsets: Vec<usize>,
+    pub raw_body_size: u64,
+    pub decoded_body_size: u64,
+}
+
+/// write the response body to the sink, decoding content encoding
+/// and converting its charset if they are configured.
+/// the beginning of the body is read ahead to sniff its charset.
+fn stream_body(
+    attempts: &CrawlAttempts,
+    response: &mut Response,
+    body_sink: &mut dyn BodySink,
+) -> Result<StreamedBody, CrawlerError> {
+    let request = attempts.request();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let codings = attempts.codings_to_decode(&headers)?;
+
+    let mut counting_reader = CountingReader::new(response);
+    let mut reader = decoding_reader(&mut counting_reader, &codings)?;
+    let mut body_prefix: Vec<u8> = vec![];
+    copy_body(
+        &mut reader.by_ref().take(SNIFF_LENGTH as u64),
+        &mut body_prefix,
+        request.max_body_size,
+    )?;
+    let (response_mime, charset_source) = attempts.response_mime(&headers, &body_prefix);
+    let detected_charset = response_mime.get_charset().cloned();
+
+    let conversion: Option<(Charset, &Encoding)> =
+        match (&detected_charset, &request.encoding_setting) {
+            (Some(charset), Some(encoding)) => Some((charset.clone(), encoding)),
+            _ => None,
+        };
+
+    let mut written_mime = response_mime;
+    if let Some((_, Encoding { output, .. })) = &conversion {
+        written_mime.set_charset_when_text_mime(output.clone());
+    }
+
+    body_sink
+        .start(&ResponseHead {
+            status: status.as_u16(),
+            mime: &written_mime,
+            final_url: attempts.url(),
+        })
+        .map_err(|e| other_error!("failed to start writing response body: {}", e))?;
+
+    let mut body_reader = body_prefix.as_slice().chain(reader);
+    let (decoded_body_size, invalid_offsets) = match conversion {
+        Some((input, encoding)) => {
+            let mut writer = TranscodingWriter::new(
+                &input,
+                &encoding.output,
+                &encoding.conversion_mode,
+                &mut *body_sink,
+            );
+            let decoded_body_size =
+                copy_body(&mut body_reader, &mut writer, request.max_body_size)?;
+            let (_, invalid_offsets) = writer
+                .finish()
+                .map_err(|e| other_error!("failed to write response body: {}", e))?;
+            (decoded_body_size, invalid_offsets)
+        }
+        None => {
+            let decoded_body_size = copy_body(&mut body_reader, body_sink, request.max_body_size)?;
+            body_sink
+                .flush()
+                .map_err(|e| other_error!("failed to write response body: {}", e))?;
+            (decoded_body_size, vec![])
+        }
+    };
+    drop(body_reader);
+
+    Ok(StreamedBody {
+        written_mime,
+        detected_charset,
+        charset_source,
+        invalid_offsets,
+        raw_body_size: counting_reader.read_size(),
+        decoded_body_size,
+    })
+}
+
+/// state of the attempts to crawl a request.
+/// this is shared by the blocking crawler and the async crawler
+/// so that both decide retries and build results in the same way.
+#[derive(Debug)]
+pub struct CrawlAttempts {
+    request: CrawlerRequest,
+    url: String,
+    method: Method,
+    redirect_chain: Vec<String>,
+    bypass_proxy: bool,
+    proxy_pool: Option<Arc<ProxyPool>>,
+    proxy_url: Option<String>,
+    retry_count: u8,
+    removed_proxies: Vec<String>,
+    start_datetime: DateTime<Local>,
+}
+
+impl CrawlAttempts {
+    pub fn new(request: CrawlerRequest) -> CrawlAttempts {
+        let bypass_proxy = request
+            .proxy
+            .as_ref()
+            .map(|setting| setting.bypasses(&request.url))
+            .unwrap_or(false);
+
+        let proxy_pool: Option<Arc<ProxyPool>> = request
+            .proxy
+            .as_ref()
+            .filter(|_| !bypass_proxy)
+            .and_then(|setting| setting.pool.as_ref())
+            .map(get_proxy_pool);
+
+        let url = match create_query_strings(&request.query_params) {
+            Some(query_strings) => format!("{}?{}", &request.url, query_strings),
+            None => request.url.to_owned(),
+        };
+
+        CrawlAttempts {
+            method: request.method.clone(),
+            request,
+            url,
+            redirect_chain: vec![],
+            bypass_proxy,
+            proxy_pool,
+            proxy_url: None,
+            retry_count: 0,
+            removed_proxies: vec![],
+            start_datetime: Local::now(),
+        }
+    }
+
+    pub fn request(&self) -> &CrawlerRequest {
+        &self.request
+    }
+
+    /// url with query strings, or the redirected url.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// method of the request, which becomes GET after some kinds of redirects.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// move to the redirected url if the response is a redirect to follow.
+    /// returns true if the redirected url should be requested next.
+    pub fn follow_redirect(
+        &mut self,
+        status: StatusCode,
+        headers: &HeaderMap,
+    ) -> Result<bool, CrawlerError> {
+        let location = match headers.get(LOCATION).and_then(|value| value.to_str().ok()) {
+            Some(location) if status.is_redirection() => location,
+            _ => return Ok(false),
+        };
+
+        let current_url = Url::parse(&self.url)
+            .map_err(|e| other_error!("invalid url({}): {}", (self.url), e))?;
+        let next_url = current_url
+            .join(location)
+            .map_err(|e| other_error!("invalid redirect location({}): {}", location, e))?;
+
+        let setting = self.request.redirect.clone().unwrap_or_default();
+        match setting.policy {
+            RedirectPolicy::None => return Ok(false),
+            RedirectPolicy::SameHost if next_url.host_str() != current_url.host_str() => {
+                return Ok(false);
+            }
+            _ => (),
+        }
+
+        if self.redirect_chain.len() >= setting.max_hops as usize {
+            let mut redirect_chain = self.redirect_chain.clone();
+            redirect_chain.push(self.url.clone());
+            redirect_chain.push(next_url.to_string());
+            return Err(CrawlerError::TooManyRedirects {
+                max_hops: setting.max_hops,
+                redirect_chain,
+            });
+        }
+
+        // same as browsers, the body is sent again only by 307 and 308.
+        let keeps_method =
+            status == StatusCode::TEMPORARY_REDIRECT || status == StatusCode::PERMANENT_REDIRECT;
+        if !keeps_method {
+            self.method = Method::Get;
+        }
+
+        // credentials are not leaked to another host or scheme.
+        // clients are built from the request, so the stripped headers are not sent any more.
+        if next_url.origin() != current_url.origin() {
+            self.request
+                .header
+                .retain(|name, _| !setting.is_sensitive_header(name));
+        }
+
+        let redirected_url = replace(&mut self.url, next_url.to_string());
+        self.redirect_chain.push(redirected_url);
+        Ok(true)
+    }
+
+    /// headers sent with the current hop, except Host and the headers of the body.
+    /// they are the default headers of the client built from the request,
+    /// whose sensitive headers are stripped by cross-origin redirects,
+    /// and Proxy-Authorization which reqwest adds for http proxies.
+    pub fn request_headers(&self) -> HeaderMap {
+        let mut headers = create_default_headers(&self.request).unwrap_or_default();
+        let authorization = self
+            .proxy_url
+            .as_ref()
+            .and_then(|proxy_url| proxy_authorization(&self.url, proxy_url, self.proxy_setting()));
+        if let Some(authorization) = authorization {
+            headers.insert(PROXY_AUTHORIZATION, authorization);
+        }
+        headers
+    }
+
+    pub fn proxy_setting(&self) -> Option<&ProxySetting> {
+        if self.bypass_proxy {
+            None
+        } else {
+            self.request.proxy.as_ref()
+        }
+    }
+
+    /// proxy used by the current attempt.
+    pub fn proxy_url(&self) -> Option<&String> {
+        self.proxy_url.as_ref()
+    }
+
+    pub fn sleep_duration(&self) -> Option<Duration> {
+        self.request
+            .sleep
+            .map(|sleep_sec| Duration::from_secs(sleep_sec.into()))
+    }
+
+    /// pick a proxy for the next attempt.
+    pub fn start_attempt(&mut self) -> Result<(), CrawlerError> {
+        self.proxy_url = match (&self.proxy_pool, self.proxy_setting()) {
+            (Some(pool), _) => match pool.pick() {
+                Some(picked) => Some(picked),
+                None => {
+                    return Err(CrawlerError::ProxyError {
+                        proxy: None,
+                        error_detail: "no proxy is available in pool".to_owned(),
+                        retry_count: self.retry_count,
+                        removed_proxies: self.removed_proxies.clone(),
+                    });
+                }
+            },
+            (None, Some(setting)) => setting.proxy_url_for(&self.url).map(|p| p.to_owned()),
+            (None, None) => None,
+        };
+        Ok(())
+    }
+
+    /// content type of the response, and where its charset comes from.
+    /// the content type is sniffed from the body as configured by `content_sniffing`.
+    pub fn response_mime(
+        &self,
+        headers: &HeaderMap,
+        body_prefix: &[u8],
+    ) -> (Mime, Option<CharsetSource>) {
+        let (declared_mime, charset_source) = self.declared_mime(headers, body_prefix);
+        let sniffs = match self.request.content_sniffing {
+            ContentSniffing::Off => false,
+            ContentSniffing::Fallback => {
+                declared_mime.essence() == Mime::ApplicationOctetStream.essence()
+            }
+            ContentSniffing::Always => true,
+        };
+
+        match sniff_mime(body_prefix).filter(|_| sniffs) {
+            Some(sniffed) if sniffed.essence() != declared_mime.essence() => {
+                match (sniffed, declared_mime.get_charset()) {
+                    (mut sniffed @ Mime::Text { .. }, Some(charset)) => {
+                        sniffed.set_charset_when_text_mime(charset.clone());
+                        (sniffed, charset_source)
                     }
+                    (sniffed, _) => (sniffed, None),
                 }
             }
+            _ => (declared_mime, charset_source),
         }
     }
+
+    /// content type declared by the response.
+    /// the charset is taken from the encoding setting, BOM, Content-Type header
+    /// and the declaration in the body, in this order.
+    /// a body without Content-Type header is typed as text if its charset is sniffed.
+    fn declared_mime(
+        &self,
+        headers: &HeaderMap,
+        body_prefix: &[u8],
+    ) -> (Mime, Option<CharsetSource>) {
+        let encoding_setting = &self.request.encoding_setting;
+        let header_mime = headers
+            .get("Content-Type")
+            .and_then(|header_value: &HeaderValue| header_value.to_str().ok())
+            .and_then(|mime_str| Mime::from_str(mime_str).ok());
+
+        if let Some(Encoding { input: Some(_), .. }) = encoding_setting {
+            return match header_mime {
+                Some(mime) if !mime.is_text() => (mime, None),
+                mime => (
+                    mime.map(|mime| overwrite_input_charset_if_configured(mime, encoding_setting))
+                        .or_else(|| text_plain_if_input_charset_setting_exists(encoding_setting))
+                        .unwrap_or(Mime::ApplicationOctetStream),
+                    Some(CharsetSource::Configured),
+                ),
+            };
+        }
+
+        let (sniff, statistical) = encoding_setting
+            .as_ref()
+            .map(|setting| (setting.sniff, setting.statistical_detection))
+            .unwrap_or((false, false));
+        let sniffed = if sniff {
+            sniff_charset(body_prefix, statistical)
+        } else {
+            None
+        };
+
+        match (header_mime, sniffed) {
+            (Some(mut mime), Some((charset, source))) if mime.is_text() => {
+                // BOM is more reliable than the header, same as browsers.
+                if mime.get_charset().is_some() && source != CharsetSource::Bom {
+                    (mime, Some(CharsetSource::Header))
+                } else {
+                    mime.set_charset_when_text_mime(charset);
+                    (mime, Some(source))
+                }
+            }
+            (Some(mime), _) => {
+                let source = mime.get_charset().map(|_| CharsetSource::Header);
+                (mime, source)
+            }
+            (None, Some((charset, source))) => (
+                Mime::Text {
+                    text_type: sniffed_text_type(&source),
+                    charset: Some(charset),
+                    params: vec![],
+                },
+                Some(source),
+            ),
+            (None, None) => (Mime::ApplicationOctetStream, None),
+        }
+    }
+
+    /// content codings of the response which should be decoded.
+    /// nothing is decoded if compression is not configured (the http client decodes gzip),
+    /// or the raw body should be stored.
+    pub fn codings_to_decode(
+        &self,
+        headers: &HeaderMap,
+    ) -> Result<Vec<ContentCoding>, CrawlerError> {
+        match &self.request.compression {
+            Some(CompressionSetting {
+                store_raw: false, ..
+            }) => content_codings(headers),
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// returns None if the request should be retried.
+    pub fn on_response(
+        &mut self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        response_body: Vec<u8>,
+    ) -> Option<Result<CrawlerResult, CrawlerError>> {
+        let mut raven_response = self.to_crawler_result(status, headers, response_body);
+
+        let decoded = self.codings_to_decode(headers).and_then(|codings| {
+            raven_response.decode_content_encoding(&codings, self.request.max_body_size)
+        });
+        if let Err(e) = decoded {
+            return Some(Err(e));
+        }
+
+        let (response_content_type, charset_source) =
+            self.response_mime(headers, &raven_response.response_body);
+        raven_response.detected_charset = response_content_type.get_charset().cloned();
+        raven_response.charset_source = charset_source;
+        raven_response.response_content_type = response_content_type;
+
+        if is_result_status(status) {
+            let invalid_offsets = match &self.request.encoding_setting {
+                Some(Encoding {
+                    output,
+                    conversion_mode,
+                    ..
+                }) => raven_response.convert_response_encoding_if_has_text_mime_type(
+                    output.clone(),
+                    conversion_mode,
+                ),
+                None => vec![],
+            };
+            Some(self.check_output_charset(raven_response, invalid_offsets))
+        } else if status.is_client_error() {
+            raven_response.convert_response_encoding_if_has_text_mime_type(
+                Charset::Utf8,
+                &ConversionMode::Lenient,
+            );
+            Some(Err(CrawlerError::ClientError(raven_response)))
+        } else if status.is_server_error() && self.retry_count >= self.request.max_retry {
+            raven_response.convert_response_encoding_if_has_text_mime_type(
+                Charset::Utf8,
+                &ConversionMode::Lenient,
+            );
+            Some(Err(CrawlerError::ServerError(raven_response)))
+        } else {
+            self.retry_count += 1;
+            None
+        }
+    }
+
+    /// the body of a successful response is already written to a sink.
+    pub fn on_streamed_response(
+        &mut self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        streamed_body: StreamedBody,
+    ) -> Result<CrawlerResult, CrawlerError> {
+        let mut raven_response = self.to_crawler_result(status, headers, vec![]);
+        raven_response.response_content_type = streamed_body.written_mime;
+        raven_response.detected_charset = streamed_body.detected_charset;
+        raven_response.charset_source = streamed_body.charset_source;
+        raven_response.raw_body_size = streamed_body.raw_body_size;
+        raven_response.decoded_body_size = streamed_body.decoded_body_size;
+        raven_response.replacement_count = streamed_body.invalid_offsets.len() as u64;
+        self.check_output_charset(raven_response, streamed_body.invalid_offsets)
+    }
+
+    /// content type is octet-stream until the caller detects it from the body.
+    fn to_crawler_result(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        response_body: Vec<u8>,
+    ) -> CrawlerResult {
+        if let (Some(pool), Some(proxy_url)) = (&self.proxy_pool, &self.proxy_url) {
+            pool.report_success(proxy_url);
+        }
+
+        let end_datetime = Local::now().timestamp_millis();
+        let body_size = response_body.len() as u64;
+        CrawlerResult {
+            response_status: status.as_u16(),
+            response_header: header_map_to_hash_map(headers),
+            response_body,
+            mills_takes_to_complete_to_request: end_datetime
+                - self.start_datetime.timestamp_millis(),
+            retry_count: self.retry_count,
+            response_content_type: Mime::ApplicationOctetStream,
+            detected_charset: None,
+            charset_source: None,
+            replacement_count: 0,
+            crawl_date: self.start_datetime,
+            proxy: self.proxy_url.as_ref().map(|p| mask_credentials(p)),
+            removed_proxies: self.removed_proxies.clone(),
+            raw_body_size: body_size,
+            decoded_body_size: body_size,
+            final_url: self.url.clone(),
+            redirect_chain: self.redirect_chain.clone(),
+            request_header: header_map_to_hash_map(&self.request_headers()),
+        }
+    }
+
+    /// fails if the body is not converted to the output charset,
+    /// or some sequences could not be converted in strict mode.
+    fn check_output_charset(
+        &self,
+        raven_response: CrawlerResult,
+        invalid_offsets: Vec<usize>,
+    ) -> Result<CrawlerResult, CrawlerError> {
+        match &self.request.encoding_setting {
+            Some(Encoding { output, .. }) if !raven_response.has_same_charset(output) => {
+                error!(
+                    "conflict configured output charset({}) and actually converted({}): {:?}",
+                    output,
+                    raven_response
+                        .response_content_type
+                        .get_charset()
+                        .map(|c| c.to_string())
+                        .unwrap_or("".to_owned()),
+                    raven_response
+                );
+
+                Err(CrawlerError::CharsetConversionError {
+                    error_detail: "conflict configured output charset and actually converted"
+                        .to_owned(),
+                    invalid_offsets: vec![],
+                    crawler_result: raven_response,
+                })
+            }
+            Some(Encoding {
+                conversion_mode: ConversionMode::Strict,
+                ..
+            }) if !invalid_offsets.is_empty() => Err(CrawlerError::CharsetConversionError {
+                error_detail: format!(
+                    "{} malformed or unmappable sequences at byte offsets {:?}",
+                    invalid_offsets.len(),
+                    invalid_offsets
+                ),
+                invalid_offsets,
+                crawler_result: raven_response,
+            }),
+            _ => Ok(raven_response),
+        }
+    }
+
+    /// returns None if the request should be retried.
+    pub fn on_error(&mut self, error: Error) -> Option<Result<CrawlerResult, CrawlerError>> {
+        if let (Some(pool), Some(proxy_url)) = (&self.proxy_pool, &self.proxy_url) {
+            if pool.report_failure(proxy_url) {
+                self.removed_proxies.push(mask_credentials(proxy_url));
+            }
+        }
+
+        let cast_to_hyper_error = error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<IOError>())
+            .map(|e: &IOError| e.kind());
+
+        match cast_to_hyper_error {
+            Some(ErrorKind::TimedOut) | Some(ErrorKind::WouldBlock) => {
+                if self.retry_count >= self.request.max_retry {
+                    Some(Err(CrawlerError::TimeoutError {
+                        timeout_second: self.request.timeout,
+                        retry_count: self.retry_count,
+                    }))
+                } else {
+                    self.retry_count += 1;
+                    None
+                }
+            }
+
+            _ if self.proxy_pool.is_some() => {
+                let masked_proxy = self.proxy_url.as_ref().map(|p| mask_credentials(p));
+                if self.retry_count >= self.request.max_retry {
+                    error!(
+                        "request error via proxy({}): {}",
+                        masked_proxy.as_deref().unwrap_or("none"),
+                        error
+                    );
+                    Some(Err(CrawlerError::ProxyError {
+                        proxy: masked_proxy,
+                        error_detail: error.to_string(),
+                        retry_count: self.retry_count,
+                        removed_proxies: self.removed_proxies.clone(),
+                    }))
+                } else {
+                    self.retry_count += 1;
+                    None
+                }
+            }
+
+            _ => {
+                error!("unexpected request error: {}", error);
+                Some(Err(other_error!("request error: {}", error)))
+            }
+        }
+    }
+}
+
+/// http clients shared by all crawler threads.
+/// a client is created for each combination of headers, timeout, proxy and tls settings.
+#[derive(Debug)]
+pub struct ClientCache<C> {
+    clients: Mutex<HashMap<HttpClientKey, C>>,
+}
+
+pub type HttpClientCache = ClientCache<Client>;
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct HttpClientKey {
+    headers: Vec<(String, String)>,
+    timeout: u8,
+    proxy_setting: Option<ProxySetting>,
+    proxy_url: Option<String>,
+    tls: Option<TlsSetting>,
+    compression: Option<CompressionSetting>,
+}
+
+impl<C: Clone> ClientCache<C> {
+    pub fn new() -> ClientCache<C> {
+        ClientCache {
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// returns cached client for the settings, or builds and caches a new one.
+    pub fn get_or_build<F>(
+        &self,
+        request: &CrawlerRequest,
+        proxy_setting: Option<&ProxySetting>,
+        proxy_url: Option<&String>,
+        build: F,
+    ) -> Result<C, CrawlerError>
+    where
+        F: FnOnce() -> Result<C, CrawlerError>,
+    {
+        let mut headers: Vec<(String, String)> = request
+            .header
+            .iter()
+            .map(|(key, val)| (key.to_lowercase(), val.to_owned()))
+            .collect();
+        headers.sort();
+
+        let key = HttpClientKey {
+            headers,
+            timeout: request.timeout,
+            proxy_setting: proxy_setting.cloned(),
+            proxy_url: proxy_url.cloned(),
+            tls: request.tls.clone(),
+            compression: request.compression.clone(),
+        };
+
+        let mut clients = self
+            .clients
+            .lock()
+            .expect("http client cache lock is poisoned");
+        if let Some(client) = clients.get(&key) {
+            return Ok(client.clone());
+        }
+
+        let client = build()?;
+        clients.insert(key, client.clone());
+        Ok(client)
+    }
+}
+
+impl<C: Clone> Default for ClientCache<C> {
+    fn default() -> Self {
+        ClientCache::new()
+    }
+}
+
+fn build_client(
+    request: &CrawlerRequest,
+    proxy_setting: Option<&ProxySetting>,
+    proxy_url: Option<&String>,
+) -> Result<Client, CrawlerError> {
+    let mut builder = Client::builder()
+        .timeout(Duration::from_secs(request.timeout.into()))
+        .default_headers(create_default_headers(request)?)
+        .gzip(request.compression.is_none())
+        .redirect(ClientRedirectPolicy::none());
+
+    if let Some(proxy_url) = proxy_url {
+        builder = builder.proxy(create_proxy(proxy_url, proxy_setting)?);
+    }
+
+    if let Some(tls_setting) = &request.tls {
+        builder = tls_setting
+            .apply_to(builder)
+            .map_err(|err| other_error!("failed to apply tls setting: {}", err))?;
+    }
+
+    builder
+        .build()
+        .map_err(|err| other_error!("failed to build http client: {}", err))
+}
+
+/// user and password in proxy url take precedence over auth setting.
+pub fn create_proxy(
+    proxy_url: &str,
+    proxy_setting: Option<&ProxySetting>,
+) -> Result<Proxy, CrawlerError> {
+    let mut parsed_url = Url::parse(proxy_url).map_err(|err| CrawlerError::ProxyError {
+        proxy: Some(mask_credentials(proxy_url)),
+        error_detail: format!("invalid proxy url: {}", err),
+        retry_count: 0,
+        removed_proxies: vec![],
+    })?;
+
+    let credentials = proxy_credentials(&parsed_url, proxy_setting);
+
+    let _ = parsed_url.set_username("");
+    let _ = parsed_url.set_password(None);
+
+    let proxy = Proxy::all(parsed_url).map_err(|err| CrawlerError::ProxyError {
+        proxy: Some(mask_credentials(proxy_url)),
+        error_detail: format!("failed to create proxy: {}", err),
+        retry_count: 0,
+        removed_proxies: vec![],
+    })?;
+
+    Ok(match credentials {
+        Some((username, password)) => proxy.basic_auth(&username, &password),
+        None => proxy,
+    })
+}
+
+fn proxy_credentials(
+    parsed_url: &Url,
+    proxy_setting: Option<&ProxySetting>,
+) -> Option<(String, String)> {
+    if parsed_url.username().is_empty() {
+        proxy_setting
+            .and_then(|setting| setting.auth.as_ref())
+            .map(|auth| (auth.username.to_owned(), auth.password.to_owned()))
+    } else {
+        Some((
+            parsed_url.username().to_owned(),
+            parsed_url.password().unwrap_or("").to_owned(),
+        ))
+    }
+}
+
+/// Proxy-Authorization header which reqwest sends with a request to a http url
+/// through a http or https proxy with credentials.
+/// for a https url, it is sent by the CONNECT request of the tunnel instead.
+fn proxy_authorization(
+    url: &str,
+    proxy_url: &str,
+    proxy_setting: Option<&ProxySetting>,
+) -> Option<HeaderValue> {
+    let parsed_proxy_url = Url::parse(proxy_url).ok()?;
+    let is_http_proxy = matches!(parsed_proxy_url.scheme(), "http" | "https");
+    let is_http_url = Url::parse(url).is_ok_and(|url| url.scheme() == "http");
+    if !is_http_proxy || !is_http_url {
+        return None;
+    }
+    let (username, password) = proxy_credentials(&parsed_proxy_url, proxy_setting)?;
+    let credentials = base64::encode(&format!("{}:{}", username, password));
+    HeaderValue::from_str(&format!("Basic {}", credentials)).ok()
 }
 
 fn overwrite_input_charset_if_configured(mime: Mime, encoding: &Option<Encoding>) -> Mime {
@@ -169,12 +966,24 @@ fn text_plain_if_input_charset_setting_exists(encoding_setting: &Option<Encoding
         Some(Mime::Text {
             text_type: TextMime::TextPlain,
             charset: Some(input_encoding.clone()),
+            params: vec![],
         })
     } else {
         None
     }
 }
 
+/// type of a body without Content-Type header, by how its charset is declared.
+fn sniffed_text_type(source: &CharsetSource) -> TextMime {
+    match source {
+        CharsetSource::HtmlMeta => TextMime::TextOther {
+            text_mime_type_string: "text/html".to_owned(),
+        },
+        CharsetSource::XmlDeclaration => TextMime::TextXml,
+        _ => TextMime::TextPlain,
+    }
+}
+
 fn create_query_strings<T>(param_map: &HashMap<T, T>) -> Option<String>
 where
     T: Eq + Hash + AsRef<str>,
@@ -191,7 +1000,7 @@ where
     Some(query_strings.join("&"))
 }
 
-fn create_header_map<T>(headers: &HashMap<T, T>) -> Result<HeaderMap, CrawlerError>
+pub fn create_header_map<T>(headers: &HashMap<T, T>) -> Result<HeaderMap, CrawlerError>
 where
     T: Eq + Hash + AsRef<str>,
 {
@@ -213,6 +1022,25 @@ where
     Ok(header_map)
 }
 
+/// configured headers, and Accept-Encoding of the compression setting
+/// unless the header is configured explicitly.
+/// without the compression setting, reqwest accepts and decodes gzip except for range requests,
+/// which is set here so that the headers describe what is sent.
+pub fn create_default_headers(request: &CrawlerRequest) -> Result<HeaderMap, CrawlerError> {
+    let mut header_map = create_header_map(&request.header)?;
+    if header_map.contains_key(ACCEPT_ENCODING) {
+        return Ok(header_map);
+    }
+    let accept_encoding = match &request.compression {
+        Some(compression) => HeaderValue::from_str(&compression.accept_encoding_header())
+            .map_err(|err| other_error!("failed to create accept encoding header: {}", err))?,
+        None if header_map.contains_key(RANGE) => return Ok(header_map),
+        None => HeaderValue::from_static("gzip"),
+    };
+    header_map.insert(ACCEPT_ENCODING, accept_encoding);
+    Ok(header_map)
+}
+
 fn header_map_to_hash_map(header_map: &HeaderMap) -> HashMap<String, String> {
     let mut string_map: HashMap<String, String> = HashMap::new();
     for (key, val) in header_map.iter() {
@@ -239,6 +1067,13 @@ fn try_crawler() {
         body_params: HashMap::new(),
         encoding_setting: None,
         sleep: None,
+        proxy: None,
+        tls: None,
+        max_body_size: None,
+        stream_body: false,
+        compression: None,
+        redirect: None,
+        content_sniffing: ContentSniffing::Fallback,
     };
 
     let response: CrawlerResult = TestCrawler.crawl(&raven_request).unwrap();
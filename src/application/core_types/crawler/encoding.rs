@@ -1,8 +1,21 @@
-use crate::charset::Charset;
+use crate::charset::{Charset, ConversionMode};
 use serde_derive::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, PartialEq, Eq, Clone, Serialize)]
 pub struct Encoding {
     pub input: Option<Charset>,
     pub output: Charset,
+
+    /// detect charset from BOM, html meta or xml declaration
+    /// when Content-Type header does not have it.
+    #[serde(default)]
+    pub sniff: bool,
+
+    /// guess charset from the bytes when nothing is declared. only japanese charsets are guessed.
+    #[serde(default)]
+    pub statistical_detection: bool,
+
+    /// how malformed or unmappable sequences are handled on conversion. lenient if not configured.
+    #[serde(default)]
+    pub conversion_mode: ConversionMode,
 }
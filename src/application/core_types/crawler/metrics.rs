@@ -3,6 +3,7 @@ use crate::application::core_types::crawler::{
     CrawlerError, CrawlerError::*, CrawlerRequest, CrawlerResult,
 };
 use crate::application::core_types::log::elastic_search::EsDocument;
+use crate::charset_sniffing::CharsetSource;
 use chrono::{DateTime, Local};
 use rs_es::operations::mapping::Settings;
 use serde_derive::Serialize;
@@ -24,6 +25,15 @@ pub struct CrawlerMetrics {
     #[serde(with = "yyyy_mm_dd_hh_ii_ss_z")]
     pub crawled_date: DateTime<Local>,
     pub hostname: String,
+    pub proxy: Option<String>,
+    pub removed_proxies: Vec<String>,
+    pub raw_body_size: Option<u64>,
+    pub decoded_body_size: Option<u64>,
+    pub final_url: Option<String>,
+    pub redirect_chain: Vec<String>,
+    pub detected_charset: Option<String>,
+    pub charset_source: Option<CharsetSource>,
+    pub replacement_count: Option<u64>,
 
     #[serde(skip_serializing)]
     index_name: String,
@@ -48,6 +58,9 @@ impl CrawlerMetrics {
                     Some(crawler_result.mills_takes_to_complete_to_request)
                 }
                 OtherError { .. } => None,
+                ProxyError { .. } => None,
+                BodyTooLarge { .. } => None,
+                TooManyRedirects { .. } => None,
             },
         };
         let retry_count = match &result {
@@ -58,6 +71,9 @@ impl CrawlerMetrics {
                 TimeoutError { retry_count, .. } => retry_count.clone(),
                 CharsetConversionError { crawler_result, .. } => crawler_result.retry_count.clone(),
                 OtherError { .. } => 0,
+                ProxyError { retry_count, .. } => retry_count.clone(),
+                BodyTooLarge { .. } => 0,
+                TooManyRedirects { .. } => 0,
             },
         };
 
@@ -69,6 +85,92 @@ impl CrawlerMetrics {
                 TimeoutError { .. } => Local::now(),
                 CharsetConversionError { crawler_result, .. } => crawler_result.crawl_date,
                 OtherError { .. } => Local::now(),
+                ProxyError { .. } => Local::now(),
+                BodyTooLarge { .. } => Local::now(),
+                TooManyRedirects { .. } => Local::now(),
+            },
+        };
+
+        let (proxy, removed_proxies) = match &result {
+            Ok(success) => (success.proxy.clone(), success.removed_proxies.clone()),
+            Err(e) => match e {
+                ClientError(crawler_result)
+                | ServerError(crawler_result)
+                | CharsetConversionError { crawler_result, .. } => (
+                    crawler_result.proxy.clone(),
+                    crawler_result.removed_proxies.clone(),
+                ),
+                ProxyError {
+                    proxy,
+                    removed_proxies,
+                    ..
+                } => (proxy.clone(), removed_proxies.clone()),
+                TimeoutError { .. }
+                | OtherError { .. }
+                | BodyTooLarge { .. }
+                | TooManyRedirects { .. } => (None, vec![]),
+            },
+        };
+
+        let (raw_body_size, decoded_body_size) = match &result {
+            Ok(success) => (Some(success.raw_body_size), Some(success.decoded_body_size)),
+            Err(e) => match e {
+                ClientError(crawler_result)
+                | ServerError(crawler_result)
+                | CharsetConversionError { crawler_result, .. } => (
+                    Some(crawler_result.raw_body_size),
+                    Some(crawler_result.decoded_body_size),
+                ),
+                TimeoutError { .. }
+                | OtherError { .. }
+                | ProxyError { .. }
+                | BodyTooLarge { .. }
+                | TooManyRedirects { .. } => (None, None),
+            },
+        };
+
+        let (final_url, redirect_chain) = match &result {
+            Ok(success) => (
+                Some(success.final_url.clone()),
+                success.redirect_chain.clone(),
+            ),
+            Err(e) => match e {
+                ClientError(crawler_result)
+                | ServerError(crawler_result)
+                | CharsetConversionError { crawler_result, .. } => (
+                    Some(crawler_result.final_url.clone()),
+                    crawler_result.redirect_chain.clone(),
+                ),
+                TooManyRedirects { redirect_chain, .. } => (None, redirect_chain.clone()),
+                TimeoutError { .. }
+                | OtherError { .. }
+                | ProxyError { .. }
+                | BodyTooLarge { .. } => (None, vec![]),
+            },
+        };
+
+        let (detected_charset, charset_source, replacement_count) = match &result {
+            Ok(success) => (
+                success.detected_charset.as_ref().map(|c| c.to_string()),
+                success.charset_source.clone(),
+                Some(success.replacement_count),
+            ),
+            Err(e) => match e {
+                ClientError(crawler_result)
+                | ServerError(crawler_result)
+                | CharsetConversionError { crawler_result, .. } => (
+                    crawler_result
+                        .detected_charset
+                        .as_ref()
+                        .map(|c| c.to_string()),
+                    crawler_result.charset_source.clone(),
+                    Some(crawler_result.replacement_count),
+                ),
+                TimeoutError { .. }
+                | OtherError { .. }
+                | ProxyError { .. }
+                | BodyTooLarge { .. }
+                | TooManyRedirects { .. } => (None, None, None),
             },
         };
 
@@ -87,6 +189,15 @@ impl CrawlerMetrics {
             crawled_date,
             index_name,
             hostname: get_hostname().to_owned(),
+            proxy,
+            removed_proxies,
+            raw_body_size,
+            decoded_body_size,
+            final_url,
+            redirect_chain,
+            detected_charset,
+            charset_source,
+            replacement_count,
         }
     }
 }
@@ -126,7 +237,21 @@ impl<'a> EsDocument<'a> for CrawlerMetrics {
         
                                     "query_params":{ "type": "object" },
         
-                                    "body_params":{ "type": "object" }
+                                    "body_params":{ "type": "object" },
+        
+                                    "proxy":{ "type": "object" },
+        
+                                    "max_body_size":{ "type": "long" },
+        
+                                    "stream_body":{ "type": "boolean" },
+        
+                                    "compression":{ "type": "object" },
+        
+                                    "redirect":{ "type": "object" },
+        
+                                    "tls":{ "type": "object" },
+        
+                                    "content_sniffing":{ "type": "keyword" }
                                 }
                             },
         
@@ -136,6 +261,24 @@ impl<'a> EsDocument<'a> for CrawlerMetrics {
         
                             "retry_count": { "type": "integer" },
         
+                            "proxy": { "type": "keyword" },
+        
+                            "removed_proxies": { "type": "keyword" },
+        
+                            "raw_body_size": { "type": "long" },
+        
+                            "decoded_body_size": { "type": "long" },
+        
+                            "final_url": { "type": "keyword" },
+        
+                            "redirect_chain": { "type": "keyword" },
+        
+                            "detected_charset": { "type": "keyword" },
+        
+                            "charset_source": { "type": "keyword" },
+        
+                            "replacement_count": { "type": "long" },
+        
                             "crawled_date": {
                                 "type": "date",
                                 "format": "yyyy-MM-dd HH:mm:ssZZ"
@@ -168,6 +311,27 @@ impl<'a> EsDocument<'a> for CrawlerMetrics {
 
 #[test]
 fn mapping_json_test() {
-    /// if MAPPING_JSON is malformed, this test causes panic.
-    println!("{}", CrawlerMetrics::elastic_search_template());
+    // if MAPPING_JSON is malformed, this test causes panic.
+    let template = CrawlerMetrics::elastic_search_template();
+
+    assert_eq!(template["index_patterns"][0], "raven-crawler-*");
+    let properties = &template["mappings"][CrawlerMetrics::elastic_search_typename()]["properties"];
+    assert_eq!(properties["result_code"]["type"], "integer");
+    assert_eq!(properties["request"]["type"], "nested");
+    let keywords = [
+        "proxy",
+        "removed_proxies",
+        "final_url",
+        "redirect_chain",
+        "charset_source",
+    ];
+    for field in keywords.iter() {
+        assert_eq!(properties[field]["type"], "keyword", "{}", field);
+    }
+    for field in ["raw_body_size", "decoded_body_size", "replacement_count"].iter() {
+        assert_eq!(properties[field]["type"], "long", "{}", field);
+    }
+    let request_properties = &properties["request"]["properties"];
+    assert_eq!(request_properties["tls"]["type"], "object");
+    assert_eq!(request_properties["content_sniffing"]["type"], "keyword");
 }
@@ -1,10 +1,18 @@
+pub mod content_sniffing;
 pub mod encoding;
+pub mod proxy;
+pub mod redirect;
 pub mod request;
 #[macro_use]
 pub mod result;
+pub mod tls;
 pub use self::request::CrawlerRequest;
 pub use self::result::{CrawlerError, CrawlerResult};
+pub mod async_crawler;
+pub mod body;
+pub mod compression;
 pub mod crawler;
 pub mod metrics;
 
+pub use self::async_crawler::AsyncCrawler;
 pub use self::crawler::Crawler;
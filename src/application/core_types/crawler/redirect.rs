@@ -0,0 +1,72 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// redirect settings of a request.
+///
+/// redirects are followed by the crawler instead of the http client,
+/// so that every hop is recorded in `redirect_chain` of CrawlerResult.
+/// a redirect response which is not followed is returned as the result.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Serialize, Hash)]
+pub struct RedirectSetting {
+    #[serde(default)]
+    pub policy: RedirectPolicy,
+
+    #[serde(default = "default_max_hops")]
+    pub max_hops: u8,
+
+    /// headers which are dropped when a redirect leaves the origin of the request,
+    /// in addition to Authorization, Cookie and Proxy-Authorization.
+    #[serde(default)]
+    pub sensitive_headers: Vec<String>,
+}
+
+/// headers which carry credentials, and are never sent to another origin by redirects.
+const SENSITIVE_HEADERS: [&str; 3] = ["authorization", "cookie", "proxy-authorization"];
+
+fn default_max_hops() -> u8 {
+    10
+}
+
+impl Default for RedirectSetting {
+    fn default() -> Self {
+        RedirectSetting {
+            policy: RedirectPolicy::default(),
+            max_hops: default_max_hops(),
+            sensitive_headers: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Serialize, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectPolicy {
+    /// follow all redirects up to max_hops.
+    #[default]
+    Follow,
+
+    /// never follow redirects.
+    None,
+
+    /// follow redirects only to the same host as the redirecting url.
+    SameHost,
+}
+
+impl RedirectSetting {
+    /// whether the header is dropped by a redirect to another origin.
+    pub fn is_sensitive_header(&self, name: &str) -> bool {
+        SENSITIVE_HEADERS
+            .iter()
+            .any(|sensitive| sensitive.eq_ignore_ascii_case(name))
+            || self
+                .sensitive_headers
+                .iter()
+                .any(|sensitive| sensitive.eq_ignore_ascii_case(name))
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        match self.policy {
+            RedirectPolicy::None => vec![],
+            _ if self.max_hops == 0 => vec!["max_hops of redirect must be positive".to_owned()],
+            _ => vec![],
+        }
+    }
+}
@@ -1,8 +1,13 @@
+use super::compression::CompressionSetting;
+use super::content_sniffing::ContentSniffing;
 use super::encoding::Encoding;
+use super::proxy::ProxySetting;
+use super::redirect::RedirectSetting;
+use super::tls::TlsSetting;
 use crate::macros::HashMap;
 use serde_derive::*;
 
-#[derive(Debug, Eq, PartialEq, Serialize, Clone)]
+#[derive(Debug, Default, Eq, PartialEq, Serialize, Clone)]
 pub struct CrawlerRequest {
     pub url: String,
     pub method: Method,
@@ -13,10 +18,18 @@ pub struct CrawlerRequest {
     pub query_params: HashMap<String, String>,
     pub body_params: HashMap<String, String>,
     pub sleep: Option<u16>,
+    pub proxy: Option<ProxySetting>,
+    pub tls: Option<TlsSetting>,
+    pub max_body_size: Option<u64>,
+    pub stream_body: bool,
+    pub compression: Option<CompressionSetting>,
+    pub redirect: Option<RedirectSetting>,
+    pub content_sniffing: ContentSniffing,
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Clone, Serialize)]
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Clone, Serialize)]
 pub enum Method {
+    #[default]
     Get,
     Post,
 }
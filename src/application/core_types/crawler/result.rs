@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
-use crate::charset::Charset;
+use super::body::ResponseHead;
+use super::compression::{decode_body, ContentCoding};
+use crate::charset::{Charset, ConversionMode};
+use crate::charset_sniffing::CharsetSource;
 use crate::mime::Mime;
 use chrono::{DateTime, Local};
 use serde_derive::Serialize;
@@ -18,14 +21,37 @@ pub enum CrawlerError {
         retry_count: u8,
     },
 
+    /// `invalid_offsets` are byte offsets of the sequences which could not be converted in strict mode.
     CharsetConversionError {
         error_detail: String,
+        invalid_offsets: Vec<usize>,
         crawler_result: CrawlerResult,
     },
 
     OtherError {
         error_detail: String,
     },
+
+    ProxyError {
+        proxy: Option<String>,
+        error_detail: String,
+        retry_count: u8,
+        removed_proxies: Vec<String>,
+    },
+
+    /// response body exceeds `max_body_size` of the request.
+    /// `received_size` is the content length, or the size read until aborted.
+    BodyTooLarge {
+        max_body_size: u64,
+        received_size: u64,
+    },
+
+    /// redirects exceed `max_hops` of the redirect setting.
+    /// `redirect_chain` has all urls including the one which was not followed.
+    TooManyRedirects {
+        max_hops: u8,
+        redirect_chain: Vec<String>,
+    },
 }
 
 impl CrawlerError {
@@ -36,6 +62,9 @@ impl CrawlerError {
             CrawlerError::TimeoutError { .. } => 600,
             CrawlerError::CharsetConversionError { .. } => 700,
             CrawlerError::OtherError { .. } => 800,
+            CrawlerError::ProxyError { .. } => 900,
+            CrawlerError::BodyTooLarge { .. } => 1100,
+            CrawlerError::TooManyRedirects { .. } => 1200,
         }
     }
 
@@ -46,6 +75,9 @@ impl CrawlerError {
             CrawlerError::TimeoutError { .. } => "timeout",
             CrawlerError::CharsetConversionError { .. } => "charset conversion failed",
             CrawlerError::OtherError { error_detail: _ } => "other error",
+            CrawlerError::ProxyError { .. } => "proxy error",
+            CrawlerError::BodyTooLarge { .. } => "body too large",
+            CrawlerError::TooManyRedirects { .. } => "too many redirects",
         }
     }
 }
@@ -78,6 +110,35 @@ impl Display for CrawlerError {
             CrawlerError::OtherError { error_detail } => {
                 write!(f, "unexpected error: {}", error_detail)
             }
+            CrawlerError::ProxyError {
+                proxy,
+                error_detail,
+                retry_count,
+                ..
+            } => write!(
+                f,
+                "proxy error: {} (proxy: {}, retry: {})",
+                error_detail,
+                proxy.as_deref().unwrap_or("none"),
+                retry_count
+            ),
+            CrawlerError::BodyTooLarge {
+                max_body_size,
+                received_size,
+            } => write!(
+                f,
+                "response body exceeds max body size: {} bytes (received: {} bytes)",
+                max_body_size, received_size
+            ),
+            CrawlerError::TooManyRedirects {
+                max_hops,
+                redirect_chain,
+            } => write!(
+                f,
+                "too many redirects: exceeds {} hops ({})",
+                max_hops,
+                redirect_chain.join(" -> ")
+            ),
         }
     }
 }
@@ -99,7 +160,27 @@ pub struct CrawlerResult {
     pub mills_takes_to_complete_to_request: i64,
     pub retry_count: u8,
     pub response_content_type: Mime,
+    /// charset of the response body before conversion.
+    pub detected_charset: Option<Charset>,
+    /// where detected_charset comes from.
+    pub charset_source: Option<CharsetSource>,
+    /// number of malformed or unmappable sequences replaced on charset conversion.
+    pub replacement_count: u64,
     pub crawl_date: DateTime<Local>,
+    pub proxy: Option<String>,
+    pub removed_proxies: Vec<String>,
+    /// size of the body as received, before content decoding.
+    pub raw_body_size: u64,
+    /// size of the body after content decoding. same as raw_body_size if it is not decoded.
+    pub decoded_body_size: u64,
+    /// url of the response, after following redirects.
+    pub final_url: String,
+    /// urls which were redirected, in the order of hops. final_url is not included.
+    pub redirect_chain: Vec<String>,
+    /// headers sent with the request to final_url, except Host and the headers of the body.
+    /// not serialized, since Proxy-Authorization has the credentials of the proxy.
+    #[serde(skip_serializing)]
+    pub request_header: HashMap<String, String>,
 }
 
 pub fn get_result_code(result: &Result<CrawlerResult, CrawlerError>) -> u16 {
@@ -117,16 +198,49 @@ pub fn get_result_message(result: &Result<CrawlerResult, CrawlerError>) -> Strin
 }
 
 impl CrawlerResult {
-    pub fn convert_response_encoding_if_has_text_mime_type(&mut self, to: Charset) {
+    /// decode the body compressed with the codings.
+    /// this has to be done before charset conversion.
+    pub fn decode_content_encoding(
+        &mut self,
+        codings: &[ContentCoding],
+        max_body_size: Option<u64>,
+    ) -> Result<(), CrawlerError> {
+        if codings.is_empty() {
+            return Ok(());
+        }
+
+        self.response_body = decode_body(&self.response_body, codings, max_body_size)?;
+        self.decoded_body_size = self.response_body.len() as u64;
+        Ok(())
+    }
+
+    /// returns byte offsets of the sequences which could not be converted.
+    pub fn convert_response_encoding_if_has_text_mime_type(
+        &mut self,
+        to: Charset,
+        mode: &ConversionMode,
+    ) -> Vec<usize> {
         match &mut self.response_content_type {
             Mime::Text {
                 charset: Some(charset),
                 ..
             } => {
-                self.response_body = charset.convert_to(&to, &self.response_body);
+                let (converted, invalid_offsets) =
+                    charset.convert_with(&to, &self.response_body, mode);
+                self.response_body = converted;
+                self.replacement_count = invalid_offsets.len() as u64;
                 *charset = to;
+                invalid_offsets
             }
-            _ => (),
+            _ => vec![],
+        }
+    }
+
+    pub fn head(&self) -> ResponseHead<'_> {
+        ResponseHead {
+            status: self.response_status,
+            mime: &self.response_content_type,
+            final_url: &self.final_url,
         }
     }
 
@@ -0,0 +1,237 @@
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::PKey;
+use openssl::stack::Stack;
+use openssl::x509::X509;
+use reqwest::r#async::ClientBuilder as AsyncClientBuilder;
+use reqwest::{Certificate, ClientBuilder, Identity};
+use serde_derive::{Deserialize, Serialize};
+use std::fs::read;
+use std::path::Path;
+
+/// tls settings of a request.
+///
+/// `root_certificates` are added to the default trust roots (PEM or DER files).
+/// `danger_accept_invalid_certs` disables certificate verification entirely,
+/// so it must be used only for staging environments.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Serialize, Hash)]
+pub struct TlsSetting {
+    #[serde(default)]
+    pub root_certificates: Vec<String>,
+
+    pub client_identity: Option<ClientIdentity>,
+
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
+}
+
+/// client certificate for mutual tls.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Serialize, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientIdentity {
+    /// certificate (followed by its chain) and private key in PEM format.
+    Pem { cert_path: String, key_path: String },
+
+    /// DER-formatted PKCS #12 archive.
+    Pkcs12 {
+        path: String,
+
+        #[serde(default, skip_serializing)]
+        password: String,
+    },
+}
+
+impl TlsSetting {
+    pub fn validate(&self) -> Vec<String> {
+        let mut file_paths: Vec<&String> = self.root_certificates.iter().collect();
+        match &self.client_identity {
+            Some(ClientIdentity::Pem {
+                cert_path,
+                key_path,
+            }) => {
+                file_paths.push(cert_path);
+                file_paths.push(key_path);
+            }
+            Some(ClientIdentity::Pkcs12 { path, .. }) => file_paths.push(path),
+            None => (),
+        }
+
+        if self.danger_accept_invalid_certs {
+            warn!("danger_accept_invalid_certs is enabled. certificates will not be verified.");
+        }
+
+        file_paths
+            .into_iter()
+            .filter(|path| !Path::new(path).is_file())
+            .map(|path| format!("tls file does not exist: {}", path))
+            .collect()
+    }
+
+    /// load certificates from files and set them to client builder.
+    pub fn apply_to(&self, builder: ClientBuilder) -> Result<ClientBuilder, String> {
+        let mut builder = builder;
+        for certificate in self.load_root_certificates()? {
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        if let Some(identity) = &self.client_identity {
+            builder = builder.identity(identity.load()?);
+        }
+
+        Ok(builder.danger_accept_invalid_certs(self.danger_accept_invalid_certs))
+    }
+
+    /// same as apply_to, for the builder of async client.
+    pub fn apply_to_async(
+        &self,
+        builder: AsyncClientBuilder,
+    ) -> Result<AsyncClientBuilder, String> {
+        let mut builder = builder;
+        for certificate in self.load_root_certificates()? {
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        if let Some(identity) = &self.client_identity {
+            builder = builder.identity(identity.load()?);
+        }
+
+        Ok(builder.danger_accept_invalid_certs(self.danger_accept_invalid_certs))
+    }
+
+    fn load_root_certificates(&self) -> Result<Vec<Certificate>, String> {
+        self.root_certificates
+            .iter()
+            .map(|cert_path| load_certificate(cert_path))
+            .collect()
+    }
+}
+
+impl ClientIdentity {
+    pub fn load(&self) -> Result<Identity, String> {
+        match self {
+            ClientIdentity::Pem {
+                cert_path,
+                key_path,
+            } => {
+                let pkcs12_der = pem_to_pkcs12_der(&read_file(cert_path)?, &read_file(key_path)?)
+                    .map_err(|e| {
+                    format!(
+                        "failed to load client certificate({}, {}): {}",
+                        cert_path, key_path, e
+                    )
+                })?;
+                Identity::from_pkcs12_der(&pkcs12_der, "")
+                    .map_err(|e| format!("failed to load client certificate({}): {}", cert_path, e))
+            }
+
+            ClientIdentity::Pkcs12 { path, password } => {
+                Identity::from_pkcs12_der(&read_file(path)?, password)
+                    .map_err(|e| format!("failed to load client certificate({}): {}", path, e))
+            }
+        }
+    }
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, String> {
+    read(path).map_err(|e| format!("failed to read {}: {}", path, e))
+}
+
+fn load_certificate(path: &str) -> Result<Certificate, String> {
+    let content = read_file(path)?;
+    let is_pem = content.starts_with(b"-----BEGIN");
+    let result = if is_pem {
+        Certificate::from_pem(&content)
+    } else {
+        Certificate::from_der(&content)
+    };
+    result.map_err(|e| format!("failed to load root certificate({}): {}", path, e))
+}
+
+/// the native tls backend only accepts PKCS #12 archive as client identity,
+/// so PEM certificate and key are packed into an archive without password.
+fn pem_to_pkcs12_der(cert_pem: &[u8], key_pem: &[u8]) -> Result<Vec<u8>, String> {
+    let mut certs = X509::stack_from_pem(cert_pem).map_err(|e| e.to_string())?;
+    if certs.is_empty() {
+        return Err("certificate is not found".to_owned());
+    }
+    let leaf_cert = certs.remove(0);
+    let private_key = PKey::private_key_from_pem(key_pem).map_err(|e| e.to_string())?;
+
+    let mut chain = Stack::new().map_err(|e| e.to_string())?;
+    for cert in certs {
+        chain.push(cert).map_err(|e| e.to_string())?;
+    }
+
+    let mut builder = Pkcs12::builder();
+    builder.ca(chain);
+    builder
+        .build("", "raven", &private_key, &leaf_cert)
+        .and_then(|pkcs12| pkcs12.to_der())
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn tls_setting_test() {
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::rsa::Rsa;
+    use openssl::x509::X509NameBuilder;
+    use std::fs::{create_dir_all, write};
+
+    // arrange
+    let dir = "/var/tmp/application/tls";
+    create_dir_all(dir).unwrap();
+
+    let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "raven.test").unwrap();
+    let name = name.build();
+    let mut cert = X509::builder().unwrap();
+    cert.set_version(2).unwrap();
+    cert.set_subject_name(&name).unwrap();
+    cert.set_issuer_name(&name).unwrap();
+    cert.set_pubkey(&private_key).unwrap();
+    cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    cert.sign(&private_key, MessageDigest::sha256()).unwrap();
+    let cert = cert.build();
+
+    let cert_path = format!("{}/cert.pem", dir);
+    let key_path = format!("{}/key.pem", dir);
+    write(&cert_path, cert.to_pem().unwrap()).unwrap();
+    write(&key_path, private_key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+
+    let valid_setting = TlsSetting {
+        root_certificates: vec![cert_path.clone()],
+        client_identity: Some(ClientIdentity::Pem {
+            cert_path: cert_path.clone(),
+            key_path: key_path.clone(),
+        }),
+        danger_accept_invalid_certs: false,
+    };
+    let missing_setting = TlsSetting {
+        root_certificates: vec![format!("{}/not_exists.pem", dir)],
+        client_identity: Some(ClientIdentity::Pkcs12 {
+            path: format!("{}/not_exists.p12", dir),
+            password: "".to_owned(),
+        }),
+        danger_accept_invalid_certs: true,
+    };
+
+    // act & assert
+    assert_eq!(valid_setting.validate(), Vec::<String>::new());
+    assert!(valid_setting.apply_to(ClientBuilder::new()).is_ok());
+    assert!(valid_setting
+        .apply_to_async(AsyncClientBuilder::new())
+        .is_ok());
+
+    assert_eq!(
+        missing_setting.validate(),
+        vec![
+            format!("tls file does not exist: {}/not_exists.pem", dir),
+            format!("tls file does not exist: {}/not_exists.p12", dir),
+        ]
+    );
+    assert!(missing_setting.apply_to(ClientBuilder::new()).is_err());
+}
@@ -0,0 +1,305 @@
+use super::{
+    sidecar::{content_hash, SidecarMetadata},
+    PersistError,
+};
+use crate::application::core_types::crawler::{CrawlerRequest, CrawlerResult};
+use chrono::{DateTime, Datelike, Local, Timelike};
+use flate2::{write::GzEncoder, Compression};
+use serde_derive::*;
+use std::collections::HashMap;
+use std::fs::{create_dir_all, rename, File};
+use std::io::{BufWriter, Error as IOError, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tar::{Builder, EntryType, Header};
+use zip::{result::ZipError, write::FileOptions, CompressionMethod, ZipWriter};
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct FailedToWriteArchive(pub String);
+
+impl From<FailedToWriteArchive> for PersistError {
+    fn from(e: FailedToWriteArchive) -> Self {
+        PersistError::FailedToWriteArchive(e.0)
+    }
+}
+
+impl From<IOError> for FailedToWriteArchive {
+    fn from(e: IOError) -> Self {
+        FailedToWriteArchive(e.to_string())
+    }
+}
+
+impl From<ZipError> for FailedToWriteArchive {
+    fn from(e: ZipError) -> Self {
+        FailedToWriteArchive(e.to_string())
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+/// an archive of a run, written to `{path}.part` and renamed to the path when finished.
+struct Archive {
+    path: String,
+    temp_path: String,
+    writer: ArchiveWriter,
+    entry_count: usize,
+}
+
+enum ArchiveWriter {
+    Tar(Builder<BufWriter<File>>),
+    TarGz(Builder<GzEncoder<BufWriter<File>>>),
+    Zip(ZipWriter<BufWriter<File>>),
+}
+
+/// archives by path, which are open until finish_archives is called.
+type Archives = HashMap<String, (ArchiveFormat, Arc<Mutex<Archive>>)>;
+
+lazy_static! {
+    static ref ARCHIVES: Mutex<Archives> = Mutex::new(HashMap::new());
+}
+
+/// append the body of the response as an entry of the archive, and its metadata if requested.
+/// the archive is shared by all tasks, so entries of concurrent tasks never interleave.
+pub fn write_to_archive(
+    path: &str,
+    format: ArchiveFormat,
+    entry_name: &str,
+    with_metadata: bool,
+    request: &CrawlerRequest,
+    result: &CrawlerResult,
+) -> Result<(), FailedToWriteArchive> {
+    let metadata = if with_metadata {
+        let metadata = SidecarMetadata::new(request, result, content_hash(&result.response_body));
+        let json = metadata
+            .to_json()
+            .map_err(|e| FailedToWriteArchive(e.to_string()))?;
+        Some(json)
+    } else {
+        None
+    };
+
+    // entries are relative to the root of the archive.
+    let entry_name = entry_name.trim_start_matches('/');
+    let archive = get_archive(path, format)?;
+    let mut archive = archive.lock().expect("archive lock is poisoned");
+    archive.append(entry_name, &result.response_body, &result.crawl_date)?;
+    if let Some(metadata) = metadata {
+        archive.append(
+            &format!("{}.meta.json", entry_name),
+            &metadata,
+            &result.crawl_date,
+        )?;
+    }
+    Ok(())
+}
+
+/// complete all open archives and move them to their paths.
+/// called once at the end of a run, after all tasks are finished.
+pub fn finish_archives() -> Result<(), PersistError> {
+    let archives: Vec<Arc<Mutex<Archive>>> = ARCHIVES
+        .lock()
+        .expect("archives lock is poisoned")
+        .drain()
+        .map(|(_, (_, archive))| archive)
+        .collect();
+
+    let errors: Vec<String> = archives
+        .into_iter()
+        .flat_map(|archive| match Arc::try_unwrap(archive) {
+            Ok(archive) => archive
+                .into_inner()
+                .expect("archive lock is poisoned")
+                .finish()
+                .err(),
+            Err(_) => Some(FailedToWriteArchive(
+                "archive is still being written".to_owned(),
+            )),
+        })
+        .map(|e| e.0)
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(PersistError::FailedToWriteArchive(errors.join(", ")))
+    }
+}
+
+fn get_archive(
+    path: &str,
+    format: ArchiveFormat,
+) -> Result<Arc<Mutex<Archive>>, FailedToWriteArchive> {
+    let mut archives = ARCHIVES.lock().expect("archives lock is poisoned");
+    if let Some((opened_format, archive)) = archives.get(path) {
+        if *opened_format != format {
+            return Err(FailedToWriteArchive(format!(
+                "{} is already written in another format",
+                path
+            )));
+        }
+        return Ok(archive.clone());
+    }
+
+    let archive = Arc::new(Mutex::new(Archive::create(path, format)?));
+    archives.insert(path.to_owned(), (format, archive.clone()));
+    Ok(archive)
+}
+
+impl Archive {
+    fn create(path: &str, format: ArchiveFormat) -> Result<Archive, FailedToWriteArchive> {
+        if let Some(dir) = Path::new(path).parent() {
+            if !dir.as_os_str().is_empty() {
+                create_dir_all(dir)?;
+            }
+        }
+        let temp_path = format!("{}.part", path);
+        let file = BufWriter::new(File::create(&temp_path)?);
+        let writer = match format {
+            ArchiveFormat::Tar => ArchiveWriter::Tar(Builder::new(file)),
+            ArchiveFormat::TarGz => {
+                ArchiveWriter::TarGz(Builder::new(GzEncoder::new(file, Compression::default())))
+            }
+            ArchiveFormat::Zip => ArchiveWriter::Zip(ZipWriter::new(file)),
+        };
+
+        Ok(Archive {
+            path: path.to_owned(),
+            temp_path,
+            writer,
+            entry_count: 0,
+        })
+    }
+
+    fn append(
+        &mut self,
+        entry_name: &str,
+        content: &[u8],
+        modified: &DateTime<Local>,
+    ) -> Result<(), FailedToWriteArchive> {
+        match &mut self.writer {
+            ArchiveWriter::Tar(builder) => {
+                append_tar_entry(builder, entry_name, content, modified)?
+            }
+            ArchiveWriter::TarGz(builder) => {
+                append_tar_entry(builder, entry_name, content, modified)?
+            }
+            ArchiveWriter::Zip(zip) => {
+                // zip stores local time of 1980 to 2107 without time zone.
+                let modified_time = zip::DateTime::from_date_and_time(
+                    modified.year() as u16,
+                    modified.month() as u8,
+                    modified.day() as u8,
+                    modified.hour() as u8,
+                    modified.minute() as u8,
+                    modified.second() as u8,
+                )
+                .unwrap_or_default();
+                let options = FileOptions::default()
+                    .compression_method(CompressionMethod::Deflated)
+                    .last_modified_time(modified_time)
+                    .large_file(content.len() as u64 >= u64::from(u32::MAX));
+                zip.start_file(entry_name, options)?;
+                zip.write_all(content)?;
+            }
+        }
+        self.entry_count += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), FailedToWriteArchive> {
+        let mut file = match self.writer {
+            ArchiveWriter::Tar(builder) => builder.into_inner()?,
+            ArchiveWriter::TarGz(builder) => builder.into_inner()?.finish()?,
+            ArchiveWriter::Zip(mut zip) => zip.finish()?,
+        };
+        file.flush()?;
+        rename(&self.temp_path, &self.path)?;
+        info!(
+            "archive is finished: {} ({} entries)",
+            self.path, self.entry_count
+        );
+        Ok(())
+    }
+}
+
+fn append_tar_entry<W: Write>(
+    builder: &mut Builder<W>,
+    entry_name: &str,
+    content: &[u8],
+    modified: &DateTime<Local>,
+) -> Result<(), IOError> {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(modified.timestamp().max(0) as u64);
+    builder.append_data(&mut header, entry_name, content)
+}
+
+#[test]
+fn archive_test() {
+    use std::fs::{read, remove_file};
+    use std::io::Read;
+    use tar::Archive as TarArchive;
+    use zip::ZipArchive;
+
+    // arrange
+    let tar_path = "/var/tmp/application/archive/unit.tar";
+    let zip_path = "/var/tmp/application/archive/unit.zip";
+    let _ = remove_file(tar_path);
+    let _ = remove_file(zip_path);
+    let modified = Local::now();
+    let write = |path: &str, format: ArchiveFormat, entry_name: &str, content: &[u8]| {
+        let archive = get_archive(path, format).unwrap();
+        let mut archive = archive.lock().unwrap();
+        archive.append(entry_name, content, &modified).unwrap();
+    };
+
+    // act
+    write(tar_path, ArchiveFormat::Tar, "pages/1.html", b"first");
+    write(tar_path, ArchiveFormat::Tar, "pages/2.html", b"second");
+    write(zip_path, ArchiveFormat::Zip, "pages/1.html", b"first");
+    let other_format = get_archive(tar_path, ArchiveFormat::Zip);
+    let finished = finish_archives();
+
+    // assert
+    assert!(other_format.is_err());
+    assert_eq!(finished, Ok(()));
+
+    let tar = read(tar_path).unwrap();
+    let mut tar_archive = TarArchive::new(tar.as_slice());
+    let entries: Vec<(String, Vec<u8>)> = tar_archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let mut content = vec![];
+            entry.read_to_end(&mut content).unwrap();
+            (
+                entry.path().unwrap().to_string_lossy().into_owned(),
+                content,
+            )
+        })
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            ("pages/1.html".to_owned(), b"first".to_vec()),
+            ("pages/2.html".to_owned(), b"second".to_vec()),
+        ]
+    );
+
+    let mut zip_archive = ZipArchive::new(File::open(zip_path).unwrap()).unwrap();
+    let mut content = String::new();
+    zip_archive
+        .by_name("pages/1.html")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "first");
+}
@@ -0,0 +1,118 @@
+use super::{
+    content_addressed::persist_content_addressed_async,
+    error::PersistError,
+    local_file::write_to_local,
+    persist::{exchange_required, write_exchange, Placement},
+    persist_method::PersistMethod,
+    s3::{write_to_s3_async, S3ClientConfig},
+};
+use crate::application::core_types::crawler::{CrawlerRequest, CrawlerResult};
+use crate::mime::Mime;
+use futures::future::{self, Future};
+use std::sync::Arc;
+use tokio_threadpool::blocking;
+
+pub type PersistFuture = Box<dyn Future<Item = (), Error = PersistError> + Send>;
+
+pub type PlacementFuture = Box<dyn Future<Item = Placement, Error = PersistError> + Send>;
+
+/// non-blocking counterpart of Persist.
+/// the returned future must be executed on a tokio runtime.
+pub trait AsyncPersist {
+    fn persist_data_async(
+        &self,
+        method: &PersistMethod,
+        data: Arc<Vec<u8>>,
+        mime: &Mime,
+    ) -> PlacementFuture {
+        async_persist_default_impl(method, data, mime)
+    }
+
+    /// persist an exchange to the persist method which needs the request and the response.
+    fn persist_exchange_async(
+        &self,
+        method: &PersistMethod,
+        request: Arc<CrawlerRequest>,
+        result: Arc<CrawlerResult>,
+    ) -> PersistFuture {
+        async_persist_exchange_default_impl(method, request, result)
+    }
+}
+
+pub fn async_persist_default_impl(
+    method: &PersistMethod,
+    content: Arc<Vec<u8>>,
+    mime: &Mime,
+) -> PlacementFuture {
+    if let Some(addressing) = method.get_content_addressing() {
+        return persist_content_addressed_async(method, addressing, content, mime);
+    }
+    let compression = method.get_compression();
+    let content = match compression.map(|c| c.compress(&content)).transpose() {
+        Ok(Some(compressed)) => Arc::new(compressed),
+        Ok(None) => content,
+        Err(e) => return Box::new(future::err(e)),
+    };
+    let target_file_name = method.get_target_file_name();
+
+    match method {
+        // file io has no non-blocking api,
+        // so it is executed as blocking section of the tokio thread pool.
+        PersistMethod::LocalFile { file_options, .. } => {
+            let file_options = file_options.clone();
+            Box::new(
+                future::poll_fn(move || {
+                    blocking(|| write_to_local(&target_file_name, &content, &file_options))
+                })
+                .map_err(|e| PersistError::FailedToWriteLocalFile(e.to_string()))
+                .and_then(|result| result.map(Placement::from).map_err(PersistError::from)),
+            )
+        }
+
+        PersistMethod::AmazonS3 {
+            region,
+            bucket_name,
+            endpoint,
+            credentials,
+            object_options,
+            upload,
+            ..
+        } => Box::new(
+            write_to_s3_async(
+                &S3ClientConfig::new(region, endpoint, credentials),
+                bucket_name,
+                &target_file_name,
+                object_options,
+                upload,
+                mime,
+                compression.map(|c| c.content_encoding()),
+                content,
+            )
+            .map(|_| Placement::Written(target_file_name))
+            .map_err(PersistError::from),
+        ),
+
+        PersistMethod::Warc { .. }
+        | PersistMethod::JsonLines { .. }
+        | PersistMethod::Sqlite { .. }
+        | PersistMethod::Postgres { .. }
+        | PersistMethod::Archive { .. }
+        | PersistMethod::Stdout { .. } => Box::new(future::err(exchange_required(method))),
+    }
+}
+
+/// the writers of the exchange outputs are blocking,
+/// so they are executed as blocking sections of the tokio thread pool.
+pub fn async_persist_exchange_default_impl(
+    method: &PersistMethod,
+    request: Arc<CrawlerRequest>,
+    result: Arc<CrawlerResult>,
+) -> PersistFuture {
+    let method = method.clone();
+    let error_method = method.clone();
+    Box::new(
+        future::poll_fn(move || blocking(|| write_exchange(&method, &request, &result)))
+            .map_err(move |e| error_method.to_error(e.to_string()))
+            .and_then(|result| result),
+    )
+}
@@ -0,0 +1,255 @@
+use super::{PersistError, PersistWriter, Placement};
+use flate2::{write::GzEncoder, Compression};
+use serde_derive::{Deserialize, Serialize};
+use std::io::{Result as IOResult, Write};
+use std::mem::take;
+
+/// compression of persisted data, applied before writing.
+///
+/// `level` defaults to 6 for gzip (0-9) and 3 for zstd (1-22).
+/// `append_extension` appends ".gz" or ".zst" to the file path or the object key.
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Serialize, Hash)]
+pub struct OutputCompression {
+    pub codec: OutputCodec,
+
+    #[serde(default)]
+    pub level: Option<i32>,
+
+    #[serde(default)]
+    pub append_extension: bool,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Serialize, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputCodec {
+    Gzip,
+    Zstd,
+}
+
+impl OutputCompression {
+    pub fn validate(&self) -> Vec<String> {
+        let level = self.level();
+        let valid = match self.codec {
+            OutputCodec::Gzip => (0..=9).contains(&level),
+            OutputCodec::Zstd => (1..=22).contains(&level),
+        };
+        if valid {
+            vec![]
+        } else {
+            vec![format!(
+                "compression level {} is out of range for {}",
+                level,
+                self.content_encoding()
+            )]
+        }
+    }
+
+    /// value of Content-Encoding header for compressed data.
+    pub fn content_encoding(&self) -> &'static str {
+        match self.codec {
+            OutputCodec::Gzip => "gzip",
+            OutputCodec::Zstd => "zstd",
+        }
+    }
+
+    pub fn file_name(&self, file_name: &str) -> String {
+        if !self.append_extension {
+            return file_name.to_owned();
+        }
+        match self.codec {
+            OutputCodec::Gzip => format!("{}.gz", file_name),
+            OutputCodec::Zstd => format!("{}.zst", file_name),
+        }
+    }
+
+    pub fn compress(&self, content: &[u8]) -> Result<Vec<u8>, PersistError> {
+        let compressed = match self.codec {
+            OutputCodec::Gzip => {
+                let mut encoder = GzEncoder::new(vec![], self.gzip_level());
+                encoder.write_all(content).and_then(|_| encoder.finish())
+            }
+            OutputCodec::Zstd => zstd::encode_all(content, self.level()),
+        };
+        compressed.map_err(|e| PersistError::FailedToCompress(e.to_string()))
+    }
+
+    /// wrap a writer so that streamed data is compressed before written to it.
+    pub fn writer(
+        &self,
+        inner: Box<dyn PersistWriter>,
+    ) -> Result<Box<dyn PersistWriter>, PersistError> {
+        match self.codec {
+            OutputCodec::Gzip => Ok(Box::new(CompressingWriter::Gzip(GzEncoder::new(
+                inner,
+                self.gzip_level(),
+            )))),
+            OutputCodec::Zstd => zstd::Encoder::new(inner, self.level())
+                .map(|encoder| Box::new(CompressingWriter::Zstd(encoder)) as Box<dyn PersistWriter>)
+                .map_err(|e| PersistError::FailedToCompress(e.to_string())),
+        }
+    }
+
+    /// an encoder which compresses a sequence of records as one stream.
+    pub fn stream_encoder(&self) -> Result<StreamEncoder, PersistError> {
+        match self.codec {
+            OutputCodec::Gzip => Ok(StreamEncoder::Gzip(GzEncoder::new(
+                vec![],
+                self.gzip_level(),
+            ))),
+            OutputCodec::Zstd => zstd::Encoder::new(vec![], self.level())
+                .map(StreamEncoder::Zstd)
+                .map_err(|e| PersistError::FailedToCompress(e.to_string())),
+        }
+    }
+
+    fn level(&self) -> i32 {
+        match (self.level, &self.codec) {
+            (Some(level), _) => level,
+            (None, OutputCodec::Gzip) => 6,
+            (None, OutputCodec::Zstd) => 3,
+        }
+    }
+
+    fn gzip_level(&self) -> Compression {
+        Compression::new(self.level() as u32)
+    }
+}
+
+/// compresses records into one stream, and hands out the compressed bytes record by record.
+/// the stream is flushed for each record, so the written records can be read
+/// even if the stream is not finished, while the compression context is kept across records.
+pub enum StreamEncoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Zstd(zstd::Encoder<Vec<u8>>),
+}
+
+impl StreamEncoder {
+    /// compress a record, and return the compressed bytes produced so far.
+    pub fn encode(&mut self, record: &[u8]) -> IOResult<Vec<u8>> {
+        let compressed = match self {
+            StreamEncoder::Gzip(encoder) => {
+                encoder.write_all(record)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+            StreamEncoder::Zstd(encoder) => {
+                encoder.write_all(record)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+        };
+        Ok(take(compressed))
+    }
+
+    /// end the stream, and return the rest of the compressed bytes.
+    pub fn finish(self) -> IOResult<Vec<u8>> {
+        match self {
+            StreamEncoder::Gzip(encoder) => encoder.finish(),
+            StreamEncoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+enum CompressingWriter {
+    Gzip(GzEncoder<Box<dyn PersistWriter>>),
+    Zstd(zstd::Encoder<Box<dyn PersistWriter>>),
+}
+
+impl Write for CompressingWriter {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        match self {
+            CompressingWriter::Gzip(encoder) => encoder.write(buf),
+            CompressingWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        match self {
+            CompressingWriter::Gzip(encoder) => encoder.flush(),
+            CompressingWriter::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl PersistWriter for CompressingWriter {
+    fn finish(self: Box<Self>) -> Result<Placement, PersistError> {
+        let inner = match *self {
+            CompressingWriter::Gzip(encoder) => encoder.finish(),
+            CompressingWriter::Zstd(encoder) => encoder.finish(),
+        }
+        .map_err(|e| PersistError::FailedToCompress(e.to_string()))?;
+        inner.finish()
+    }
+}
+
+#[test]
+fn compress_test() {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    let content = "圧縮して保存する".repeat(100);
+    let gzip = OutputCompression {
+        codec: OutputCodec::Gzip,
+        level: None,
+        append_extension: true,
+    };
+    let zstd = OutputCompression {
+        codec: OutputCodec::Zstd,
+        level: Some(19),
+        append_extension: false,
+    };
+
+    let mut gunzipped = String::new();
+    GzDecoder::new(gzip.compress(content.as_bytes()).unwrap().as_slice())
+        .read_to_string(&mut gunzipped)
+        .unwrap();
+    let unzstd = zstd::decode_all(zstd.compress(content.as_bytes()).unwrap().as_slice()).unwrap();
+
+    assert_eq!(gunzipped, content);
+    assert_eq!(unzstd, content.as_bytes());
+    assert_eq!(gzip.file_name("a/b.html"), "a/b.html.gz");
+    assert_eq!(zstd.file_name("a/b.html"), "a/b.html");
+    assert_eq!(gzip.validate(), Vec::<String>::new());
+    assert_eq!(
+        OutputCompression {
+            level: Some(23),
+            ..zstd
+        }
+        .validate(),
+        vec!["compression level 23 is out of range for zstd".to_owned()]
+    );
+}
+
+#[test]
+fn stream_encoder_test() {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    for codec in vec![OutputCodec::Gzip, OutputCodec::Zstd] {
+        // arrange
+        let compression = OutputCompression {
+            codec,
+            level: None,
+            append_extension: false,
+        };
+        let mut encoder = compression.stream_encoder().unwrap();
+
+        // act
+        let mut compressed = encoder.encode(b"{\"n\":1}\n").unwrap();
+        compressed.extend(encoder.encode(b"{\"n\":2}\n").unwrap());
+        compressed.extend(encoder.finish().unwrap());
+
+        // assert: the records are compressed as one stream.
+        let decoded = match compression.codec {
+            OutputCodec::Gzip => {
+                let mut decoded = vec![];
+                GzDecoder::new(compressed.as_slice())
+                    .read_to_end(&mut decoded)
+                    .unwrap();
+                decoded
+            }
+            OutputCodec::Zstd => zstd::decode_all(compressed.as_slice()).unwrap(),
+        };
+        assert_eq!(decoded, b"{\"n\":1}\n{\"n\":2}\n".to_vec());
+    }
+}
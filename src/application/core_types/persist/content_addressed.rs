@@ -0,0 +1,280 @@
+use super::{
+    async_persist::{async_persist_default_impl, PersistFuture, PlacementFuture},
+    local_file::OverwritePolicy,
+    persist::persist_default_impl,
+    s3::{s3_object_exists, S3ClientConfig},
+    sidecar::{content_hash, json_mime},
+    PersistError, PersistMethod, Placement,
+};
+use crate::mime::Mime;
+use futures::future::{self, Future};
+use serde_derive::*;
+use std::path::Path;
+use std::sync::Arc;
+use tokio_threadpool::blocking;
+
+/// where bodies are stored once by their sha256, so that identical bodies of repeated crawls
+/// are not stored again. the file of the output is written as a manifest pointing at the blob.
+#[derive(Debug, Deserialize, PartialEq, Clone, Eq, Hash, Serialize)]
+pub struct ContentAddressing {
+    /// directory of blobs for local_file output, or key prefix of blobs for amazon_s3 output.
+    pub blob_path: String,
+
+    /// bucket of blobs for amazon_s3 output, the bucket of the output by default.
+    #[serde(default)]
+    pub blob_bucket: Option<String>,
+}
+
+impl ContentAddressing {
+    /// path of the blob named by the hash, under a directory of the first 2 characters of the hash
+    /// so that a directory does not hold too many blobs.
+    pub fn blob_path_of(&self, content_hash: &str) -> String {
+        if self.blob_path.is_empty() {
+            format!("{}/{}", &content_hash[..2], content_hash)
+        } else {
+            format!(
+                "{}/{}/{}",
+                self.blob_path.trim_end_matches('/'),
+                &content_hash[..2],
+                content_hash
+            )
+        }
+    }
+}
+
+/// content of a manifest, written to the file of the output instead of the body.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct BlobManifest {
+    /// path of the blob file, or key of the blob object.
+    pub blob: String,
+
+    /// bucket of the blob object.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+
+    /// hex encoded sha256 of the body before output compression.
+    pub content_hash: String,
+
+    /// size of the body before output compression.
+    pub size: usize,
+
+    pub content_type: String,
+}
+
+impl BlobManifest {
+    fn new(blob_method: &PersistMethod, content_hash: String, size: usize, mime: &Mime) -> Self {
+        let bucket = match blob_method {
+            PersistMethod::AmazonS3 { bucket_name, .. } => Some(bucket_name.to_owned()),
+            _ => None,
+        };
+        BlobManifest {
+            blob: blob_method.get_target_file_name(),
+            bucket,
+            content_hash,
+            size,
+            content_type: mime.to_string(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, serde_json::Error> {
+        serde_json::to_vec_pretty(self)
+    }
+}
+
+/// the persist method which writes the blob of the content, compressed if requested.
+fn blob_method(
+    method: &PersistMethod,
+    addressing: &ContentAddressing,
+    content_hash: &str,
+) -> PersistMethod {
+    let mut blob_method = method.clone();
+    blob_method.update_file_path(addressing.blob_path_of(content_hash));
+    match &mut blob_method {
+        PersistMethod::LocalFile {
+            file_options,
+            sidecar,
+            content_addressed,
+            ..
+        } => {
+            // a blob written by a concurrent task has the same content.
+            file_options.overwrite = OverwritePolicy::SkipIfExists;
+            *sidecar = None;
+            *content_addressed = None;
+        }
+        PersistMethod::AmazonS3 {
+            bucket_name,
+            sidecar,
+            content_addressed,
+            ..
+        } => {
+            if let Some(blob_bucket) = &addressing.blob_bucket {
+                *bucket_name = blob_bucket.to_owned();
+            }
+            *sidecar = None;
+            *content_addressed = None;
+        }
+        _ => {}
+    }
+    blob_method
+}
+
+/// the persist method which writes the manifest to the file of the output.
+fn manifest_method(method: &PersistMethod) -> PersistMethod {
+    let mut manifest_method = method.clone();
+    if let PersistMethod::LocalFile {
+        compression,
+        sidecar,
+        content_addressed,
+        ..
+    }
+    | PersistMethod::AmazonS3 {
+        compression,
+        sidecar,
+        content_addressed,
+        ..
+    } = &mut manifest_method
+    {
+        *compression = None;
+        *sidecar = None;
+        *content_addressed = None;
+    }
+    manifest_method
+}
+
+fn blob_exists(blob_method: &PersistMethod) -> Result<bool, PersistError> {
+    let target_file_name = blob_method.get_target_file_name();
+    match blob_method {
+        PersistMethod::LocalFile { .. } => Ok(Path::new(&target_file_name).exists()),
+        PersistMethod::AmazonS3 {
+            region,
+            bucket_name,
+            endpoint,
+            credentials,
+            upload,
+            ..
+        } => Ok(s3_object_exists(
+            &S3ClientConfig::new(region, endpoint, credentials),
+            bucket_name,
+            &target_file_name,
+            upload,
+        )?),
+        _ => Ok(false),
+    }
+}
+
+/// write the content to its blob unless the blob exists,
+/// and the manifest pointing at the blob to the file of the method.
+/// returns where the manifest is placed.
+pub fn persist_content_addressed(
+    method: &PersistMethod,
+    addressing: &ContentAddressing,
+    content: &[u8],
+    mime: &Mime,
+) -> Result<Placement, PersistError> {
+    let content_hash = content_hash(content);
+    let blob_method = blob_method(method, addressing, &content_hash);
+    if !blob_exists(&blob_method)? {
+        persist_default_impl(&blob_method, content, mime)?;
+    }
+
+    let manifest = BlobManifest::new(&blob_method, content_hash, content.len(), mime)
+        .to_json()
+        .map_err(|e| method.to_error(e.to_string()))?;
+    persist_default_impl(&manifest_method(method), &manifest, &json_mime())
+}
+
+/// async counterpart of persist_content_addressed.
+pub fn persist_content_addressed_async(
+    method: &PersistMethod,
+    addressing: &ContentAddressing,
+    content: Arc<Vec<u8>>,
+    mime: &Mime,
+) -> PlacementFuture {
+    let content_hash = content_hash(&content);
+    let blob_method = blob_method(method, addressing, &content_hash);
+    let manifest =
+        match BlobManifest::new(&blob_method, content_hash, content.len(), mime).to_json() {
+            Ok(manifest) => Arc::new(manifest),
+            Err(e) => return Box::new(future::err(method.to_error(e.to_string()))),
+        };
+    let manifest_method = manifest_method(method);
+    let mime = mime.clone();
+
+    // the blob is checked by a file system call or a head request, which are blocking.
+    let checking_method = blob_method.clone();
+    let error_method = method.clone();
+    let blob_persisted = future::poll_fn(move || blocking(|| blob_exists(&checking_method)))
+        .map_err(move |e| error_method.to_error(e.to_string()))
+        .and_then(|exists| exists)
+        .and_then(move |exists| -> PersistFuture {
+            if exists {
+                Box::new(future::ok(()))
+            } else {
+                Box::new(async_persist_default_impl(&blob_method, content, &mime).map(|_| ()))
+            }
+        });
+    Box::new(
+        blob_persisted.and_then(move |_| {
+            async_persist_default_impl(&manifest_method, manifest, &json_mime())
+        }),
+    )
+}
+
+#[test]
+fn content_addressed_methods_test() {
+    use super::compression::{OutputCodec, OutputCompression};
+
+    // arrange
+    let addressing = ContentAddressing {
+        blob_path: "blobs/".to_owned(),
+        blob_bucket: Some("raven-blobs".to_owned()),
+    };
+    let method = PersistMethod::AmazonS3 {
+        region: "ap-northeast-1".to_owned(),
+        bucket_name: "raven".to_owned(),
+        object_key: "pages/index.html".to_owned(),
+        endpoint: None,
+        credentials: None,
+        object_options: Default::default(),
+        upload: Default::default(),
+        compression: Some(OutputCompression {
+            codec: OutputCodec::Gzip,
+            level: None,
+            append_extension: true,
+        }),
+        sidecar: None,
+        content_addressed: Some(addressing.clone()),
+    };
+    let content_hash = content_hash(b"raven");
+
+    // act
+    let blob_method = blob_method(&method, &addressing, &content_hash);
+    let manifest_method = manifest_method(&method);
+    let manifest = BlobManifest::new(
+        &blob_method,
+        content_hash.to_owned(),
+        5,
+        &Mime::ApplicationOctetStream,
+    );
+
+    // assert
+    assert_eq!(
+        ContentAddressing {
+            blob_path: String::new(),
+            blob_bucket: None,
+        }
+        .blob_path_of(&content_hash),
+        format!("{}/{}", &content_hash[..2], content_hash)
+    );
+    assert_eq!(method.get_target_file_name(), "pages/index.html");
+    assert_eq!(
+        blob_method.get_target_file_name(),
+        format!("blobs/{}/{}.gz", &content_hash[..2], content_hash)
+    );
+    assert_eq!(blob_method.get_content_addressing(), None);
+    assert_eq!(manifest_method.get_target_file_name(), "pages/index.html");
+    assert_eq!(manifest_method.get_compression(), None);
+    assert_eq!(manifest.blob, blob_method.get_target_file_name());
+    assert_eq!(manifest.bucket, Some("raven-blobs".to_owned()));
+    assert_eq!(manifest.content_type, "application/octet-stream");
+}
@@ -6,13 +6,34 @@ pub enum PersistError {
     FailedToWriteLocalFile(String),
 
     FailedToPutToS3(String),
+
+    FailedToCompress(String),
+
+    FailedToWriteWarc(String),
+
+    FailedToWriteJsonLines(String),
+
+    FailedToWriteSqlite(String),
+
+    FailedToWritePostgres(String),
+
+    FailedToWriteArchive(String),
+
+    FailedToWriteStdout(String),
 }
 
 impl Display for PersistError {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         match self {
             PersistError::FailedToWriteLocalFile(e) => write!(f, "failed to write to local file: {}", e),
-            PersistError::FailedToPutToS3(e) => write!(f, "failed to put to s3: {}", e)
+            PersistError::FailedToPutToS3(e) => write!(f, "failed to put to s3: {}", e),
+            PersistError::FailedToCompress(e) => write!(f, "failed to compress: {}", e),
+            PersistError::FailedToWriteWarc(e) => write!(f, "failed to write to warc file: {}", e),
+            PersistError::FailedToWriteJsonLines(e) => write!(f, "failed to write to json lines file: {}", e),
+            PersistError::FailedToWriteSqlite(e) => write!(f, "failed to write to sqlite: {}", e),
+            PersistError::FailedToWritePostgres(e) => write!(f, "failed to write to postgres: {}", e),
+            PersistError::FailedToWriteArchive(e) => write!(f, "failed to write to archive: {}", e),
+            PersistError::FailedToWriteStdout(e) => write!(f, "failed to write to stdout: {}", e)
         }
     }
 }
@@ -0,0 +1,185 @@
+use super::{
+    compression::{OutputCompression, StreamEncoder},
+    rotating_file::RotatingFile,
+    PersistError,
+};
+use crate::application::core_types::crawler::{request::Method, CrawlerRequest, CrawlerResult};
+use chrono::{DateTime, Local};
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::io::Error as IOError;
+use std::sync::{Arc, Mutex};
+
+/// one line of json lines output, which has a request and its response.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct JsonLinesRecord<'a> {
+    pub url: &'a str,
+    pub final_url: &'a str,
+    pub method: &'a Method,
+    pub query_params: &'a HashMap<String, String>,
+    pub body_params: &'a HashMap<String, String>,
+    pub status: u16,
+    pub headers: &'a HashMap<String, String>,
+    pub content_type: String,
+    pub crawl_date: &'a DateTime<Local>,
+    /// the body as it is if it is utf-8 text, otherwise base64 encoded.
+    pub body: String,
+    /// "text" or "base64".
+    pub body_encoding: &'static str,
+}
+
+impl<'a> JsonLinesRecord<'a> {
+    pub fn new(request: &'a CrawlerRequest, result: &'a CrawlerResult) -> JsonLinesRecord<'a> {
+        let text = if result.response_content_type.is_text() {
+            String::from_utf8(result.response_body.clone()).ok()
+        } else {
+            None
+        };
+        let (body, body_encoding) = match text {
+            Some(text) => (text, "text"),
+            None => (base64::encode(&result.response_body), "base64"),
+        };
+
+        JsonLinesRecord {
+            url: &request.url,
+            final_url: &result.final_url,
+            method: &request.method,
+            query_params: &request.query_params,
+            body_params: &request.body_params,
+            status: result.response_status,
+            headers: &result.response_header,
+            content_type: result.response_content_type.to_string(),
+            crawl_date: &result.crawl_date,
+            body,
+            body_encoding,
+        }
+    }
+}
+
+/// append a record of the exchange to the current json lines file of the directory and prefix.
+/// the file is shared by all tasks, so records of concurrent tasks never interleave.
+/// with compression, records of a file are compressed as one stream,
+/// which is finished when the file is rotated or finish_json_lines is called.
+pub fn write_to_json_lines(
+    directory: &str,
+    prefix: &str,
+    max_file_size: u64,
+    compression: Option<&OutputCompression>,
+    request: &CrawlerRequest,
+    result: &CrawlerResult,
+) -> Result<(), PersistError> {
+    let mut line = serde_json::to_vec(&JsonLinesRecord::new(request, result))
+        .map_err(|e| PersistError::FailedToWriteJsonLines(e.to_string()))?;
+    line.push(b'\n');
+
+    let json_lines_file = get_json_lines_file(directory, prefix, compression, max_file_size);
+    let mut json_lines_file = json_lines_file
+        .lock()
+        .expect("json lines file lock is poisoned");
+    json_lines_file.write_line(&line)
+}
+
+/// finish the compression streams of all json lines files.
+/// called once at the end of a run, after all tasks are finished.
+/// records written after this are compressed as a new stream appended to the same file,
+/// since concatenated gzip members and zstd frames are allowed.
+pub fn finish_json_lines() -> Result<(), PersistError> {
+    let json_lines_files: Vec<Arc<Mutex<JsonLinesFile>>> = JSON_LINES_FILES
+        .lock()
+        .expect("json lines files lock is poisoned")
+        .values()
+        .cloned()
+        .collect();
+
+    let errors: Vec<String> = json_lines_files
+        .iter()
+        .flat_map(|json_lines_file| {
+            json_lines_file
+                .lock()
+                .expect("json lines file lock is poisoned")
+                .finish_encoder()
+                .err()
+        })
+        .map(|e| e.to_string())
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(PersistError::FailedToWriteJsonLines(errors.join(", ")))
+    }
+}
+
+/// a rotating json lines file, with the compression stream of the current file.
+struct JsonLinesFile {
+    file: RotatingFile,
+    compression: Option<OutputCompression>,
+    encoder: Option<StreamEncoder>,
+}
+
+impl JsonLinesFile {
+    fn write_line(&mut self, line: &[u8]) -> Result<(), PersistError> {
+        if self.file.is_full() {
+            self.finish_encoder()
+                .and_then(|_| self.file.rotate_if_full())
+                .map_err(|e| PersistError::FailedToWriteJsonLines(e.to_string()))?;
+        }
+        let encoder = match (&mut self.encoder, &self.compression) {
+            (Some(encoder), _) => Some(encoder),
+            (None, Some(compression)) => Some(self.encoder.insert(compression.stream_encoder()?)),
+            (None, None) => None,
+        };
+        match encoder {
+            Some(encoder) => encoder
+                .encode(line)
+                .and_then(|compressed| self.file.write_all(&compressed)),
+            None => self.file.write_all(line),
+        }
+        .map_err(|e: IOError| PersistError::FailedToWriteJsonLines(e.to_string()))
+    }
+
+    /// write the end of the compression stream to the current file, if it is compressed.
+    fn finish_encoder(&mut self) -> Result<(), IOError> {
+        match self.encoder.take() {
+            Some(encoder) => encoder.finish().and_then(|rest| self.file.write_all(&rest)),
+            None => Ok(()),
+        }
+    }
+}
+
+lazy_static! {
+    static ref JSON_LINES_FILES: Mutex<JsonLinesFiles> = Mutex::new(HashMap::new());
+}
+
+fn get_json_lines_file(
+    directory: &str,
+    prefix: &str,
+    compression: Option<&OutputCompression>,
+    max_file_size: u64,
+) -> Arc<Mutex<JsonLinesFile>> {
+    let mut json_lines_files = JSON_LINES_FILES
+        .lock()
+        .expect("json lines files lock is poisoned");
+    json_lines_files
+        .entry((
+            directory.to_owned(),
+            prefix.to_owned(),
+            compression.cloned(),
+            max_file_size,
+        ))
+        .or_insert_with(|| {
+            let extension = match compression {
+                Some(compression) => compression.file_name(".jsonl"),
+                None => ".jsonl".to_owned(),
+            };
+            Arc::new(Mutex::new(JsonLinesFile {
+                file: RotatingFile::new(directory, prefix, &extension, max_file_size),
+                compression: compression.cloned(),
+                encoder: None,
+            }))
+        })
+        .clone()
+}
+
+/// json lines files by directory, prefix, compression and max_file_size.
+type JsonLinesFiles =
+    HashMap<(String, String, Option<OutputCompression>, u64), Arc<Mutex<JsonLinesFile>>>;
@@ -1,7 +1,17 @@
-use crate::application::core_types::persist::PersistError;
+use crate::application::core_types::persist::{PersistError, PersistWriter, Placement};
+use serde_derive::*;
 use std::{
-    fs::{create_dir_all, OpenOptions},
-    io::{BufWriter, Error as IOError, Write},
+    ffi::OsString,
+    fs::{create_dir_all, hard_link, remove_file, rename, File, OpenOptions, Permissions},
+    io::{BufWriter, Error as IOError, ErrorKind, Result as IOResult, Write},
+    iter::once,
+    os::unix::fs::{fchown, PermissionsExt},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
 };
 
 #[derive(Debug, Eq, PartialEq)]
@@ -19,27 +29,400 @@ impl From<IOError> for FailedToWriteLocal {
     }
 }
 
-pub fn write_to_local(file_path: &str, content: &[u8]) -> Result<(), FailedToWriteLocal> {
-    let split_by_slash: Vec<&str> = file_path.split("/").collect();
-    if let Some((_, dir_names)) = split_by_slash.split_last() {
-        if dir_names.len() != 0 {
-            create_dir_all(dir_names.join("/"))?;
+/// what to do when the file already exists.
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Copy, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverwritePolicy {
+    /// replace the existing file.
+    #[default]
+    Overwrite,
+
+    /// keep the existing file and discard the content.
+    SkipIfExists,
+
+    /// keep the existing file and fail the task.
+    Fail,
+
+    /// write to the first absent path of the file path itself,
+    /// `{stem}_1.{extension}`, `{stem}_2.{extension}`, ...
+    SuffixWithCounter,
+
+    /// replace the existing file, which is kept as `{file_name}.~1~`, `{file_name}.~2~`, ...
+    Versioned,
+}
+
+/// how a local file is written.
+#[derive(Debug, Deserialize, PartialEq, Clone, Eq, Hash, Serialize)]
+pub struct LocalFileOptions {
+    #[serde(default)]
+    pub overwrite: OverwritePolicy,
+
+    /// write to a temporary file next to the target and rename it to the target,
+    /// so that readers never see a partially written file.
+    #[serde(default = "default_atomic")]
+    pub atomic: bool,
+
+    /// flush the file and its directory to the disk before the task finishes.
+    #[serde(default)]
+    pub fsync: bool,
+
+    /// permission bits of the file in octal, such as "640".
+    #[serde(default)]
+    pub mode: Option<String>,
+
+    /// user id of the file owner.
+    #[serde(default)]
+    pub owner: Option<u32>,
+
+    /// group id of the file owner.
+    #[serde(default)]
+    pub group: Option<u32>,
+}
+
+fn default_atomic() -> bool {
+    true
+}
+
+impl Default for LocalFileOptions {
+    fn default() -> Self {
+        LocalFileOptions {
+            overwrite: OverwritePolicy::default(),
+            atomic: default_atomic(),
+            fsync: false,
+            mode: None,
+            owner: None,
+            group: None,
+        }
+    }
+}
+
+impl LocalFileOptions {
+    pub fn permission_bits(&self) -> Option<u32> {
+        self.mode
+            .as_ref()
+            .and_then(|mode| u32::from_str_radix(mode, 8).ok())
+            .filter(|bits| *bits <= 0o7777)
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        match &self.mode {
+            Some(mode) if self.permission_bits().is_none() => {
+                vec![format!("invalid local file mode: {}", mode)]
+            }
+            _ => vec![],
         }
-        let target_file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(file_path)?;
+    }
 
-        let mut writer = BufWriter::new(target_file);
-        writer.write_all(content)?;
-        writer.flush()?;
+    /// set permission and owner of the opened file.
+    fn apply_to(&self, file: &File) -> IOResult<()> {
+        if let Some(bits) = self.permission_bits() {
+            file.set_permissions(Permissions::from_mode(bits))?;
+        }
+        if self.owner.is_some() || self.group.is_some() {
+            fchown(file, self.owner, self.group)?;
+        }
         Ok(())
+    }
+}
+
+/// writes streamed data to a temporary file next to the target,
+/// and moves it to the target by the overwrite policy when finished.
+#[derive(Debug)]
+pub struct LocalFileWriter {
+    file_path: PathBuf,
+    temp_file_path: PathBuf,
+    options: LocalFileOptions,
+    writer: Option<BufWriter<File>>,
+}
+
+impl LocalFileWriter {
+    pub fn create(
+        file_path: &str,
+        options: &LocalFileOptions,
+    ) -> Result<LocalFileWriter, FailedToWriteLocal> {
+        let file_path = Path::new(file_path);
+        create_parent_dir(file_path)?;
+
+        let temp_file_path = temp_path(file_path);
+        let temp_file = File::create(&temp_file_path)?;
+        if let Err(e) = options.apply_to(&temp_file) {
+            let _ = remove_file(&temp_file_path);
+            return Err(e.into());
+        }
+        Ok(LocalFileWriter {
+            file_path: file_path.to_owned(),
+            temp_file_path,
+            options: options.clone(),
+            writer: Some(BufWriter::new(temp_file)),
+        })
+    }
+
+    fn writer(&mut self) -> IOResult<&mut BufWriter<File>> {
+        self.writer
+            .as_mut()
+            .ok_or_else(|| IOError::other("writer is already finished"))
+    }
+
+    /// flush the temporary file and move it to the target.
+    /// returns the path written to, or None if skipped.
+    fn commit(&mut self) -> Result<Option<PathBuf>, FailedToWriteLocal> {
+        let fsync = self.options.fsync;
+        let writer = self.writer()?;
+        writer.flush()?;
+        if fsync {
+            writer.get_ref().sync_all()?;
+        }
+
+        let placed = place_file(
+            &self.temp_file_path,
+            &self.file_path,
+            self.options.overwrite,
+        );
+        // the temporary file is moved or linked to the target, or must be removed.
+        self.writer = None;
+        let _ = remove_file(&self.temp_file_path);
+
+        let placed = placed?;
+        match &placed {
+            Some(placed_path) if self.options.fsync => sync_parent_dir(placed_path)?,
+            Some(_) => {}
+            None => info!("{} already exists, skipped", self.file_path.display()),
+        }
+        Ok(placed)
+    }
+}
+
+impl Write for LocalFileWriter {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.writer()?.write(buf)
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        self.writer()?.flush()
+    }
+}
+
+impl PersistWriter for LocalFileWriter {
+    fn finish(mut self: Box<Self>) -> Result<Placement, PersistError> {
+        Ok(self.commit()?.into())
+    }
+}
+
+impl Drop for LocalFileWriter {
+    fn drop(&mut self) {
+        if self.writer.take().is_some() {
+            let _ = remove_file(&self.temp_file_path);
+        }
+    }
+}
+
+/// returns the path written to, which differs from the file path by the overwrite policy,
+/// or None if skipped.
+pub fn write_to_local(
+    file_path: &str,
+    content: &[u8],
+    options: &LocalFileOptions,
+) -> Result<Option<PathBuf>, FailedToWriteLocal> {
+    if Path::new(file_path).file_name().is_none() {
+        return Err(FailedToWriteLocal(format!(
+            "file path has no file name: {}",
+            file_path
+        )));
+    }
+
+    if options.atomic {
+        let mut writer = LocalFileWriter::create(file_path, options)?;
+        writer.write_all(content)?;
+        writer.commit()
+    } else {
+        write_in_place(Path::new(file_path), content, options)
+    }
+}
+
+/// write directly to the target, which readers may see while it is written.
+fn write_in_place(
+    file_path: &Path,
+    content: &[u8],
+    options: &LocalFileOptions,
+) -> Result<Option<PathBuf>, FailedToWriteLocal> {
+    create_parent_dir(file_path)?;
+
+    let open_target = |create_new: bool| {
+        open_file(file_path, create_new).map(|file| (file_path.to_owned(), file))
+    };
+    let opened = match options.overwrite {
+        OverwritePolicy::Overwrite => open_target(false).map(Some)?,
+        OverwritePolicy::SkipIfExists => match open_target(true) {
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists => None,
+            opened => opened.map(Some)?,
+        },
+        OverwritePolicy::Fail => open_target(true)
+            .map(Some)
+            .map_err(|e| already_exists_error(e, file_path))?,
+        OverwritePolicy::SuffixWithCounter => candidate_paths(file_path)
+            .map(|path| open_file(&path, true).map(|file| (path, file)))
+            .find(|opened| !is_already_exists(opened))
+            .transpose()?,
+        OverwritePolicy::Versioned => {
+            let _lock = VERSIONS_LOCK.lock().expect("versions lock is poisoned");
+            if back_up_existing(file_path)? {
+                remove_file(file_path)?;
+            }
+            open_target(true).map(Some)?
+        }
+    };
+
+    let (placed_path, file) = match opened {
+        Some(opened) => opened,
+        None => {
+            info!("{} already exists, skipped", file_path.display());
+            return Ok(None);
+        }
+    };
+    options.apply_to(&file)?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(content)?;
+    writer.flush()?;
+    if options.fsync {
+        writer.get_ref().sync_all()?;
+        sync_parent_dir(&placed_path)?;
+    }
+    Ok(Some(placed_path))
+}
+
+lazy_static! {
+    /// serializes backups of versioned files, so that concurrent tasks keep every version.
+    static ref VERSIONS_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// move or link the finished temporary file to the target by the policy.
+/// returns the path written to, or None if skipped.
+fn place_file(
+    temp_file_path: &Path,
+    file_path: &Path,
+    overwrite: OverwritePolicy,
+) -> IOResult<Option<PathBuf>> {
+    // hard_link fails if the target exists, so that existing files are not replaced.
+    match overwrite {
+        OverwritePolicy::Overwrite => {
+            rename(temp_file_path, file_path)?;
+            Ok(Some(file_path.to_owned()))
+        }
+        OverwritePolicy::SkipIfExists => match hard_link(temp_file_path, file_path) {
+            Ok(()) => Ok(Some(file_path.to_owned())),
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists => Ok(None),
+            Err(e) => Err(e),
+        },
+        OverwritePolicy::Fail => hard_link(temp_file_path, file_path)
+            .map(|_| Some(file_path.to_owned()))
+            .map_err(|e| already_exists_error(e, file_path)),
+        OverwritePolicy::SuffixWithCounter => candidate_paths(file_path)
+            .map(|path| hard_link(temp_file_path, &path).map(|_| path))
+            .find(|linked| !is_already_exists(linked))
+            .transpose(),
+        OverwritePolicy::Versioned => {
+            let _lock = VERSIONS_LOCK.lock().expect("versions lock is poisoned");
+            back_up_existing(file_path)?;
+            rename(temp_file_path, file_path)?;
+            Ok(Some(file_path.to_owned()))
+        }
+    }
+}
+
+/// link the existing file to the first absent backup path.
+/// returns false if the file does not exist.
+fn back_up_existing(file_path: &Path) -> IOResult<bool> {
+    (1..)
+        .map(|n| backup_path(file_path, n))
+        .map(|path| hard_link(file_path, path))
+        .find(|linked| !is_already_exists(linked))
+        .map_or(Ok(false), |linked| match linked {
+            Ok(()) => Ok(true),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        })
+}
+
+fn open_file(file_path: &Path, create_new: bool) -> IOResult<File> {
+    let mut options = OpenOptions::new();
+    if create_new {
+        options.write(true).create_new(true);
+    } else {
+        options.write(true).create(true).truncate(true);
+    }
+    options.open(file_path)
+}
+
+fn is_already_exists<T>(result: &IOResult<T>) -> bool {
+    result
+        .as_ref()
+        .is_err_and(|e| e.kind() == ErrorKind::AlreadyExists)
+}
+
+fn already_exists_error(e: IOError, file_path: &Path) -> IOError {
+    if e.kind() == ErrorKind::AlreadyExists {
+        IOError::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already exists", file_path.display()),
+        )
     } else {
-        return Err(FailedToWriteLocal("filepath name is empty".to_owned()));
+        e
+    }
+}
+
+fn create_parent_dir(file_path: &Path) -> IOResult<()> {
+    match file_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => create_dir_all(dir),
+        _ => Ok(()),
     }
 }
 
+/// a rename is durable after the directory entry is flushed.
+fn sync_parent_dir(file_path: &Path) -> IOResult<()> {
+    match file_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+/// a temporary path in the same directory as the target, so that it can be renamed.
+/// it is unique among concurrent tasks writing to the same target.
+fn temp_path(file_path: &Path) -> PathBuf {
+    static TEMP_FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let mut file_name = OsString::from(".");
+    file_name.push(file_path.file_name().unwrap_or_default());
+    file_name.push(format!(
+        ".{}-{}.part",
+        process::id(),
+        TEMP_FILE_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    file_path.with_file_name(file_name)
+}
+
+/// `dir/page.html`, `dir/page_1.html`, `dir/page_2.html`, ...
+fn candidate_paths(file_path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    once(file_path.to_owned()).chain((1..).map(move |n| numbered_path(file_path, n)))
+}
+
+/// `dir/page.html` to `dir/page_{n}.html`.
+fn numbered_path(file_path: &Path, n: usize) -> PathBuf {
+    let mut file_name = file_path.file_stem().unwrap_or_default().to_owned();
+    file_name.push(format!("_{}", n));
+    if let Some(extension) = file_path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    file_path.with_file_name(file_name)
+}
+
+/// `dir/page.html` to `dir/page.html.~{n}~`.
+fn backup_path(file_path: &Path, n: usize) -> PathBuf {
+    let mut file_name = file_path.file_name().unwrap_or_default().to_owned();
+    file_name.push(format!(".~{}~", n));
+    file_path.with_file_name(file_name)
+}
+
 #[test]
 fn test_write_to_local() {
     use std::fs::File;
@@ -50,10 +433,14 @@ fn test_write_to_local() {
     let test_content = "testだよーん";
 
     // act
-    let result = write_to_local(test_file_path, test_content.as_bytes());
+    let result = write_to_local(
+        test_file_path,
+        test_content.as_bytes(),
+        &LocalFileOptions::default(),
+    );
 
     // assert
-    assert_eq!(Ok(()), result);
+    assert_eq!(Ok(Some(PathBuf::from(test_file_path))), result);
 
     let mut result_content: Vec<u8> = vec![];
     BufReader::new(File::open(test_file_path).unwrap())
@@ -61,3 +448,143 @@ fn test_write_to_local() {
         .unwrap();
     assert_eq!(test_content.as_bytes().to_owned(), result_content);
 }
+
+#[test]
+fn local_file_writer_test() {
+    use std::fs::{read, read_dir};
+
+    // arrange
+    let finished_path = "/var/tmp/application/stream/finished.txt";
+    let dropped_path = "/var/tmp/application/stream/dropped.txt";
+    let _ = remove_file(dropped_path);
+
+    // act
+    let mut finished =
+        Box::new(LocalFileWriter::create(finished_path, &LocalFileOptions::default()).unwrap());
+    finished.write_all(b"streamed ").unwrap();
+    finished.write_all(b"content").unwrap();
+    let finish_result = finished.finish();
+
+    let mut dropped = LocalFileWriter::create(dropped_path, &LocalFileOptions::default()).unwrap();
+    dropped.write_all(b"aborted").unwrap();
+    drop(dropped);
+
+    // assert
+    assert_eq!(
+        finish_result,
+        Ok(Placement::Written(finished_path.to_owned()))
+    );
+    assert_eq!(read(finished_path).unwrap(), b"streamed content");
+    assert!(!Path::new(dropped_path).exists());
+    let temp_files = read_dir("/var/tmp/application/stream")
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .ends_with(".part")
+        })
+        .count();
+    assert_eq!(temp_files, 0);
+}
+
+#[test]
+fn overwrite_policy_test() {
+    use std::fs::{read, read_dir, remove_dir_all};
+
+    let dir = "/var/tmp/application/overwrite";
+    let _ = remove_dir_all(dir);
+    let file_path = format!("{}/page.html", dir);
+    let write = |atomic: bool, overwrite: OverwritePolicy, content: &[u8]| {
+        let options = LocalFileOptions {
+            overwrite,
+            atomic,
+            mode: Some("640".to_owned()),
+            ..Default::default()
+        };
+        write_to_local(&file_path, content, &options)
+    };
+
+    for atomic in [true, false] {
+        // arrange
+        let _ = remove_dir_all(dir);
+        write(atomic, OverwritePolicy::Overwrite, b"first").unwrap();
+
+        // act & assert
+        assert_eq!(
+            write(atomic, OverwritePolicy::SkipIfExists, b"skipped"),
+            Ok(None)
+        );
+        assert_eq!(read(&file_path).unwrap(), b"first");
+
+        assert!(write(atomic, OverwritePolicy::Fail, b"failed").is_err());
+        assert_eq!(read(&file_path).unwrap(), b"first");
+
+        write(atomic, OverwritePolicy::SuffixWithCounter, b"second").unwrap();
+        write(atomic, OverwritePolicy::SuffixWithCounter, b"third").unwrap();
+        assert_eq!(read(format!("{}/page_1.html", dir)).unwrap(), b"second");
+        assert_eq!(read(format!("{}/page_2.html", dir)).unwrap(), b"third");
+
+        write(atomic, OverwritePolicy::Versioned, b"fourth").unwrap();
+        write(atomic, OverwritePolicy::Versioned, b"fifth").unwrap();
+        assert_eq!(read(&file_path).unwrap(), b"fifth");
+        assert_eq!(read(format!("{}/page.html.~1~", dir)).unwrap(), b"first");
+        assert_eq!(read(format!("{}/page.html.~2~", dir)).unwrap(), b"fourth");
+
+        write(atomic, OverwritePolicy::Overwrite, b"sixth").unwrap();
+        assert_eq!(read(&file_path).unwrap(), b"sixth");
+        let mode = File::open(&file_path)
+            .unwrap()
+            .metadata()
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o640);
+        assert_eq!(read_dir(dir).unwrap().count(), 5);
+    }
+}
+
+#[test]
+fn suffix_with_counter_test() {
+    use std::fs::{read, read_dir, remove_dir_all};
+
+    for atomic in [true, false] {
+        // arrange
+        let dir = format!("/var/tmp/application/suffix_with_counter/{}", atomic);
+        let _ = remove_dir_all(&dir);
+        let file_path = format!("{}/page.html", dir);
+        let options = LocalFileOptions {
+            overwrite: OverwritePolicy::SuffixWithCounter,
+            atomic,
+            ..Default::default()
+        };
+
+        // act
+        let first = write_to_local(&file_path, b"first", &options).unwrap();
+        let second = write_to_local(&file_path, b"second", &options).unwrap();
+
+        // assert
+        assert_eq!(first, Some(PathBuf::from(&file_path)));
+        assert_eq!(second, Some(PathBuf::from(format!("{}/page_1.html", dir))));
+        assert_eq!(read(&file_path).unwrap(), b"first");
+        assert_eq!(read(format!("{}/page_1.html", dir)).unwrap(), b"second");
+        assert_eq!(read_dir(&dir).unwrap().count(), 2);
+    }
+}
+
+#[test]
+fn local_file_options_test() {
+    let options = |mode: &str| LocalFileOptions {
+        mode: Some(mode.to_owned()),
+        ..Default::default()
+    };
+
+    assert_eq!(options("644").permission_bits(), Some(0o644));
+    assert_eq!(options("0750").permission_bits(), Some(0o750));
+    assert!(options("644").validate().is_empty());
+    assert_eq!(options("rw-r--r--").validate().len(), 1);
+    assert_eq!(options("17777").validate().len(), 1);
+    assert!(LocalFileOptions::default().validate().is_empty());
+}
@@ -1,9 +1,21 @@
+pub mod archive;
+pub mod async_persist;
+pub mod compression;
+pub mod content_addressed;
 pub mod error;
+pub mod json_lines;
 pub mod local_file;
 pub mod persist;
 pub mod persist_method;
+pub mod postgres;
+pub mod rotating_file;
 pub mod s3;
+pub mod sidecar;
+pub mod sqlite;
+pub mod stdout;
+pub mod warc;
 
+pub use self::async_persist::AsyncPersist;
 pub use self::error::PersistError;
-pub use self::persist::Persist;
+pub use self::persist::{Persist, PersistWriter, Placement};
 pub use self::persist_method::PersistMethod;
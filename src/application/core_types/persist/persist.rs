@@ -1,10 +1,41 @@
 use super::{
+    archive::write_to_archive,
+    content_addressed::persist_content_addressed,
     error::PersistError,
-    local_file::write_to_local,
+    json_lines::write_to_json_lines,
+    local_file::{write_to_local, LocalFileWriter},
     persist_method::PersistMethod,
-    s3::{write_to_s3, S3WriteFileRequest},
+    postgres::write_to_postgres,
+    s3::{write_to_s3, S3ClientConfig, S3MultipartWriter, S3WriteFileRequest},
+    sidecar::persist_with_sidecar,
+    sqlite::write_to_sqlite,
+    stdout::write_to_stdout,
+    warc::write_to_warc,
 };
+use crate::application::core_types::crawler::{CrawlerRequest, CrawlerResult};
 use crate::mime::Mime;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// where the data is placed by a persist method.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Placement {
+    /// written to the file path or the object key,
+    /// which differs from the target of the method by the overwrite policy of local files.
+    Written(String),
+
+    /// not written, since the target exists and is kept.
+    Skipped,
+}
+
+impl From<Option<PathBuf>> for Placement {
+    fn from(placed_path: Option<PathBuf>) -> Self {
+        match placed_path {
+            Some(path) => Placement::Written(path.to_string_lossy().into_owned()),
+            None => Placement::Skipped,
+        }
+    }
+}
 
 pub trait Persist {
     fn persist_data(
@@ -12,36 +43,207 @@ pub trait Persist {
         method: &PersistMethod,
         data: &[u8],
         mime: &Mime,
-    ) -> Result<(), PersistError> {
+    ) -> Result<Placement, PersistError> {
         persist_default_impl(method, data, mime)
     }
+
+    /// persist a response with the request which it is for.
+    /// persist methods which do not need the exchange persist the response body by persist_data,
+    /// followed by its metadata sidecar if requested.
+    fn persist_exchange(
+        &self,
+        method: &PersistMethod,
+        request: &CrawlerRequest,
+        result: &CrawlerResult,
+    ) -> Result<(), PersistError> {
+        if method.needs_exchange() {
+            write_exchange(method, request, result)
+        } else {
+            persist_with_sidecar(self, method, request, result)
+        }
+    }
+
+    /// open a writer to stream data to the persist method.
+    fn open_persist_writer(
+        &self,
+        method: &PersistMethod,
+        mime: &Mime,
+    ) -> Result<Box<dyn PersistWriter>, PersistError> {
+        open_persist_writer_default_impl(method, mime)
+    }
+}
+
+/// destination of streamed data.
+/// written data is discarded unless `finish` is called.
+pub trait PersistWriter: Write {
+    fn finish(self: Box<Self>) -> Result<Placement, PersistError>;
+}
+
+/// write the exchange to the persist method which needs the request and the response.
+pub fn write_exchange(
+    method: &PersistMethod,
+    request: &CrawlerRequest,
+    result: &CrawlerResult,
+) -> Result<(), PersistError> {
+    match method {
+        PersistMethod::Warc {
+            directory,
+            prefix,
+            max_file_size,
+        } => {
+            write_to_warc(directory, prefix, *max_file_size, request, result)?;
+            Ok(())
+        }
+        PersistMethod::JsonLines {
+            directory,
+            prefix,
+            max_file_size,
+            compression,
+        } => write_to_json_lines(
+            directory,
+            prefix,
+            *max_file_size,
+            compression.as_ref(),
+            request,
+            result,
+        ),
+        PersistMethod::Sqlite { path, table, key } => {
+            write_to_sqlite(path, table, key, &request.url, result)
+        }
+        PersistMethod::Postgres {
+            url,
+            table,
+            key,
+            batch_size,
+            max_connections,
+            max_retry,
+        } => write_to_postgres(
+            url,
+            table,
+            key,
+            *batch_size,
+            *max_connections,
+            *max_retry,
+            request,
+            result,
+        ),
+        PersistMethod::Archive {
+            path,
+            entry_name,
+            format,
+            metadata,
+        } => {
+            write_to_archive(path, *format, entry_name, *metadata, request, result)?;
+            Ok(())
+        }
+        PersistMethod::Stdout { format, delimiter } => {
+            write_to_stdout(*format, delimiter, request, result)?;
+            Ok(())
+        }
+        PersistMethod::LocalFile { .. } | PersistMethod::AmazonS3 { .. } => Err(method
+            .to_error("only the body is persisted, not the request and the response".to_owned())),
+    }
 }
 
 pub fn persist_default_impl(
     method: &PersistMethod,
     content: &[u8],
     mime: &Mime,
-) -> Result<(), PersistError> {
+) -> Result<Placement, PersistError> {
+    if let Some(addressing) = method.get_content_addressing() {
+        return persist_content_addressed(method, addressing, content, mime);
+    }
+    let compression = method.get_compression();
+    let compressed = compression.map(|c| c.compress(content)).transpose()?;
+    let content = compressed.as_ref().map_or(content, |c| c.as_slice());
+    let target_file_name = method.get_target_file_name();
+
     match method {
-        PersistMethod::LocalFile { file_path } => {
-            write_to_local(&file_path, content)?;
-            Ok(())
+        PersistMethod::LocalFile { file_options, .. } => {
+            Ok(write_to_local(&target_file_name, content, file_options)?.into())
         }
 
         PersistMethod::AmazonS3 {
             region,
             bucket_name,
-            object_key,
+            endpoint,
+            credentials,
+            object_options,
+            upload,
+            ..
         } => {
             let s3_request = S3WriteFileRequest {
-                region: region.to_owned(),
+                client_config: S3ClientConfig::new(region, endpoint, credentials),
                 bucket_name: bucket_name.to_owned(),
-                object_key: object_key.to_owned(),
+                object_key: target_file_name.clone(),
+                object_options,
+                upload_setting: upload,
                 content_type: mime,
+                content_encoding: compression.map(|c| c.content_encoding()),
                 content,
             };
             write_to_s3(s3_request)?;
-            Ok(())
+            Ok(Placement::Written(target_file_name))
         }
+
+        PersistMethod::Warc { .. }
+        | PersistMethod::JsonLines { .. }
+        | PersistMethod::Sqlite { .. }
+        | PersistMethod::Postgres { .. }
+        | PersistMethod::Archive { .. }
+        | PersistMethod::Stdout { .. } => Err(exchange_required(method)),
     }
 }
+
+pub fn open_persist_writer_default_impl(
+    method: &PersistMethod,
+    mime: &Mime,
+) -> Result<Box<dyn PersistWriter>, PersistError> {
+    // the blob is named by the hash of the whole content, which is not known until streamed.
+    if method.get_content_addressing().is_some() {
+        return Err(method
+            .to_error("content_addressed output is not supported with stream_body".to_owned()));
+    }
+    let compression = method.get_compression();
+    let target_file_name = method.get_target_file_name();
+
+    let writer: Box<dyn PersistWriter> = match method {
+        PersistMethod::LocalFile { file_options, .. } => {
+            Box::new(LocalFileWriter::create(&target_file_name, file_options)?)
+        }
+
+        PersistMethod::AmazonS3 {
+            region,
+            bucket_name,
+            endpoint,
+            credentials,
+            object_options,
+            upload,
+            ..
+        } => Box::new(S3MultipartWriter::new(
+            &S3ClientConfig::new(region, endpoint, credentials),
+            bucket_name,
+            &target_file_name,
+            object_options,
+            upload,
+            mime,
+            compression.map(|c| c.content_encoding()),
+        )?),
+
+        PersistMethod::Warc { .. }
+        | PersistMethod::JsonLines { .. }
+        | PersistMethod::Sqlite { .. }
+        | PersistMethod::Postgres { .. }
+        | PersistMethod::Archive { .. }
+        | PersistMethod::Stdout { .. } => return Err(exchange_required(method)),
+    };
+
+    match compression {
+        Some(compression) => compression.writer(writer),
+        None => Ok(writer),
+    }
+}
+
+pub fn exchange_required(method: &PersistMethod) -> PersistError {
+    method.to_error("the request and the response are required, not only the body".to_owned())
+}
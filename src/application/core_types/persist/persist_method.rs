@@ -1,35 +1,344 @@
+use super::{
+    archive::ArchiveFormat,
+    compression::OutputCompression,
+    content_addressed::ContentAddressing,
+    local_file::LocalFileOptions,
+    postgres::serialize_without_password,
+    s3::{S3ClientConfig, S3Credentials, S3ObjectOptions, S3UploadSetting},
+    sidecar::MetadataSidecar,
+    stdout::StdoutFormat,
+    PersistError,
+};
 use serde_derive::*;
 
 /// this module supplies how to persist crawled data.
 
 #[derive(Debug, Deserialize, PartialEq, Clone, Eq, Hash, Serialize)]
 #[serde(rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
 pub enum PersistMethod {
     /// write to local file
-    LocalFile { file_path: String },
+    LocalFile {
+        file_path: String,
 
-    /// write to amazon s3
+        #[serde(flatten)]
+        file_options: LocalFileOptions,
+
+        #[serde(default)]
+        compression: Option<OutputCompression>,
+
+        #[serde(default)]
+        sidecar: Option<MetadataSidecar>,
+
+        #[serde(default)]
+        content_addressed: Option<ContentAddressing>,
+    },
+
+    /// write to amazon s3, or a s3 compatible storage if endpoint is given.
     AmazonS3 {
         region: String,
         bucket_name: String,
         object_key: String,
+
+        #[serde(default)]
+        endpoint: Option<String>,
+
+        #[serde(default)]
+        credentials: Option<S3Credentials>,
+
+        #[serde(flatten)]
+        object_options: S3ObjectOptions,
+
+        #[serde(default)]
+        upload: S3UploadSetting,
+
+        #[serde(default)]
+        compression: Option<OutputCompression>,
+
+        #[serde(default)]
+        sidecar: Option<MetadataSidecar>,
+
+        #[serde(default)]
+        content_addressed: Option<ContentAddressing>,
+    },
+
+    /// write request and response records to rotating warc files in the directory.
+    /// the request record is reconstructed, since the http client does not expose the sent request.
+    /// it has the headers of the final hop, without the default User-Agent and Accept of the client.
+    Warc {
+        directory: String,
+
+        #[serde(default = "default_prefix")]
+        prefix: String,
+
+        #[serde(default = "default_max_file_size")]
+        max_file_size: u64,
+    },
+
+    /// append a json record of each request and response to rotating json lines files in the directory.
+    JsonLines {
+        directory: String,
+
+        #[serde(default = "default_prefix")]
+        prefix: String,
+
+        #[serde(default = "default_max_file_size")]
+        max_file_size: u64,
+
+        #[serde(default)]
+        compression: Option<OutputCompression>,
+    },
+
+    /// insert a row of each response into the table of the sqlite database file.
+    /// a row with the same key is replaced.
+    Sqlite {
+        path: String,
+        table: String,
+
+        #[serde(default = "default_key")]
+        key: String,
     },
+
+    /// upsert a row of each response into the table of the postgres database.
+    /// rows of concurrent tasks are inserted in batches of batch_size at most.
+    /// the password in url is removed when the method is serialized.
+    Postgres {
+        #[serde(serialize_with = "serialize_without_password")]
+        url: String,
+        table: String,
+
+        #[serde(default = "default_key")]
+        key: String,
+
+        #[serde(default = "default_batch_size")]
+        batch_size: usize,
+
+        #[serde(default = "default_max_connections")]
+        max_connections: usize,
+
+        #[serde(default = "default_max_retry")]
+        max_retry: u8,
+    },
+
+    /// append the body of each response to a tar or zip archive of the run,
+    /// as an entry named by entry_name. the archive is completed when the run finishes.
+    Archive {
+        path: String,
+        entry_name: String,
+
+        #[serde(default = "default_archive_format")]
+        format: ArchiveFormat,
+
+        /// also append `{entry_name}.meta.json` entries with the request and the response.
+        #[serde(default)]
+        metadata: bool,
+    },
+
+    /// write the body or a json lines record of each response to the standard output,
+    /// to pipe crawled data into other tools.
+    Stdout {
+        #[serde(default)]
+        format: StdoutFormat,
+
+        /// written after each body of raw format.
+        #[serde(default = "default_delimiter")]
+        delimiter: String,
+    },
+}
+
+fn default_delimiter() -> String {
+    "\n".to_owned()
+}
+
+fn default_archive_format() -> ArchiveFormat {
+    ArchiveFormat::TarGz
+}
+
+fn default_key() -> String {
+    "{{final_url}}".to_owned()
+}
+
+fn default_batch_size() -> usize {
+    100
+}
+
+fn default_max_connections() -> usize {
+    4
+}
+
+fn default_max_retry() -> u8 {
+    3
+}
+
+fn default_prefix() -> String {
+    "raven".to_owned()
+}
+
+fn default_max_file_size() -> u64 {
+    1024 * 1024 * 1024
 }
 
 impl PersistMethod {
     pub fn update_file_path(&mut self, new_file_path: String) {
         match self {
-            PersistMethod::LocalFile { file_path } => *file_path = new_file_path,
+            PersistMethod::LocalFile { file_path, .. } => *file_path = new_file_path,
             PersistMethod::AmazonS3 {
                 ref mut object_key, ..
             } => *object_key = new_file_path,
+            PersistMethod::Warc { directory, .. } => *directory = new_file_path,
+            PersistMethod::JsonLines { directory, .. } => *directory = new_file_path,
+            PersistMethod::Sqlite { key, .. } => *key = new_file_path,
+            PersistMethod::Postgres { key, .. } => *key = new_file_path,
+            PersistMethod::Archive { entry_name, .. } => *entry_name = new_file_path,
+            PersistMethod::Stdout { .. } => {}
         };
     }
 
     pub fn get_file_name(&self) -> &str {
         match self {
-            PersistMethod::LocalFile { file_path } => file_path,
+            PersistMethod::LocalFile { file_path, .. } => file_path,
             PersistMethod::AmazonS3 { object_key, .. } => object_key,
+            PersistMethod::Warc { directory, .. } => directory,
+            PersistMethod::JsonLines { directory, .. } => directory,
+            PersistMethod::Sqlite { key, .. } => key,
+            PersistMethod::Postgres { key, .. } => key,
+            PersistMethod::Archive { entry_name, .. } => entry_name,
+            // stdout has no file to template, and is named "-" as by command line tools.
+            PersistMethod::Stdout { .. } => "-",
+        }
+    }
+
+    /// values templated like the file name, other than the file name.
+    pub fn get_templated_values(&self) -> Vec<&String> {
+        match self {
+            PersistMethod::AmazonS3 { object_options, .. } => object_options.templated_values(),
+            PersistMethod::Archive { path, .. } => vec![path],
+            _ => vec![],
+        }
+    }
+
+    pub fn get_templated_values_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            PersistMethod::AmazonS3 { object_options, .. } => object_options.templated_values_mut(),
+            PersistMethod::Archive { path, .. } => vec![path],
+            _ => vec![],
+        }
+    }
+
+    pub fn get_compression(&self) -> Option<&OutputCompression> {
+        match self {
+            PersistMethod::LocalFile { compression, .. } => compression.as_ref(),
+            PersistMethod::AmazonS3 { compression, .. } => compression.as_ref(),
+            PersistMethod::Warc { .. }
+            | PersistMethod::Sqlite { .. }
+            | PersistMethod::Postgres { .. }
+            | PersistMethod::Archive { .. }
+            | PersistMethod::Stdout { .. } => None,
+            PersistMethod::JsonLines { compression, .. } => compression.as_ref(),
+        }
+    }
+
+    pub fn get_sidecar(&self) -> Option<&MetadataSidecar> {
+        match self {
+            PersistMethod::LocalFile { sidecar, .. } => sidecar.as_ref(),
+            PersistMethod::AmazonS3 { sidecar, .. } => sidecar.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn get_content_addressing(&self) -> Option<&ContentAddressing> {
+        match self {
+            PersistMethod::LocalFile {
+                content_addressed, ..
+            } => content_addressed.as_ref(),
+            PersistMethod::AmazonS3 {
+                content_addressed, ..
+            } => content_addressed.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// the persist method which writes the json sidecar next to the path where the body is placed,
+    /// if the sidecar is requested.
+    pub fn get_sidecar_method(&self, placed_path: &str) -> Option<PersistMethod> {
+        if self.get_sidecar() != Some(&MetadataSidecar::Json) {
+            return None;
+        }
+        let mut sidecar_method = self.clone();
+        sidecar_method.update_file_path(format!("{}.meta.json", placed_path));
+        match &mut sidecar_method {
+            PersistMethod::LocalFile {
+                compression,
+                sidecar,
+                content_addressed,
+                ..
+            } => {
+                *compression = None;
+                *sidecar = None;
+                *content_addressed = None;
+            }
+            PersistMethod::AmazonS3 {
+                compression,
+                sidecar,
+                content_addressed,
+                ..
+            } => {
+                *compression = None;
+                *sidecar = None;
+                *content_addressed = None;
+            }
+            _ => {}
+        }
+        Some(sidecar_method)
+    }
+
+    /// how to connect to s3, if the persist method writes to s3.
+    pub fn get_s3_client_config(&self) -> Option<S3ClientConfig> {
+        match self {
+            PersistMethod::AmazonS3 {
+                region,
+                endpoint,
+                credentials,
+                ..
+            } => Some(S3ClientConfig::new(region, endpoint, credentials)),
+            _ => None,
+        }
+    }
+
+    /// true if the persist method needs the request and the response, not only the body.
+    /// such methods cannot be used with stream_body.
+    pub fn needs_exchange(&self) -> bool {
+        match self {
+            PersistMethod::Warc { .. }
+            | PersistMethod::JsonLines { .. }
+            | PersistMethod::Sqlite { .. }
+            | PersistMethod::Postgres { .. }
+            | PersistMethod::Archive { .. }
+            | PersistMethod::Stdout { .. } => true,
+            PersistMethod::LocalFile { .. } | PersistMethod::AmazonS3 { .. } => false,
+        }
+    }
+
+    pub fn to_error(&self, detail: String) -> PersistError {
+        match self {
+            PersistMethod::LocalFile { .. } => PersistError::FailedToWriteLocalFile(detail),
+            PersistMethod::AmazonS3 { .. } => PersistError::FailedToPutToS3(detail),
+            PersistMethod::Warc { .. } => PersistError::FailedToWriteWarc(detail),
+            PersistMethod::JsonLines { .. } => PersistError::FailedToWriteJsonLines(detail),
+            PersistMethod::Sqlite { .. } => PersistError::FailedToWriteSqlite(detail),
+            PersistMethod::Postgres { .. } => PersistError::FailedToWritePostgres(detail),
+            PersistMethod::Archive { .. } => PersistError::FailedToWriteArchive(detail),
+            PersistMethod::Stdout { .. } => PersistError::FailedToWriteStdout(detail),
+        }
+    }
+
+    /// file name which data is written to, with the extension of compression if requested.
+    /// a content addressed output compresses the blob, not the manifest written to the file.
+    pub fn get_target_file_name(&self) -> String {
+        match self.get_compression() {
+            Some(_) if self.get_content_addressing().is_some() => self.get_file_name().to_owned(),
+            Some(compression) => compression.file_name(self.get_file_name()),
+            None => self.get_file_name().to_owned(),
         }
     }
 }
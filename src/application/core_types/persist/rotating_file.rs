@@ -0,0 +1,98 @@
+use crate::hostname::get_hostname;
+use chrono::Utc;
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{Error as IOError, Result as IOResult, Write};
+
+/// files named `{prefix}-{timestamp}-{serial}-{hostname}{extension}` in a directory.
+/// a new file is started when the current one reaches max_file_size,
+/// so a file can exceed it by the last write.
+pub struct RotatingFile {
+    directory: String,
+    prefix: String,
+    extension: String,
+    max_file_size: u64,
+    serial: u32,
+    current: Option<(File, u64)>,
+}
+
+impl RotatingFile {
+    pub fn new(directory: &str, prefix: &str, extension: &str, max_file_size: u64) -> RotatingFile {
+        RotatingFile {
+            directory: directory.to_owned(),
+            prefix: prefix.to_owned(),
+            extension: extension.to_owned(),
+            max_file_size,
+            serial: 0,
+            current: None,
+        }
+    }
+
+    /// start a new file if no file is opened or the current one is full.
+    /// returns the name of the new file.
+    pub fn rotate_if_full(&mut self) -> IOResult<Option<String>> {
+        if !self.is_full() {
+            return Ok(None);
+        }
+
+        create_dir_all(&self.directory)?;
+        let file_name = format!(
+            "{}-{}-{:05}-{}{}",
+            self.prefix,
+            Utc::now().format("%Y%m%d%H%M%S%3f"),
+            self.serial,
+            get_hostname(),
+            self.extension
+        );
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(format!("{}/{}", self.directory, file_name))?;
+
+        self.serial += 1;
+        self.current = Some((file, 0));
+        Ok(Some(file_name))
+    }
+
+    /// whether a new file is started by the next rotate_if_full.
+    pub fn is_full(&self) -> bool {
+        self.current
+            .as_ref()
+            .is_none_or(|(_, size)| *size >= self.max_file_size)
+    }
+
+    /// append bytes to the current file. rotate_if_full must be called before.
+    pub fn write_all(&mut self, bytes: &[u8]) -> IOResult<()> {
+        let (file, size) = self
+            .current
+            .as_mut()
+            .ok_or_else(|| IOError::other("no file is opened"))?;
+        file.write_all(bytes)?;
+        file.flush()?;
+        *size += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+#[test]
+fn rotating_file_test() {
+    use std::fs::{read_dir, remove_dir_all};
+
+    // arrange
+    let directory = "/var/tmp/application/rotating_file";
+    let _ = remove_dir_all(directory);
+    let mut rotating_file = RotatingFile::new(directory, "test", ".txt", 5);
+
+    // act
+    let first = rotating_file.rotate_if_full().unwrap();
+    rotating_file.write_all(b"abc").unwrap();
+    let not_full = rotating_file.rotate_if_full().unwrap();
+    rotating_file.write_all(b"def").unwrap();
+    let second = rotating_file.rotate_if_full().unwrap();
+    rotating_file.write_all(b"ghi").unwrap();
+
+    // assert
+    assert!(first.unwrap().starts_with("test-"));
+    assert_eq!(not_full, None);
+    assert!(second.unwrap().ends_with(".txt"));
+    assert_eq!(read_dir(directory).unwrap().count(), 2);
+}
@@ -1,67 +1,910 @@
+use std::collections::{BTreeMap, HashMap};
 use std::default::Default;
+use std::fmt::Display;
+use std::io::{Error as IOError, Result as IOResult, Write};
+use std::mem::replace;
 use std::str::FromStr;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 
-use rusoto_core::ByteStream;
+use futures::future::{self, Future, Loop};
+use openssl::hash::{hash, MessageDigest};
+use tokio::timer::Delay;
+
+use rusoto_core::credential::{ProfileProvider, StaticProvider};
 pub use rusoto_core::Region;
-use rusoto_s3::{PutObjectError, PutObjectOutput, PutObjectRequest, S3Client, S3};
+use rusoto_core::{ByteStream, HttpClient, RusotoFuture};
+use rusoto_s3::{
+    AbortMultipartUploadRequest, CompleteMultipartUploadError, CompleteMultipartUploadRequest,
+    CompletedMultipartUpload, CompletedPart, CreateMultipartUploadError,
+    CreateMultipartUploadRequest, HeadObjectError, HeadObjectRequest, PutObjectError,
+    PutObjectRequest, S3Client, UploadPartError, UploadPartRequest, S3,
+};
+
+use serde_derive::{Deserialize, Serialize};
 
-use super::PersistError;
+use super::{PersistError, PersistWriter, Placement};
 use crate::mime::Mime;
+use crate::url_encoding::encode_unreserved;
 
-pub fn write_to_s3(request: S3WriteFileRequest) -> Result<(), S3WriterError> {
-    let typed_region = match Region::from_str(&request.region) {
-        Ok(parsed) => parsed,
-        Err(parse_error) => return Err(S3WriterError(parse_error.to_string())),
-    };
-    let client = S3Client::new(typed_region);
-    let mut retry_count = 5;
-    loop {
-        let request = request.to_put_object_request();
+/// where and as whom to connect, to amazon s3 or a s3 compatible storage such as minio, ceph or localstack.
+/// objects are always addressed in path style (`{endpoint}/{bucket}/{key}`),
+/// which s3 compatible storages accept without wildcard dns for buckets.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct S3ClientConfig {
+    pub region: String,
+    /// url such as `http://localhost:9000`. the endpoint of aws for the region is used if None.
+    pub endpoint: Option<String>,
+    /// the default credentials chain of aws (environment variables, profile and instance metadata) is used if None.
+    pub credentials: Option<S3Credentials>,
+}
 
-        let result: Result<PutObjectOutput, PutObjectError> = client
-            .put_object(request)
-            .with_timeout(Duration::from_secs(10))
-            .sync();
-
-        match result {
-            Ok(_) => return Ok(()),
-            Err(e) => {
-                if let PutObjectError::HttpDispatch(_) = e {
-                    if retry_count == 0 {
-                        return Err(S3WriterError(e.to_string()));
-                    } else {
-                        retry_count -= 1;
-                        continue;
+#[derive(Debug, Deserialize, PartialEq, Clone, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum S3Credentials {
+    /// a profile of the aws credentials file, `~/.aws/credentials` unless file_path is given.
+    Profile {
+        name: String,
+
+        #[serde(default)]
+        file_path: Option<String>,
+    },
+
+    /// keys written in the config. the secret and the token are never serialized,
+    /// so they are not written to metrics or sidecars.
+    Static {
+        access_key_id: String,
+
+        #[serde(skip_serializing)]
+        secret_access_key: String,
+
+        #[serde(default, skip_serializing)]
+        session_token: Option<String>,
+    },
+}
+
+impl S3ClientConfig {
+    pub fn new(
+        region: &str,
+        endpoint: &Option<String>,
+        credentials: &Option<S3Credentials>,
+    ) -> S3ClientConfig {
+        S3ClientConfig {
+            region: region.to_owned(),
+            endpoint: endpoint.to_owned(),
+            credentials: credentials.to_owned(),
+        }
+    }
+
+    pub fn to_region(&self) -> Result<Region, S3WriterError> {
+        match &self.endpoint {
+            Some(endpoint) => Ok(Region::Custom {
+                name: self.region.to_owned(),
+                endpoint: endpoint.trim_end_matches('/').to_owned(),
+            }),
+            None => Region::from_str(&self.region)
+                .map_err(|parse_error| S3WriterError(parse_error.to_string())),
+        }
+    }
+
+    /// a client shared by all uploads with the same config, so that connections are reused.
+    pub fn get_client(&self) -> Result<Arc<S3Client>, S3WriterError> {
+        lazy_static! {
+            static ref CLIENTS: Mutex<HashMap<S3ClientConfig, Arc<S3Client>>> =
+                Mutex::new(HashMap::new());
+        }
+
+        let mut clients = CLIENTS.lock().expect("s3 clients lock is poisoned");
+        if let Some(client) = clients.get(self) {
+            return Ok(client.clone());
+        }
+        let client = Arc::new(self.create_client()?);
+        clients.insert(self.clone(), client.clone());
+        Ok(client)
+    }
+
+    pub fn create_client(&self) -> Result<S3Client, S3WriterError> {
+        let region = self.to_region()?;
+        let credentials = match &self.credentials {
+            Some(credentials) => credentials,
+            None => return Ok(S3Client::new(region)),
+        };
+
+        let dispatcher = HttpClient::new().map_err(|e| S3WriterError(e.to_string()))?;
+        let client = match credentials {
+            S3Credentials::Profile { name, file_path } => {
+                let provider = match file_path {
+                    Some(file_path) => ProfileProvider::with_configuration(file_path, name),
+                    None => {
+                        let mut provider =
+                            ProfileProvider::new().map_err(|e| S3WriterError(e.to_string()))?;
+                        provider.set_profile(name.to_owned());
+                        provider
+                    }
+                };
+                S3Client::new_with(dispatcher, provider, region)
+            }
+            S3Credentials::Static {
+                access_key_id,
+                secret_access_key,
+                session_token,
+            } => S3Client::new_with(
+                dispatcher,
+                StaticProvider::new(
+                    access_key_id.to_owned(),
+                    secret_access_key.to_owned(),
+                    session_token.to_owned(),
+                    None,
+                ),
+                region,
+            ),
+        };
+        Ok(client)
+    }
+
+    /// errors of the endpoint url, which rusoto does not validate.
+    pub fn validate(&self) -> Vec<String> {
+        match &self.endpoint {
+            Some(endpoint)
+                if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") =>
+            {
+                vec![format!(
+                    "s3 endpoint must start with http:// or https://: {}",
+                    endpoint
+                )]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// how an object is stored, other than its content.
+/// tag and metadata values are templated like the object key.
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Eq, Hash, Serialize)]
+pub struct S3ObjectOptions {
+    #[serde(default)]
+    pub storage_class: Option<S3StorageClass>,
+
+    /// canned acl of the object.
+    #[serde(default)]
+    pub acl: Option<S3Acl>,
+
+    #[serde(default)]
+    pub server_side_encryption: Option<S3ServerSideEncryption>,
+
+    /// s3 accepts 10 tags at most.
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+
+    /// user metadata, sent as `x-amz-meta-{name}` headers.
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy, Eq, Hash, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum S3StorageClass {
+    Standard,
+    ReducedRedundancy,
+    StandardIa,
+    OnezoneIa,
+    IntelligentTiering,
+    Glacier,
+    DeepArchive,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum S3Acl {
+    Private,
+    PublicRead,
+    PublicReadWrite,
+    AuthenticatedRead,
+    AwsExecRead,
+    BucketOwnerRead,
+    BucketOwnerFullControl,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum S3ServerSideEncryption {
+    /// SSE-S3, with keys managed by s3.
+    Aes256,
+
+    /// SSE-KMS, with the kms key of key_id or the aws managed key if None.
+    AwsKms {
+        #[serde(default)]
+        key_id: Option<String>,
+    },
+}
+
+impl S3StorageClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            S3StorageClass::Standard => "STANDARD",
+            S3StorageClass::ReducedRedundancy => "REDUCED_REDUNDANCY",
+            S3StorageClass::StandardIa => "STANDARD_IA",
+            S3StorageClass::OnezoneIa => "ONEZONE_IA",
+            S3StorageClass::IntelligentTiering => "INTELLIGENT_TIERING",
+            S3StorageClass::Glacier => "GLACIER",
+            S3StorageClass::DeepArchive => "DEEP_ARCHIVE",
+        }
+    }
+}
+
+impl S3Acl {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            S3Acl::Private => "private",
+            S3Acl::PublicRead => "public-read",
+            S3Acl::PublicReadWrite => "public-read-write",
+            S3Acl::AuthenticatedRead => "authenticated-read",
+            S3Acl::AwsExecRead => "aws-exec-read",
+            S3Acl::BucketOwnerRead => "bucket-owner-read",
+            S3Acl::BucketOwnerFullControl => "bucket-owner-full-control",
+        }
+    }
+}
+
+impl S3ObjectOptions {
+    pub fn storage_class(&self) -> Option<String> {
+        self.storage_class.map(|class| class.as_str().to_owned())
+    }
+
+    pub fn acl(&self) -> Option<String> {
+        self.acl.map(|acl| acl.as_str().to_owned())
+    }
+
+    /// the value of `x-amz-server-side-encryption` header.
+    pub fn server_side_encryption(&self) -> Option<String> {
+        self.server_side_encryption
+            .as_ref()
+            .map(|encryption| match encryption {
+                S3ServerSideEncryption::Aes256 => "AES256".to_owned(),
+                S3ServerSideEncryption::AwsKms { .. } => "aws:kms".to_owned(),
+            })
+    }
+
+    pub fn ssekms_key_id(&self) -> Option<String> {
+        match &self.server_side_encryption {
+            Some(S3ServerSideEncryption::AwsKms { key_id }) => key_id.to_owned(),
+            _ => None,
+        }
+    }
+
+    /// tags encoded as url query parameters, the value of `x-amz-tagging` header.
+    pub fn tagging(&self) -> Option<String> {
+        if self.tags.is_empty() {
+            return None;
+        }
+        let tagging: Vec<String> = self
+            .tags
+            .iter()
+            .map(|(key, value)| format!("{}={}", encode_unreserved(key), encode_unreserved(value)))
+            .collect();
+        Some(tagging.join("&"))
+    }
+
+    pub fn metadata(&self) -> Option<HashMap<String, String>> {
+        if self.metadata.is_empty() {
+            None
+        } else {
+            Some(self.metadata.clone().into_iter().collect())
+        }
+    }
+
+    /// values which may have template vars.
+    pub fn templated_values(&self) -> Vec<&String> {
+        self.tags.values().chain(self.metadata.values()).collect()
+    }
+
+    pub fn templated_values_mut(&mut self) -> Vec<&mut String> {
+        self.tags
+            .values_mut()
+            .chain(self.metadata.values_mut())
+            .collect()
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = vec![];
+        if self.tags.len() > 10 {
+            errors.push(format!(
+                "s3 accepts 10 tags at most, but {} tags are given",
+                self.tags.len()
+            ));
+        }
+        // metadata is sent as headers, which s3 accepts only in us-ascii.
+        let non_ascii_metadata = self
+            .metadata
+            .iter()
+            .filter(|(name, value)| !name.is_ascii() || !value.is_ascii());
+        for (name, _) in non_ascii_metadata {
+            errors.push(format!("s3 metadata must be ascii: {}", name));
+        }
+        errors
+    }
+}
+
+/// how objects are uploaded.
+#[derive(Debug, Deserialize, PartialEq, Clone, Eq, Hash, Serialize)]
+pub struct S3UploadSetting {
+    /// timeout of each request, except uploading parts of multipart upload.
+    #[serde(default = "default_timeout_in_seconds")]
+    pub timeout_in_seconds: u64,
+
+    #[serde(default = "default_part_timeout_in_seconds")]
+    pub part_timeout_in_seconds: u64,
+
+    /// retries of each request on network errors and 429 or 5xx responses.
+    #[serde(default = "default_max_retry")]
+    pub max_retry: u8,
+
+    /// wait before the first retry, which is doubled on each retry.
+    #[serde(default = "default_backoff_millis")]
+    pub backoff_millis: u64,
+
+    /// objects larger than this are uploaded by multipart upload.
+    #[serde(default = "default_multipart_threshold")]
+    pub multipart_threshold: usize,
+
+    /// s3 requires at least 5 MiB except the last part.
+    #[serde(default = "default_part_size")]
+    pub part_size: usize,
+
+    /// send Content-MD5 of the body, so that s3 rejects the body corrupted on the way.
+    #[serde(default = "default_verify_checksum")]
+    pub verify_checksum: bool,
+}
+
+fn default_timeout_in_seconds() -> u64 {
+    10
+}
+
+fn default_part_timeout_in_seconds() -> u64 {
+    60
+}
+
+fn default_max_retry() -> u8 {
+    5
+}
+
+fn default_backoff_millis() -> u64 {
+    100
+}
+
+fn default_multipart_threshold() -> usize {
+    16 * 1024 * 1024
+}
+
+fn default_part_size() -> usize {
+    8 * 1024 * 1024
+}
+
+fn default_verify_checksum() -> bool {
+    true
+}
+
+impl Default for S3UploadSetting {
+    fn default() -> Self {
+        S3UploadSetting {
+            timeout_in_seconds: default_timeout_in_seconds(),
+            part_timeout_in_seconds: default_part_timeout_in_seconds(),
+            max_retry: default_max_retry(),
+            backoff_millis: default_backoff_millis(),
+            multipart_threshold: default_multipart_threshold(),
+            part_size: default_part_size(),
+            verify_checksum: default_verify_checksum(),
+        }
+    }
+}
+
+impl S3UploadSetting {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_in_seconds)
+    }
+
+    pub fn part_timeout(&self) -> Duration {
+        Duration::from_secs(self.part_timeout_in_seconds)
+    }
+
+    /// wait before the retry of retry_count, starting from 0.
+    pub fn backoff(&self, retry_count: u8) -> Duration {
+        Duration::from_millis(
+            self.backoff_millis
+                .saturating_mul(1 << u32::from(retry_count.min(16))),
+        )
+    }
+
+    /// base64 encoded md5 of the content, if verify_checksum is enabled.
+    pub fn content_md5(&self, content: &[u8]) -> Result<Option<String>, S3WriterError> {
+        if !self.verify_checksum {
+            return Ok(None);
+        }
+        hash(MessageDigest::md5(), content)
+            .map(|digest| Some(base64::encode(&digest)))
+            .map_err(|e| S3WriterError(e.to_string()))
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = vec![];
+        if self.timeout_in_seconds == 0 || self.part_timeout_in_seconds == 0 {
+            errors.push("s3 upload timeout must be positive".to_owned());
+        }
+        if self.part_size < 5 * 1024 * 1024 {
+            errors.push("s3 upload part_size must be 5 MiB or more".to_owned());
+        }
+        errors
+    }
+}
+
+/// errors of s3 requests, which may succeed on retry if they are transient.
+trait S3RequestError: Display {
+    fn is_transient(&self) -> bool;
+}
+
+macro_rules! impl_s3_request_error {
+    ($($error:ident),*) => {
+        $(
+            impl S3RequestError for $error {
+                fn is_transient(&self) -> bool {
+                    match self {
+                        $error::HttpDispatch(_) => true,
+                        $error::Unknown(response) => {
+                            response.status.as_u16() == 429 || response.status.is_server_error()
+                        }
+                        _ => false,
                     }
-                } else {
-                    return Err(S3WriterError(e.to_string()));
                 }
             }
+        )*
+    };
+}
+
+impl_s3_request_error!(
+    HeadObjectError,
+    PutObjectError,
+    CreateMultipartUploadError,
+    UploadPartError,
+    CompleteMultipartUploadError
+);
+
+/// send a request, retrying it with exponential backoff on transient errors.
+fn send_with_retry<T, E, F>(
+    upload_setting: &S3UploadSetting,
+    timeout: Duration,
+    send: F,
+) -> Result<T, S3WriterError>
+where
+    T: Send + 'static,
+    E: S3RequestError + From<rusoto_core::CredentialsError> + From<rusoto_core::HttpDispatchError>,
+    E: Send + 'static,
+    F: FnMut() -> RusotoFuture<T, E>,
+{
+    try_send_with_retry(upload_setting, timeout, send).map_err(|e| S3WriterError(e.to_string()))
+}
+
+/// same as send_with_retry, but returns the error of the request as it is.
+fn try_send_with_retry<T, E, F>(
+    upload_setting: &S3UploadSetting,
+    timeout: Duration,
+    mut send: F,
+) -> Result<T, E>
+where
+    T: Send + 'static,
+    E: S3RequestError + From<rusoto_core::CredentialsError> + From<rusoto_core::HttpDispatchError>,
+    E: Send + 'static,
+    F: FnMut() -> RusotoFuture<T, E>,
+{
+    let mut retry_count = 0;
+    loop {
+        match send().with_timeout(timeout).sync() {
+            Ok(output) => return Ok(output),
+            Err(ref e) if retry_count < upload_setting.max_retry && e.is_transient() => {
+                warn!("failed to send a request to s3, retrying: {}", e);
+                sleep(upload_setting.backoff(retry_count));
+                retry_count += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// upload the content by a put request, or by multipart upload if it is larger than multipart_threshold.
+pub fn write_to_s3(request: S3WriteFileRequest) -> Result<(), S3WriterError> {
+    if request.content.len() > request.upload_setting.multipart_threshold {
+        let mut writer = S3MultipartWriter::new(
+            &request.client_config,
+            &request.bucket_name,
+            &request.object_key,
+            request.object_options,
+            request.upload_setting,
+            request.content_type,
+            request.content_encoding,
+        )?;
+        for part in request.content.chunks(request.upload_setting.part_size) {
+            writer.buffer.extend_from_slice(part);
+            writer.upload_part()?;
         }
+        writer.complete()?;
+        writer.finished = true;
+        return Ok(());
     }
+
+    let client = request.client_config.get_client()?;
+    put_object(&client, &request)
 }
 
+fn put_object(client: &S3Client, request: &S3WriteFileRequest) -> Result<(), S3WriterError> {
+    let content_md5 = request.upload_setting.content_md5(request.content)?;
+    send_with_retry(
+        request.upload_setting,
+        request.upload_setting.timeout(),
+        || {
+            let mut put_object_request = request.to_put_object_request();
+            put_object_request.content_md5 = content_md5.to_owned();
+            client.put_object(put_object_request)
+        },
+    )
+    .map(|_| ())
+}
+
+/// true if the object exists, checked by a head request.
+pub fn s3_object_exists(
+    client_config: &S3ClientConfig,
+    bucket_name: &str,
+    object_key: &str,
+    upload_setting: &S3UploadSetting,
+) -> Result<bool, S3WriterError> {
+    let client = client_config.get_client()?;
+    let head_object = try_send_with_retry(upload_setting, upload_setting.timeout(), || {
+        client.head_object(HeadObjectRequest {
+            bucket: bucket_name.to_owned(),
+            key: object_key.to_owned(),
+            ..Default::default()
+        })
+    });
+    match head_object {
+        Ok(_) => Ok(true),
+        // a head response has no body, so a missing object is usually an unknown 404.
+        Err(HeadObjectError::NoSuchKey(_)) => Ok(false),
+        Err(HeadObjectError::Unknown(ref response)) if response.status.as_u16() == 404 => Ok(false),
+        Err(e) => Err(S3WriterError(e.to_string())),
+    }
+}
+
+pub type S3WriteFuture = Box<dyn Future<Item = (), Error = S3WriterError> + Send>;
+
+/// non-blocking counterpart of write_to_s3.
+/// the returned future must be executed on a tokio runtime.
+/// content larger than multipart_threshold is uploaded by blocking write_to_s3,
+/// as a blocking section of the tokio thread pool.
+#[allow(clippy::too_many_arguments)]
+pub fn write_to_s3_async(
+    client_config: &S3ClientConfig,
+    bucket_name: &str,
+    object_key: &str,
+    object_options: &S3ObjectOptions,
+    upload_setting: &S3UploadSetting,
+    content_type: &Mime,
+    content_encoding: Option<&str>,
+    content: Arc<Vec<u8>>,
+) -> S3WriteFuture {
+    let client_config = client_config.clone();
+    let bucket_name = bucket_name.to_owned();
+    let object_key = object_key.to_owned();
+    let object_options = object_options.clone();
+    let upload_setting = upload_setting.clone();
+    let content_type = content_type.clone();
+    let content_encoding = content_encoding.map(|encoding| encoding.to_owned());
+
+    if content.len() > upload_setting.multipart_threshold {
+        return Box::new(
+            future::poll_fn(move || {
+                tokio_threadpool::blocking(|| {
+                    write_to_s3(S3WriteFileRequest {
+                        client_config: client_config.clone(),
+                        bucket_name: bucket_name.to_owned(),
+                        object_key: object_key.to_owned(),
+                        object_options: &object_options,
+                        upload_setting: &upload_setting,
+                        content_type: &content_type,
+                        content_encoding: content_encoding.as_deref(),
+                        content: &content,
+                    })
+                })
+            })
+            .map_err(|e| S3WriterError(e.to_string()))
+            .and_then(|result| result),
+        );
+    }
+
+    let client = match client_config.get_client() {
+        Ok(client) => client,
+        Err(e) => return Box::new(future::err(e)),
+    };
+    let content_md5 = match upload_setting.content_md5(&content) {
+        Ok(content_md5) => content_md5,
+        Err(e) => return Box::new(future::err(e)),
+    };
+
+    Box::new(future::loop_fn(0, move |retry_count: u8| {
+        let mut request = put_object_request(
+            &bucket_name,
+            &object_key,
+            &object_options,
+            &content_type,
+            content_encoding.as_deref(),
+            content.as_ref(),
+        );
+        request.content_md5 = content_md5.to_owned();
+        let upload_setting = upload_setting.clone();
+
+        client
+            .put_object(request)
+            .with_timeout(upload_setting.timeout())
+            .then(move |result| -> S3WriteLoopFuture {
+                match result {
+                    Ok(_) => Box::new(future::ok(Loop::Break(()))),
+                    Err(ref e) if retry_count < upload_setting.max_retry && e.is_transient() => {
+                        warn!("failed to send a request to s3, retrying: {}", e);
+                        Box::new(
+                            Delay::new(Instant::now() + upload_setting.backoff(retry_count))
+                                .map(move |_| Loop::Continue(retry_count + 1))
+                                .map_err(|e| S3WriterError(e.to_string())),
+                        )
+                    }
+                    Err(e) => Box::new(future::err(S3WriterError(e.to_string()))),
+                }
+            })
+    }))
+}
+
+type S3WriteLoopFuture = Box<dyn Future<Item = Loop<(), u8>, Error = S3WriterError> + Send>;
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct S3WriteFileRequest<'a> {
-    pub region: String,
+    pub client_config: S3ClientConfig,
     pub bucket_name: String,
     pub object_key: String,
+    pub object_options: &'a S3ObjectOptions,
+    pub upload_setting: &'a S3UploadSetting,
     pub content_type: &'a Mime,
+    /// Content-Encoding of the object, set when content is compressed.
+    pub content_encoding: Option<&'a str>,
     pub content: &'a [u8],
 }
 
 impl<'a> S3WriteFileRequest<'a> {
     fn to_put_object_request(&self) -> PutObjectRequest {
-        PutObjectRequest {
+        put_object_request(
+            &self.bucket_name,
+            &self.object_key,
+            self.object_options,
+            self.content_type,
+            self.content_encoding,
+            self.content,
+        )
+    }
+}
+
+fn put_object_request(
+    bucket_name: &str,
+    object_key: &str,
+    object_options: &S3ObjectOptions,
+    content_type: &Mime,
+    content_encoding: Option<&str>,
+    content: &[u8],
+) -> PutObjectRequest {
+    PutObjectRequest {
+        bucket: bucket_name.to_owned(),
+        key: object_key.to_owned(),
+        content_type: Some(content_type.to_string()),
+        content_encoding: content_encoding.map(|encoding| encoding.to_owned()),
+        body: Some(ByteStream::from(content.to_owned())),
+        storage_class: object_options.storage_class(),
+        acl: object_options.acl(),
+        server_side_encryption: object_options.server_side_encryption(),
+        ssekms_key_id: object_options.ssekms_key_id(),
+        tagging: object_options.tagging(),
+        metadata: object_options.metadata(),
+        ..Default::default()
+    }
+}
+
+/// uploads streamed data by multipart upload, without keeping whole data in memory.
+/// data smaller than a part is uploaded by a single put request when finished.
+/// the multipart upload is aborted if the writer is dropped before finished.
+pub struct S3MultipartWriter {
+    client: Arc<S3Client>,
+    client_config: S3ClientConfig,
+    bucket_name: String,
+    object_key: String,
+    object_options: S3ObjectOptions,
+    upload_setting: S3UploadSetting,
+    content_type: Mime,
+    content_encoding: Option<String>,
+    upload_id: Option<String>,
+    buffer: Vec<u8>,
+    completed_parts: Vec<CompletedPart>,
+    finished: bool,
+}
+
+impl S3MultipartWriter {
+    pub fn new(
+        client_config: &S3ClientConfig,
+        bucket_name: &str,
+        object_key: &str,
+        object_options: &S3ObjectOptions,
+        upload_setting: &S3UploadSetting,
+        content_type: &Mime,
+        content_encoding: Option<&str>,
+    ) -> Result<S3MultipartWriter, S3WriterError> {
+        Ok(S3MultipartWriter {
+            client: client_config.get_client()?,
+            client_config: client_config.clone(),
+            bucket_name: bucket_name.to_owned(),
+            object_key: object_key.to_owned(),
+            object_options: object_options.clone(),
+            upload_setting: upload_setting.clone(),
+            content_type: content_type.clone(),
+            content_encoding: content_encoding.map(|encoding| encoding.to_owned()),
+            upload_id: None,
+            buffer: Vec::with_capacity(upload_setting.part_size),
+            completed_parts: vec![],
+            finished: false,
+        })
+    }
+
+    fn upload_id(&mut self) -> Result<String, S3WriterError> {
+        if let Some(upload_id) = &self.upload_id {
+            return Ok(upload_id.to_owned());
+        }
+
+        let request = CreateMultipartUploadRequest {
             bucket: self.bucket_name.to_owned(),
             key: self.object_key.to_owned(),
             content_type: Some(self.content_type.to_string()),
-            body: Some(ByteStream::from(self.content.to_owned())),
+            content_encoding: self.content_encoding.to_owned(),
+            storage_class: self.object_options.storage_class(),
+            acl: self.object_options.acl(),
+            server_side_encryption: self.object_options.server_side_encryption(),
+            ssekms_key_id: self.object_options.ssekms_key_id(),
+            tagging: self.object_options.tagging(),
+            metadata: self.object_options.metadata(),
             ..Default::default()
+        };
+        let client = &self.client;
+        let upload_id =
+            send_with_retry(&self.upload_setting, self.upload_setting.timeout(), || {
+                client.create_multipart_upload(request.clone())
+            })?
+            .upload_id
+            .ok_or_else(|| S3WriterError("upload id is not returned".to_owned()))?;
+
+        self.upload_id = Some(upload_id.to_owned());
+        Ok(upload_id)
+    }
+
+    fn upload_part(&mut self) -> Result<(), S3WriterError> {
+        let upload_id = self.upload_id()?;
+        let part_number = self.completed_parts.len() as i64 + 1;
+        let body = replace(
+            &mut self.buffer,
+            Vec::with_capacity(self.upload_setting.part_size),
+        );
+        let content_md5 = self.upload_setting.content_md5(&body)?;
+
+        let client = &self.client;
+        let output = send_with_retry(
+            &self.upload_setting,
+            self.upload_setting.part_timeout(),
+            || {
+                client.upload_part(UploadPartRequest {
+                    bucket: self.bucket_name.to_owned(),
+                    key: self.object_key.to_owned(),
+                    upload_id: upload_id.to_owned(),
+                    part_number,
+                    content_length: Some(body.len() as i64),
+                    content_md5: content_md5.to_owned(),
+                    body: Some(ByteStream::from(body.clone())),
+                    ..Default::default()
+                })
+            },
+        )?;
+
+        self.completed_parts.push(CompletedPart {
+            e_tag: output.e_tag,
+            part_number: Some(part_number),
+        });
+        Ok(())
+    }
+
+    fn complete(&mut self) -> Result<(), S3WriterError> {
+        let upload_id = match &self.upload_id {
+            Some(upload_id) => upload_id.to_owned(),
+            None => {
+                let request = S3WriteFileRequest {
+                    client_config: self.client_config.clone(),
+                    bucket_name: self.bucket_name.to_owned(),
+                    object_key: self.object_key.to_owned(),
+                    object_options: &self.object_options,
+                    upload_setting: &self.upload_setting,
+                    content_type: &self.content_type,
+                    content_encoding: self.content_encoding.as_deref(),
+                    content: &self.buffer,
+                };
+                return put_object(&self.client, &request);
+            }
+        };
+
+        if !self.buffer.is_empty() {
+            self.upload_part()?;
+        }
+
+        let request = CompleteMultipartUploadRequest {
+            bucket: self.bucket_name.to_owned(),
+            key: self.object_key.to_owned(),
+            upload_id,
+            multipart_upload: Some(CompletedMultipartUpload {
+                parts: Some(self.completed_parts.clone()),
+            }),
+            ..Default::default()
+        };
+        let client = &self.client;
+        send_with_retry(&self.upload_setting, self.upload_setting.timeout(), || {
+            client.complete_multipart_upload(request.clone())
+        })
+        .map(|_| ())
+    }
+}
+
+impl Write for S3MultipartWriter {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= self.upload_setting.part_size {
+            self.upload_part().map_err(|e| IOError::other(e.0))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        Ok(())
+    }
+}
+
+impl PersistWriter for S3MultipartWriter {
+    fn finish(mut self: Box<Self>) -> Result<Placement, PersistError> {
+        self.complete()?;
+        self.finished = true;
+        Ok(Placement::Written(self.object_key.to_owned()))
+    }
+}
+
+impl Drop for S3MultipartWriter {
+    fn drop(&mut self) {
+        if let (Some(upload_id), false) = (&self.upload_id, self.finished) {
+            let request = AbortMultipartUploadRequest {
+                bucket: self.bucket_name.to_owned(),
+                key: self.object_key.to_owned(),
+                upload_id: upload_id.to_owned(),
+                ..Default::default()
+            };
+            let abort_result = self
+                .client
+                .abort_multipart_upload(request)
+                .with_timeout(self.upload_setting.timeout())
+                .sync();
+            if let Err(e) = abort_result {
+                warn!(
+                    "failed to abort multipart upload(s3://{}/{}): {}",
+                    self.bucket_name, self.object_key, e
+                );
+            }
         }
     }
 }
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct S3WriterError(pub String);
 
@@ -71,23 +914,287 @@ impl From<S3WriterError> for PersistError {
     }
 }
 
+#[test]
+fn s3_client_config_test() {
+    let aws = S3ClientConfig::new("ap-northeast-1", &None, &None);
+    let minio = S3ClientConfig::new(
+        "us-east-1",
+        &Some("http://localhost:9000/".to_owned()),
+        &None,
+    );
+    let invalid = S3ClientConfig::new("us-east-1", &Some("localhost:9000".to_owned()), &None);
+
+    assert_eq!(aws.to_region(), Ok(Region::ApNortheast1));
+    assert_eq!(
+        minio.to_region(),
+        Ok(Region::Custom {
+            name: "us-east-1".to_owned(),
+            endpoint: "http://localhost:9000".to_owned(),
+        })
+    );
+    assert!(S3ClientConfig::new("mars-1", &None, &None)
+        .to_region()
+        .is_err());
+    assert!(aws.validate().is_empty());
+    assert!(minio.validate().is_empty());
+    assert_eq!(invalid.validate().len(), 1);
+}
+
+#[test]
+fn s3_credentials_serialization_test() {
+    use super::PersistMethod;
+    use crate::application::core_types::crawler::CrawlerRequest;
+    use crate::application::raven_crawl_task::RavenCrawlTask;
+
+    // arrange
+    let task = RavenCrawlTask {
+        request: CrawlerRequest {
+            url: "https://www.craw.app/".to_owned(),
+            ..Default::default()
+        },
+        persist_methods: vec![PersistMethod::AmazonS3 {
+            region: "ap-northeast-1".to_owned(),
+            bucket_name: "raven".to_owned(),
+            object_key: "index.html".to_owned(),
+            endpoint: None,
+            credentials: Some(S3Credentials::Static {
+                access_key_id: "AKIARAVEN".to_owned(),
+                secret_access_key: "raven-secret-access-key".to_owned(),
+                session_token: Some("raven-session-token".to_owned()),
+            }),
+            object_options: Default::default(),
+            upload: Default::default(),
+            compression: None,
+            sidecar: None,
+            content_addressed: None,
+        }],
+    };
+
+    // act
+    let serialized = serde_json::to_string(&task).unwrap();
+
+    // assert
+    assert!(serialized.contains("AKIARAVEN"));
+    assert!(!serialized.contains("raven-secret-access-key"));
+    assert!(!serialized.contains("raven-session-token"));
+}
+
+#[test]
+fn s3_object_options_test() {
+    let object_options = S3ObjectOptions {
+        server_side_encryption: Some(S3ServerSideEncryption::AwsKms { key_id: None }),
+        tags: vec![
+            ("run id".to_owned(), "2019/05/01".to_owned()),
+            ("crawler".to_owned(), "raven".to_owned()),
+        ]
+        .into_iter()
+        .collect(),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        object_options.tagging(),
+        Some("crawler=raven&run%20id=2019%2F05%2F01".to_owned())
+    );
+    assert_eq!(
+        object_options.server_side_encryption(),
+        Some("aws:kms".to_owned())
+    );
+    assert_eq!(object_options.ssekms_key_id(), None);
+    assert_eq!(object_options.metadata(), None);
+    assert_eq!(S3ObjectOptions::default().tagging(), None);
+}
+
+#[test]
+fn s3_upload_setting_test() {
+    let upload_setting = S3UploadSetting::default();
+
+    assert_eq!(upload_setting.backoff(0), Duration::from_millis(100));
+    assert_eq!(upload_setting.backoff(3), Duration::from_millis(800));
+    assert_eq!(
+        upload_setting.content_md5(b"raven"),
+        Ok(Some("kH4THrO/byEpL6HtFui2DA==".to_owned()))
+    );
+    assert!(upload_setting.validate().is_empty());
+    assert_eq!(
+        S3UploadSetting {
+            part_size: 1024,
+            ..Default::default()
+        }
+        .validate()
+        .len(),
+        1
+    );
+}
+
+/// uploads to a local s3 compatible storage, such as minio started by
+/// `docker run -p 9000:9000 minio/minio server /data`.
+/// RAVEN_S3_TEST_ENDPOINT, RAVEN_S3_TEST_ACCESS_KEY_ID and RAVEN_S3_TEST_SECRET_ACCESS_KEY
+/// change where and as whom to upload.
 #[test]
 #[ignore]
 fn s3_upload_test() {
     use crate::charset::Charset;
     use crate::mime::TextMime;
+    use rusoto_s3::{CreateBucketRequest, GetObjectRequest, GetObjectTaggingRequest};
+    use std::env::var;
+    use std::io::Read;
+
+    // arrange
+    let client_config = S3ClientConfig {
+        region: "us-east-1".to_owned(),
+        endpoint: Some(
+            var("RAVEN_S3_TEST_ENDPOINT").unwrap_or_else(|_| "http://127.0.0.1:9000".to_owned()),
+        ),
+        credentials: Some(S3Credentials::Static {
+            access_key_id: var("RAVEN_S3_TEST_ACCESS_KEY_ID")
+                .unwrap_or_else(|_| "minioadmin".to_owned()),
+            secret_access_key: var("RAVEN_S3_TEST_SECRET_ACCESS_KEY")
+                .unwrap_or_else(|_| "minioadmin".to_owned()),
+            session_token: None,
+        }),
+    };
+    let client = client_config.create_client().unwrap();
+    // the bucket may exist already.
+    let _ = client
+        .create_bucket(CreateBucketRequest {
+            bucket: "raven-test".to_owned(),
+            ..Default::default()
+        })
+        .sync();
     let test_strings = "テストだよーん";
+    let object_options = S3ObjectOptions {
+        storage_class: Some(S3StorageClass::StandardIa),
+        acl: Some(S3Acl::Private),
+        server_side_encryption: Some(S3ServerSideEncryption::Aes256),
+        tags: vec![("crawler".to_owned(), "raven test".to_owned())]
+            .into_iter()
+            .collect(),
+        metadata: vec![("status".to_owned(), "200".to_owned())]
+            .into_iter()
+            .collect(),
+    };
     let s3_request = S3WriteFileRequest {
-        region: "ap-northeast-1".to_owned(),
-        bucket_name: "crow-dev".to_owned(),
-        object_key: "test_raven1.txt".to_owned(),
+        client_config,
+        bucket_name: "raven-test".to_owned(),
+        object_key: "test/test_raven1.txt".to_owned(),
+        object_options: &object_options,
+        upload_setting: &S3UploadSetting::default(),
         content_type: &Mime::Text {
             charset: Some(Charset::Utf8),
             text_type: TextMime::TextPlain,
+            params: vec![],
         },
+        content_encoding: None,
         content: test_strings.as_bytes(),
     };
-    let result = write_to_s3(s3_request).unwrap();
-    println!("{:?}", result);
-    assert_eq!(1, 1);
+
+    // act
+    write_to_s3(s3_request).unwrap();
+
+    // assert
+    let object = client
+        .get_object(GetObjectRequest {
+            bucket: "raven-test".to_owned(),
+            key: "test/test_raven1.txt".to_owned(),
+            ..Default::default()
+        })
+        .sync()
+        .unwrap();
+    let mut body = String::new();
+    object
+        .body
+        .unwrap()
+        .into_blocking_read()
+        .read_to_string(&mut body)
+        .unwrap();
+    assert_eq!(body, test_strings);
+    assert_eq!(object.content_type.unwrap(), "text/plain; charset=utf-8");
+    assert_eq!(object.storage_class.unwrap(), "STANDARD_IA");
+    assert_eq!(object.server_side_encryption.unwrap(), "AES256");
+    assert_eq!(object.metadata.unwrap()["status"], "200");
+
+    let tag_set = client
+        .get_object_tagging(GetObjectTaggingRequest {
+            bucket: "raven-test".to_owned(),
+            key: "test/test_raven1.txt".to_owned(),
+            ..Default::default()
+        })
+        .sync()
+        .unwrap()
+        .tag_set;
+    assert_eq!(tag_set.len(), 1);
+    assert_eq!(tag_set[0].key, "crawler");
+    assert_eq!(tag_set[0].value, "raven test");
+}
+
+/// uploads by multipart upload to a local s3 compatible storage, like s3_upload_test.
+#[test]
+#[ignore]
+fn s3_multipart_upload_test() {
+    use rusoto_s3::{CreateBucketRequest, GetObjectRequest};
+    use std::env::var;
+    use std::io::Read;
+
+    // arrange
+    let client_config = S3ClientConfig {
+        region: "us-east-1".to_owned(),
+        endpoint: Some(
+            var("RAVEN_S3_TEST_ENDPOINT").unwrap_or_else(|_| "http://127.0.0.1:9000".to_owned()),
+        ),
+        credentials: Some(S3Credentials::Static {
+            access_key_id: var("RAVEN_S3_TEST_ACCESS_KEY_ID")
+                .unwrap_or_else(|_| "minioadmin".to_owned()),
+            secret_access_key: var("RAVEN_S3_TEST_SECRET_ACCESS_KEY")
+                .unwrap_or_else(|_| "minioadmin".to_owned()),
+            session_token: None,
+        }),
+    };
+    let client = client_config.get_client().unwrap();
+    let _ = client
+        .create_bucket(CreateBucketRequest {
+            bucket: "raven-test".to_owned(),
+            ..Default::default()
+        })
+        .sync();
+    // 2 parts of 5 MiB and 1 MiB.
+    let content: Vec<u8> = (0..6 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let upload_setting = S3UploadSetting {
+        multipart_threshold: 5 * 1024 * 1024,
+        part_size: 5 * 1024 * 1024,
+        ..Default::default()
+    };
+
+    // act
+    write_to_s3(S3WriteFileRequest {
+        client_config,
+        bucket_name: "raven-test".to_owned(),
+        object_key: "test/test_raven_multipart.bin".to_owned(),
+        object_options: &S3ObjectOptions::default(),
+        upload_setting: &upload_setting,
+        content_type: &Mime::ApplicationOctetStream,
+        content_encoding: None,
+        content: &content,
+    })
+    .unwrap();
+
+    // assert
+    let object = client
+        .get_object(GetObjectRequest {
+            bucket: "raven-test".to_owned(),
+            key: "test/test_raven_multipart.bin".to_owned(),
+            ..Default::default()
+        })
+        .sync()
+        .unwrap();
+    // etag of multipart upload ends with the number of parts.
+    assert!(object.e_tag.unwrap().ends_with("-2\""));
+    let mut body = vec![];
+    object
+        .body
+        .unwrap()
+        .into_blocking_read()
+        .read_to_end(&mut body)
+        .unwrap();
+    assert_eq!(body, content);
 }
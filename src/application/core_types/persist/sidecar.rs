@@ -0,0 +1,300 @@
+use super::{
+    async_persist::PersistFuture, AsyncPersist, Persist, PersistError, PersistMethod, Placement,
+};
+use crate::application::core_types::crawler::{CrawlerRequest, CrawlerResult};
+use crate::charset::Charset;
+use crate::mime::{Mime, TextMime};
+use chrono::{DateTime, Local};
+use futures::future::{self, Future};
+use openssl::sha::sha256;
+use serde_derive::*;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// where metadata of a persisted body is written.
+/// warc, json_lines, sqlite and postgres outputs keep metadata with the body by themselves,
+/// and archive output writes metadata entries by its own option.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataSidecar {
+    /// a `{file name of the body}.meta.json` file or object next to the body.
+    /// it is not written when the body is skipped by the overwrite policy.
+    Json,
+
+    /// user metadata of the s3 object itself.
+    /// request headers and params are not included, since s3 limits user metadata to 2 KB.
+    ObjectMetadata,
+}
+
+/// content of a json sidecar.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SidecarMetadata<'a> {
+    pub request: &'a CrawlerRequest,
+    pub final_url: &'a str,
+    pub redirect_chain: &'a [String],
+    pub status: u16,
+    pub headers: &'a HashMap<String, String>,
+    pub content_type: String,
+    pub crawl_date: &'a DateTime<Local>,
+    pub retry_count: u8,
+    /// hex encoded sha256 of the body before output compression.
+    pub content_hash: String,
+}
+
+impl<'a> SidecarMetadata<'a> {
+    /// the body of the result may be taken or streamed, so its hash is given separately.
+    pub fn new(
+        request: &'a CrawlerRequest,
+        result: &'a CrawlerResult,
+        content_hash: String,
+    ) -> SidecarMetadata<'a> {
+        SidecarMetadata {
+            request,
+            final_url: &result.final_url,
+            redirect_chain: &result.redirect_chain,
+            status: result.response_status,
+            headers: &result.response_header,
+            content_type: result.response_content_type.to_string(),
+            crawl_date: &result.crawl_date,
+            retry_count: result.retry_count,
+            content_hash,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, serde_json::Error> {
+        serde_json::to_vec_pretty(self)
+    }
+
+    /// values sent as `x-amz-meta-raven-{name}` headers, which must be ascii.
+    pub fn object_metadata(&self) -> BTreeMap<String, String> {
+        let values = vec![
+            ("url", self.request.url.to_owned()),
+            ("method", format!("{:?}", self.request.method)),
+            ("final-url", self.final_url.to_owned()),
+            ("status", self.status.to_string()),
+            ("content-type", self.content_type.to_owned()),
+            ("crawl-date", self.crawl_date.to_rfc3339()),
+            ("retry-count", self.retry_count.to_string()),
+            ("content-hash", self.content_hash.to_owned()),
+        ];
+        values
+            .into_iter()
+            .map(|(name, value)| (format!("raven-{}", name), value))
+            .filter(|(_, value)| value.is_ascii())
+            .collect()
+    }
+}
+
+/// hex encoded sha256 of the body.
+pub fn content_hash(body: &[u8]) -> String {
+    to_hex(&sha256(body))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn json_mime() -> Mime {
+    Mime::Text {
+        text_type: TextMime::ApplicationJson,
+        charset: Some(Charset::Utf8),
+        params: vec![],
+    }
+}
+
+/// the persist method to write the body by, with the metadata as s3 object metadata if configured.
+pub fn with_object_metadata(method: &PersistMethod, metadata: &SidecarMetadata) -> PersistMethod {
+    let mut method = method.clone();
+    if let PersistMethod::AmazonS3 {
+        object_options,
+        sidecar: Some(MetadataSidecar::ObjectMetadata),
+        ..
+    } = &mut method
+    {
+        object_options.metadata.extend(metadata.object_metadata());
+    }
+    method
+}
+
+/// persist the body of the result, and its metadata by the sidecar of the method.
+pub fn persist_with_sidecar<App: Persist + ?Sized>(
+    app: &App,
+    method: &PersistMethod,
+    request: &CrawlerRequest,
+    result: &CrawlerResult,
+) -> Result<(), PersistError> {
+    if method.get_sidecar().is_none() {
+        return app
+            .persist_data(method, &result.response_body, &result.response_content_type)
+            .map(|_| ());
+    }
+
+    let metadata = SidecarMetadata::new(request, result, content_hash(&result.response_body));
+    let placement = app.persist_data(
+        &with_object_metadata(method, &metadata),
+        &result.response_body,
+        &result.response_content_type,
+    )?;
+    persist_sidecar_json(app, method, &placement, &metadata)
+}
+
+/// write the json sidecar next to where the body is placed by the method, if configured.
+/// no sidecar is written for a skipped body, since the kept one is not described by the metadata.
+pub fn persist_sidecar_json<App: Persist + ?Sized>(
+    app: &App,
+    method: &PersistMethod,
+    placement: &Placement,
+    metadata: &SidecarMetadata,
+) -> Result<(), PersistError> {
+    match sidecar_method(method, placement) {
+        Some(sidecar_method) => {
+            let json = metadata
+                .to_json()
+                .map_err(|e| method.to_error(e.to_string()))?;
+            app.persist_data(&sidecar_method, &json, &json_mime())
+                .map(|_| ())
+        }
+        None => Ok(()),
+    }
+}
+
+fn sidecar_method(method: &PersistMethod, placement: &Placement) -> Option<PersistMethod> {
+    match placement {
+        Placement::Written(placed_path) => method.get_sidecar_method(placed_path),
+        Placement::Skipped => None,
+    }
+}
+
+/// async counterpart of persist_with_sidecar.
+/// the body of the result is taken, so it is given as the content.
+pub fn persist_with_sidecar_async<App: AsyncPersist + Send + Sync + ?Sized + 'static>(
+    app: &Arc<App>,
+    method: &PersistMethod,
+    request: &CrawlerRequest,
+    result: &CrawlerResult,
+    content: Arc<Vec<u8>>,
+) -> PersistFuture {
+    if method.get_sidecar().is_none() {
+        return Box::new(
+            app.persist_data_async(method, content, &result.response_content_type)
+                .map(|_| ()),
+        );
+    }
+
+    let metadata = SidecarMetadata::new(request, result, content_hash(&content));
+    let persisted = app.persist_data_async(
+        &with_object_metadata(method, &metadata),
+        content,
+        &result.response_content_type,
+    );
+    if method.get_sidecar() != Some(&MetadataSidecar::Json) {
+        return Box::new(persisted.map(|_| ()));
+    }
+    let json = match metadata.to_json() {
+        Ok(json) => Arc::new(json),
+        Err(e) => return Box::new(future::err(method.to_error(e.to_string()))),
+    };
+    // the sidecar is written after the body is persisted successfully,
+    // since it is placed next to the body.
+    let method = method.clone();
+    let app = app.clone();
+    Box::new(persisted.and_then(move |placement| -> PersistFuture {
+        match sidecar_method(&method, &placement) {
+            Some(sidecar_method) => Box::new(
+                app.persist_data_async(&sidecar_method, json, &json_mime())
+                    .map(|_| ()),
+            ),
+            None => Box::new(future::ok(())),
+        }
+    }))
+}
+
+#[test]
+fn with_object_metadata_test() {
+    use crate::application::core_types::crawler::{
+        content_sniffing::ContentSniffing, request::Method,
+    };
+    use chrono::TimeZone;
+
+    // arrange
+    let request = CrawlerRequest {
+        url: "https://www.craw.app/".to_owned(),
+        method: Method::Get,
+        header: HashMap::new(),
+        encoding_setting: None,
+        timeout: 5,
+        max_retry: 0,
+        query_params: HashMap::new(),
+        body_params: HashMap::new(),
+        sleep: None,
+        proxy: None,
+        tls: None,
+        max_body_size: None,
+        stream_body: false,
+        compression: None,
+        redirect: None,
+        content_sniffing: ContentSniffing::Fallback,
+    };
+    let result = CrawlerResult {
+        response_status: 200,
+        response_header: HashMap::new(),
+        response_body: b"raven".to_vec(),
+        mills_takes_to_complete_to_request: 0,
+        retry_count: 1,
+        response_content_type: Mime::ApplicationPdf,
+        detected_charset: None,
+        charset_source: None,
+        replacement_count: 0,
+        crawl_date: Local.timestamp_opt(0, 0).unwrap(),
+        proxy: None,
+        removed_proxies: vec![],
+        raw_body_size: 5,
+        decoded_body_size: 5,
+        final_url: "https://www.craw.app/index.pdf".to_owned(),
+        redirect_chain: vec!["https://www.craw.app/".to_owned()],
+        request_header: HashMap::new(),
+    };
+    let metadata = SidecarMetadata::new(&request, &result, content_hash(&result.response_body));
+    let s3 = |sidecar| PersistMethod::AmazonS3 {
+        region: "ap-northeast-1".to_owned(),
+        bucket_name: "raven".to_owned(),
+        object_key: "index.pdf".to_owned(),
+        endpoint: None,
+        credentials: None,
+        object_options: Default::default(),
+        upload: Default::default(),
+        compression: None,
+        sidecar,
+        content_addressed: None,
+    };
+
+    // act
+    let with_metadata = with_object_metadata(&s3(Some(MetadataSidecar::ObjectMetadata)), &metadata);
+    let with_json = with_object_metadata(&s3(Some(MetadataSidecar::Json)), &metadata);
+
+    // assert
+    match with_metadata {
+        PersistMethod::AmazonS3 { object_options, .. } => {
+            assert_eq!(object_options.metadata.len(), 8);
+            assert_eq!(object_options.metadata["raven-status"], "200");
+            assert_eq!(object_options.metadata["raven-method"], "Get");
+            assert_eq!(object_options.metadata["raven-retry-count"], "1");
+            assert_eq!(
+                object_options.metadata["raven-final-url"],
+                "https://www.craw.app/index.pdf"
+            );
+            assert_eq!(
+                object_options.metadata["raven-content-hash"],
+                content_hash(b"raven")
+            );
+        }
+        other => panic!("unexpected method: {:?}", other),
+    }
+    assert_eq!(with_json, s3(Some(MetadataSidecar::Json)));
+    assert_eq!(
+        with_json
+            .get_sidecar_method("index_1.pdf")
+            .map(|method| method.get_file_name().to_owned()),
+        Some("index_1.pdf.meta.json".to_owned())
+    );
+}
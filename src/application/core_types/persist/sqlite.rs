@@ -0,0 +1,128 @@
+use super::PersistError;
+use crate::application::core_types::crawler::CrawlerResult;
+use rusqlite::{params, Connection};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// a connection shared by all tasks writing to the same database file,
+/// so that inserts from worker threads are serialized.
+struct SqliteDatabase {
+    connection: Connection,
+    created_tables: HashSet<String>,
+}
+
+/// insert a row of the response into the table, which is created if absent.
+/// a row with the same key is replaced.
+pub fn write_to_sqlite(
+    path: &str,
+    table: &str,
+    key: &str,
+    request_url: &str,
+    result: &CrawlerResult,
+) -> Result<(), PersistError> {
+    let database = get_database(path)?;
+    let mut database = database.lock().expect("sqlite database lock is poisoned");
+    database
+        .write(table, key, request_url, result)
+        .map_err(|e| PersistError::FailedToWriteSqlite(e.to_string()))
+}
+
+/// a table name is embedded into sql, so only alphanumerics and underscores are allowed.
+pub fn is_valid_table_name(table: &str) -> bool {
+    let mut chars = table.chars();
+    chars
+        .next()
+        .is_some_and(|head| head.is_ascii_alphabetic() || head == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn get_database(path: &str) -> Result<Arc<Mutex<SqliteDatabase>>, PersistError> {
+    lazy_static! {
+        static ref DATABASES: Mutex<HashMap<String, Arc<Mutex<SqliteDatabase>>>> =
+            Mutex::new(HashMap::new());
+    }
+
+    let mut databases = DATABASES.lock().expect("sqlite databases lock is poisoned");
+    if let Some(database) = databases.get(path) {
+        return Ok(database.clone());
+    }
+
+    let connection = Connection::open(path)
+        .and_then(|connection| {
+            // other processes may write to the same file.
+            connection.busy_timeout(Duration::from_secs(10))?;
+            Ok(connection)
+        })
+        .map_err(|e| PersistError::FailedToWriteSqlite(e.to_string()))?;
+    let database = Arc::new(Mutex::new(SqliteDatabase {
+        connection,
+        created_tables: HashSet::new(),
+    }));
+    databases.insert(path.to_owned(), database.clone());
+    Ok(database)
+}
+
+impl SqliteDatabase {
+    fn write(
+        &mut self,
+        table: &str,
+        key: &str,
+        request_url: &str,
+        result: &CrawlerResult,
+    ) -> Result<(), rusqlite::Error> {
+        if !is_valid_table_name(table) {
+            return Err(rusqlite::Error::InvalidParameterName(table.to_owned()));
+        }
+        if !self.created_tables.contains(table) {
+            self.connection.execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS \"{}\" (
+                        key TEXT PRIMARY KEY,
+                        url TEXT NOT NULL,
+                        final_url TEXT NOT NULL,
+                        status INTEGER NOT NULL,
+                        headers TEXT NOT NULL,
+                        content_type TEXT NOT NULL,
+                        crawl_date TEXT NOT NULL,
+                        body BLOB NOT NULL
+                    )",
+                    table
+                ),
+                params![],
+            )?;
+            self.created_tables.insert(table.to_owned());
+        }
+
+        let headers = serde_json::to_string(&result.response_header)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.connection.execute(
+            &format!(
+                "INSERT OR REPLACE INTO \"{}\"
+                    (key, url, final_url, status, headers, content_type, crawl_date, body)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                table
+            ),
+            params![
+                key,
+                request_url,
+                result.final_url,
+                result.response_status as i64,
+                headers,
+                result.response_content_type.to_string(),
+                result.crawl_date.to_rfc3339(),
+                result.response_body,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+#[test]
+fn is_valid_table_name_test() {
+    assert!(is_valid_table_name("pages"));
+    assert!(is_valid_table_name("_crawl_2019"));
+    assert!(!is_valid_table_name(""));
+    assert!(!is_valid_table_name("1pages"));
+    assert!(!is_valid_table_name("pages; DROP TABLE pages"));
+}
@@ -0,0 +1,134 @@
+use super::{json_lines::JsonLinesRecord, PersistError};
+use crate::application::core_types::crawler::{CrawlerRequest, CrawlerResult};
+use serde_derive::*;
+use std::io::{stdout, Error as IOError, Write};
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct FailedToWriteStdout(pub String);
+
+impl From<FailedToWriteStdout> for PersistError {
+    fn from(e: FailedToWriteStdout) -> Self {
+        PersistError::FailedToWriteStdout(e.0)
+    }
+}
+
+impl From<IOError> for FailedToWriteStdout {
+    fn from(e: IOError) -> Self {
+        FailedToWriteStdout(e.to_string())
+    }
+}
+
+/// what is written to the standard output for each response.
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Copy, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StdoutFormat {
+    /// the body as it is, followed by the delimiter.
+    #[default]
+    Raw,
+
+    /// a json record of the request and the response per line, same as json_lines output.
+    JsonLines,
+}
+
+/// bytes written to the standard output for the response.
+pub fn stdout_record(
+    format: StdoutFormat,
+    delimiter: &str,
+    request: &CrawlerRequest,
+    result: &CrawlerResult,
+) -> Result<Vec<u8>, FailedToWriteStdout> {
+    match format {
+        StdoutFormat::Raw => {
+            let mut record = Vec::with_capacity(result.response_body.len() + delimiter.len());
+            record.extend_from_slice(&result.response_body);
+            record.extend_from_slice(delimiter.as_bytes());
+            Ok(record)
+        }
+        StdoutFormat::JsonLines => {
+            let mut line = serde_json::to_vec(&JsonLinesRecord::new(request, result))
+                .map_err(|e| FailedToWriteStdout(e.to_string()))?;
+            line.push(b'\n');
+            Ok(line)
+        }
+    }
+}
+
+/// write a record of the response to the standard output.
+/// the standard output is locked until the whole record is written and flushed,
+/// so records of concurrent tasks never interleave.
+pub fn write_to_stdout(
+    format: StdoutFormat,
+    delimiter: &str,
+    request: &CrawlerRequest,
+    result: &CrawlerResult,
+) -> Result<(), FailedToWriteStdout> {
+    let record = stdout_record(format, delimiter, request, result)?;
+    let stdout = stdout();
+    let mut stdout = stdout.lock();
+    stdout.write_all(&record)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+#[test]
+fn stdout_record_test() {
+    use crate::application::core_types::crawler::{
+        content_sniffing::ContentSniffing, request::Method,
+    };
+    use crate::mime::Mime;
+    use chrono::Local;
+    use serde_json::Value;
+    use std::collections::HashMap;
+
+    // arrange
+    let request = CrawlerRequest {
+        url: "https://www.craw.app/".to_owned(),
+        method: Method::Get,
+        header: HashMap::new(),
+        encoding_setting: None,
+        timeout: 5,
+        max_retry: 0,
+        query_params: HashMap::new(),
+        body_params: HashMap::new(),
+        sleep: None,
+        proxy: None,
+        tls: None,
+        max_body_size: None,
+        stream_body: false,
+        compression: None,
+        redirect: None,
+        content_sniffing: ContentSniffing::Fallback,
+    };
+    let result = CrawlerResult {
+        response_status: 200,
+        response_header: HashMap::new(),
+        response_body: vec![0xff, 0x00, 0x01],
+        mills_takes_to_complete_to_request: 0,
+        retry_count: 0,
+        response_content_type: Mime::ApplicationOctetStream,
+        detected_charset: None,
+        charset_source: None,
+        replacement_count: 0,
+        crawl_date: Local::now(),
+        proxy: None,
+        removed_proxies: vec![],
+        raw_body_size: 3,
+        decoded_body_size: 3,
+        final_url: "https://www.craw.app/".to_owned(),
+        redirect_chain: vec![],
+        request_header: HashMap::new(),
+    };
+
+    // act
+    let raw = stdout_record(StdoutFormat::Raw, "\0", &request, &result);
+    let json_lines = stdout_record(StdoutFormat::JsonLines, "\0", &request, &result).unwrap();
+
+    // assert
+    assert_eq!(raw, Ok(vec![0xff, 0x00, 0x01, 0x00]));
+    assert_eq!(json_lines.last(), Some(&b'\n'));
+    assert_eq!(json_lines.iter().filter(|byte| **byte == b'\n').count(), 1);
+    let record: Value = serde_json::from_slice(&json_lines).unwrap();
+    assert_eq!(record["url"], "https://www.craw.app/");
+    assert_eq!(record["body"], "/wAB");
+    assert_eq!(record["body_encoding"], "base64");
+}
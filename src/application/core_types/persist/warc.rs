@@ -0,0 +1,311 @@
+use super::{rotating_file::RotatingFile, PersistError};
+use crate::application::core_types::crawler::{request::Method, CrawlerRequest, CrawlerResult};
+use crate::hostname::get_hostname;
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use openssl::sha::sha1;
+use reqwest::{StatusCode, Url};
+use std::collections::HashMap;
+use std::io::{Error as IOError, Write};
+use std::sync::{Arc, Mutex};
+use url::form_urlencoded::Serializer;
+use uuid::Uuid;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct FailedToWriteWarc(pub String);
+
+impl From<FailedToWriteWarc> for PersistError {
+    fn from(e: FailedToWriteWarc) -> Self {
+        PersistError::FailedToWriteWarc(e.0)
+    }
+}
+
+impl From<IOError> for FailedToWriteWarc {
+    fn from(e: IOError) -> Self {
+        FailedToWriteWarc(e.to_string())
+    }
+}
+
+/// append a request record and a response record to the current warc file of the directory and prefix.
+/// a new file is started when the current one reaches max_file_size,
+/// so a file can exceed it by the records of one exchange.
+pub fn write_to_warc(
+    directory: &str,
+    prefix: &str,
+    max_file_size: u64,
+    request: &CrawlerRequest,
+    result: &CrawlerResult,
+) -> Result<(), FailedToWriteWarc> {
+    let warc_file = get_warc_file(directory, prefix, max_file_size);
+    let mut warc_file = warc_file.lock().expect("warc file lock is poisoned");
+    warc_file.write_exchange(request, result)
+}
+
+fn get_warc_file(
+    directory: &str,
+    prefix: &str,
+    max_file_size: u64,
+) -> Arc<Mutex<RotatingWarcFile>> {
+    lazy_static! {
+        static ref WARC_FILES: Mutex<WarcFiles> = Mutex::new(HashMap::new());
+    }
+
+    let mut warc_files = WARC_FILES.lock().expect("warc files lock is poisoned");
+    warc_files
+        .entry((directory.to_owned(), prefix.to_owned(), max_file_size))
+        .or_insert_with(|| {
+            Arc::new(Mutex::new(RotatingWarcFile {
+                file: RotatingFile::new(directory, prefix, ".warc.gz", max_file_size),
+                warcinfo_id: String::new(),
+            }))
+        })
+        .clone()
+}
+
+/// rotating warc files by directory, prefix and max_file_size.
+type WarcFiles = HashMap<(String, String, u64), Arc<Mutex<RotatingWarcFile>>>;
+
+/// each file starts with a warcinfo record, and each record is a separate gzip member.
+struct RotatingWarcFile {
+    file: RotatingFile,
+    /// id of the warcinfo record of the current file.
+    warcinfo_id: String,
+}
+
+impl RotatingWarcFile {
+    fn write_exchange(
+        &mut self,
+        request: &CrawlerRequest,
+        result: &CrawlerResult,
+    ) -> Result<(), FailedToWriteWarc> {
+        if let Some(file_name) = self.file.rotate_if_full()? {
+            let warcinfo = warcinfo_record(&file_name);
+            self.write_record(&warcinfo)?;
+            self.warcinfo_id = warcinfo.id;
+        }
+
+        let date = result.crawl_date.with_timezone(&Utc);
+        let request_record = request_record(request, result, &date, &self.warcinfo_id);
+        let response_record = response_record(
+            request,
+            result,
+            &date,
+            &self.warcinfo_id,
+            &request_record.id,
+        );
+        self.write_record(&request_record)?;
+        self.write_record(&response_record)
+    }
+
+    fn write_record(&mut self, record: &WarcRecord) -> Result<(), FailedToWriteWarc> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&record.to_bytes())?;
+        self.file.write_all(&encoder.finish()?)?;
+        Ok(())
+    }
+}
+
+struct WarcRecord {
+    id: String,
+    headers: Vec<(&'static str, String)>,
+    block: Vec<u8>,
+}
+
+impl WarcRecord {
+    fn new(warc_type: &str, date: &DateTime<Utc>) -> WarcRecord {
+        let id = format!("<urn:uuid:{}>", Uuid::new_v4());
+        WarcRecord {
+            headers: vec![
+                ("WARC-Type", warc_type.to_owned()),
+                ("WARC-Record-ID", id.to_owned()),
+                ("WARC-Date", date.format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+            ],
+            id,
+            block: vec![],
+        }
+    }
+
+    fn header(mut self, name: &'static str, value: String) -> WarcRecord {
+        self.headers.push((name, value));
+        self
+    }
+
+    fn block(mut self, content_type: &str, block: Vec<u8>) -> WarcRecord {
+        self.headers.push(("Content-Type", content_type.to_owned()));
+        self.block = block;
+        self
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = b"WARC/1.0\r\n".to_vec();
+        for (name, value) in &self.headers {
+            bytes.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        bytes.extend_from_slice(format!("Content-Length: {}\r\n\r\n", self.block.len()).as_bytes());
+        bytes.extend_from_slice(&self.block);
+        bytes.extend_from_slice(b"\r\n\r\n");
+        bytes
+    }
+}
+
+fn warcinfo_record(file_name: &str) -> WarcRecord {
+    let fields = format!(
+        "software: raven/{}\r\nformat: WARC File Format 1.0\r\nhostname: {}\r\n",
+        env!("CARGO_PKG_VERSION"),
+        get_hostname()
+    );
+    WarcRecord::new("warcinfo", &Utc::now())
+        .header("WARC-Filename", file_name.to_owned())
+        .block("application/warc-fields", fields.into_bytes())
+}
+
+/// reqwest does not expose the request which was sent, so the request is rebuilt
+/// from the headers recorded for the final hop, the final url and the body params.
+fn request_record(
+    request: &CrawlerRequest,
+    result: &CrawlerResult,
+    date: &DateTime<Utc>,
+    warcinfo_id: &str,
+) -> WarcRecord {
+    let (path, host) = match Url::parse(&result.final_url) {
+        Ok(url) => {
+            let path = match url.query() {
+                Some(query) => format!("{}?{}", url.path(), query),
+                None => url.path().to_owned(),
+            };
+            let host = match (url.host_str(), url.port()) {
+                (Some(host), Some(port)) => format!("{}:{}", host, port),
+                (host, _) => host.unwrap_or_default().to_owned(),
+            };
+            (path, host)
+        }
+        Err(_) => (result.final_url.to_owned(), String::new()),
+    };
+
+    let (method, body) = match request.method {
+        Method::Get => ("GET", vec![]),
+        Method::Post => ("POST", form_urlencoded(&request.body_params).into_bytes()),
+    };
+    let mut headers: Vec<(String, String)> = result
+        .request_header
+        .iter()
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
+    headers.sort();
+    headers.insert(0, ("host".to_owned(), host));
+    if let Method::Post = request.method {
+        headers.push((
+            "content-type".to_owned(),
+            "application/x-www-form-urlencoded".to_owned(),
+        ));
+        headers.push(("content-length".to_owned(), body.len().to_string()));
+    }
+
+    let block = http_message(&format!("{} {} HTTP/1.1", method, path), &headers, &body);
+    WarcRecord::new("request", date)
+        .header("WARC-Target-URI", result.final_url.to_owned())
+        .header("WARC-Warcinfo-ID", warcinfo_id.to_owned())
+        .header("WARC-Block-Digest", sha1_digest(&block))
+        .block("application/http; msgtype=request", block)
+}
+
+/// headers are rewritten to describe the stored body,
+/// which may be decoded, sniffed or converted to another charset.
+fn response_record(
+    request: &CrawlerRequest,
+    result: &CrawlerResult,
+    date: &DateTime<Utc>,
+    warcinfo_id: &str,
+    request_id: &str,
+) -> WarcRecord {
+    let store_raw = request
+        .compression
+        .as_ref()
+        .is_some_and(|compression| compression.store_raw);
+    let mut headers: Vec<(String, String)> = result
+        .response_header
+        .iter()
+        .filter(|(name, _)| match name.to_lowercase().as_str() {
+            "content-type" | "content-length" | "transfer-encoding" => false,
+            "content-encoding" => store_raw,
+            _ => true,
+        })
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
+    headers.sort();
+    headers.push((
+        "content-type".to_owned(),
+        result.response_content_type.to_string(),
+    ));
+    headers.push((
+        "content-length".to_owned(),
+        result.response_body.len().to_string(),
+    ));
+
+    let reason = StatusCode::from_u16(result.response_status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or("");
+    let status_line = format!("HTTP/1.1 {} {}", result.response_status, reason);
+    let block = http_message(&status_line, &headers, &result.response_body);
+    WarcRecord::new("response", date)
+        .header("WARC-Target-URI", result.final_url.to_owned())
+        .header("WARC-Warcinfo-ID", warcinfo_id.to_owned())
+        .header("WARC-Concurrent-To", request_id.to_owned())
+        .header("WARC-Block-Digest", sha1_digest(&block))
+        .header("WARC-Payload-Digest", sha1_digest(&result.response_body))
+        .block("application/http; msgtype=response", block)
+}
+
+fn http_message(start_line: &str, headers: &[(String, String)], body: &[u8]) -> Vec<u8> {
+    let mut message = format!("{}\r\n", start_line);
+    for (name, value) in headers {
+        message.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    message.push_str("\r\n");
+
+    let mut message = message.into_bytes();
+    message.extend_from_slice(body);
+    message
+}
+
+/// params are sorted by name, so that the same request is written as the same record.
+fn form_urlencoded(params: &HashMap<String, String>) -> String {
+    let mut pairs: Vec<(&String, &String)> = params.iter().collect();
+    pairs.sort();
+    Serializer::new(String::new()).extend_pairs(pairs).finish()
+}
+
+/// sha1 digest in base32, which is the form used by most warc tools.
+pub fn sha1_digest(bytes: &[u8]) -> String {
+    static ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+    let digest = sha1(bytes);
+    let mut encoded = String::new();
+    for chunk in digest.chunks(5) {
+        let mut buffer = [0u8; 5];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let bits = buffer
+            .iter()
+            .fold(0u64, |bits, byte| (bits << 8) | *byte as u64);
+        for i in 0..8 {
+            encoded.push(ALPHABET[((bits >> (35 - i * 5)) & 0x1f) as usize] as char);
+        }
+    }
+    format!("sha1:{}", encoded)
+}
+
+#[test]
+fn warc_record_test() {
+    let date = Utc::now();
+    let record = WarcRecord::new("resource", &date).block("text/plain", b"abc".to_vec());
+    let bytes = String::from_utf8(record.to_bytes()).unwrap();
+
+    assert!(bytes.starts_with("WARC/1.0\r\nWARC-Type: resource\r\nWARC-Record-ID: <urn:uuid:"));
+    assert!(bytes.ends_with("Content-Type: text/plain\r\nContent-Length: 3\r\n\r\nabc\r\n\r\n"));
+    assert_eq!(sha1_digest(b"abc"), "sha1:VGMT4NSHA2AWVOR6EVYXQUGCNSONBWE5");
+    assert_eq!(
+        form_urlencoded(&hashmap!("q".to_owned() => "a b&c".to_owned())),
+        "q=a+b%26c"
+    );
+}
@@ -0,0 +1,181 @@
+use crate::application::core_types::crawler::body::ResponseHead;
+use crate::application::core_types::persist::{sidecar::content_hash, PersistMethod};
+use crate::url_encoding::encode_unreserved;
+use reqwest::Url;
+use std::collections::HashMap;
+
+/// template variables of output paths which are known only after crawling.
+/// they are kept as they are when tasks are created, and embedded just before persisting.
+///
+/// - `final_url`: url of the response after following redirects, percent-encoded.
+/// - `final_host`: host of final_url.
+/// - `status`: status code of the response.
+/// - `ext`: file extension for the content type of the response, such as `html` or `pdf`.
+/// - `charset`: charset of the persisted body, or `none` if it is not text.
+/// - `content_hash`: hex encoded sha256 of the persisted body. not available with stream_body.
+pub static LATE_BOUND_VARS: [&str; 6] = [
+    "final_url",
+    "final_host",
+    "status",
+    "ext",
+    "charset",
+    "content_hash",
+];
+
+/// values which embed late bound vars into themselves,
+/// so that building templates on creating tasks keeps them.
+pub fn late_bound_placeholders() -> HashMap<String, String> {
+    LATE_BOUND_VARS
+        .iter()
+        .map(|var| (var.to_string(), placeholder(var)))
+        .collect()
+}
+
+/// `content_hash` is bound only if the body is given.
+pub fn late_bound_values(
+    head: &ResponseHead,
+    body: Option<&[u8]>,
+) -> HashMap<&'static str, String> {
+    let final_host = Url::parse(head.final_url)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_owned()))
+        .unwrap_or_default();
+    let charset = head
+        .mime
+        .get_charset()
+        .map(|charset| charset.to_string())
+        .unwrap_or_else(|| "none".to_owned());
+
+    let mut values = hashmap!(
+        "final_url" => encode_unreserved(head.final_url),
+        "final_host" => encode_unreserved(&final_host),
+        "status" => head.status.to_string(),
+        "ext" => head.mime.extension().to_owned(),
+        "charset" => charset
+    );
+    if let Some(body) = body {
+        values.insert("content_hash", content_hash(body));
+    }
+    values
+}
+
+/// embed late bound values into the file paths and the other templated values of persist methods.
+pub fn bind_late_bound_vars(
+    persist_methods: &[PersistMethod],
+    values: &HashMap<&'static str, String>,
+) -> Vec<PersistMethod> {
+    persist_methods
+        .iter()
+        .map(|persist_method| {
+            let bind = |template: &str| {
+                values
+                    .iter()
+                    .fold(template.to_owned(), |bound, (var, value)| {
+                        bound.replace(&placeholder(var), value)
+                    })
+            };
+            let mut bound_method = persist_method.clone();
+            bound_method.update_file_path(bind(persist_method.get_file_name()));
+            for templated_value in bound_method.get_templated_values_mut() {
+                *templated_value = bind(templated_value);
+            }
+            bound_method
+        })
+        .collect()
+}
+
+/// true if the file path or the other templated values use the var, which is not bound yet.
+pub fn uses_late_bound_var(persist_method: &PersistMethod, var: &str) -> bool {
+    let placeholder = placeholder(var);
+    persist_method.get_file_name().contains(&placeholder)
+        || persist_method
+            .get_templated_values()
+            .iter()
+            .any(|value| value.contains(&placeholder))
+}
+
+fn placeholder(var: &str) -> String {
+    format!("{{{{{}}}}}", var)
+}
+
+#[test]
+fn bind_late_bound_vars_test() {
+    use crate::charset::Charset;
+    use crate::mime::{Mime, TextMime};
+
+    let persist_methods = vec![
+        PersistMethod::LocalFile {
+            file_path: "/var/tmp/{{id}}/{{final_url}}.html".to_owned(),
+            file_options: Default::default(),
+            compression: None,
+            sidecar: None,
+            content_addressed: None,
+        },
+        PersistMethod::LocalFile {
+            file_path: "/var/tmp/{{final_host}}/{{status}}/{{content_hash}}.{{ext}}".to_owned(),
+            file_options: Default::default(),
+            compression: None,
+            sidecar: None,
+            content_addressed: None,
+        },
+        PersistMethod::LocalFile {
+            file_path: "/var/tmp/{{charset}}/{{content_hash}}".to_owned(),
+            file_options: Default::default(),
+            compression: None,
+            sidecar: None,
+            content_addressed: None,
+        },
+    ];
+    let mime = Mime::Text {
+        text_type: TextMime::ApplicationJson,
+        charset: Some(Charset::Utf8),
+        params: vec![],
+    };
+    let head = ResponseHead {
+        status: 200,
+        mime: &mime,
+        final_url: "https://www.craw.app/a b?c=1",
+    };
+
+    let bound = bind_late_bound_vars(&persist_methods, &late_bound_values(&head, Some(b"{}")));
+    let streaming_bound =
+        bind_late_bound_vars(&persist_methods[2..], &late_bound_values(&head, None));
+
+    assert_eq!(
+        bound,
+        vec![
+            PersistMethod::LocalFile {
+                file_path: "/var/tmp/{{id}}/https%3A%2F%2Fwww.craw.app%2Fa%20b%3Fc%3D1.html"
+                    .to_owned(),
+                file_options: Default::default(),
+                compression: None,
+                sidecar: None,
+                content_addressed: None,
+            },
+            PersistMethod::LocalFile {
+                file_path: "/var/tmp/www.craw.app/200/44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a.json".to_owned(),
+                file_options: Default::default(),
+                compression: None,
+                sidecar: None,
+                content_addressed: None,
+            },
+            PersistMethod::LocalFile {
+                file_path: "/var/tmp/utf-8/44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a".to_owned(),
+                file_options: Default::default(),
+                compression: None,
+                sidecar: None,
+                content_addressed: None,
+            },
+        ]
+    );
+    assert_eq!(
+        streaming_bound,
+        vec![PersistMethod::LocalFile {
+            file_path: "/var/tmp/utf-8/{{content_hash}}".to_owned(),
+            file_options: Default::default(),
+            compression: None,
+            sidecar: None,
+            content_addressed: None,
+        }]
+    );
+}
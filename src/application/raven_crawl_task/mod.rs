@@ -1,9 +1,11 @@
+pub mod late_bound_vars;
+pub mod persist_sink;
 pub mod raven_crawl_task;
 pub mod raven_task_metrics;
 pub mod task_error;
 pub mod task_result;
 
-pub use self::raven_crawl_task::RavenCrawlTask;
+pub use self::raven_crawl_task::{CrawledTask, RavenCrawlTask};
 pub use self::raven_task_metrics::RavenCrawlTaskMetrics;
 pub use self::task_error::CrawlTaskError;
 pub use self::task_result::{CrawlTaskSuccess, CrawlerTaskResult, TaskFailed, TaskSuccess};
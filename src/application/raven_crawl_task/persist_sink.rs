@@ -0,0 +1,163 @@
+use crate::application::core_types::{
+    crawler::{
+        body::{BodySink, ResponseHead},
+        CrawlerRequest, CrawlerResult,
+    },
+    persist::{
+        sidecar::{persist_sidecar_json, to_hex, MetadataSidecar, SidecarMetadata},
+        Persist, PersistError, PersistMethod, PersistWriter,
+    },
+};
+use crate::application::raven_crawl_task::late_bound_vars::{
+    bind_late_bound_vars, late_bound_values, uses_late_bound_var,
+};
+use openssl::sha::Sha256;
+use std::io::{Error as IOError, Result as IOResult, Write};
+
+/// outputs which cannot be written while the body is streamed.
+/// they need the whole body or the exchange before they are opened:
+/// - object_metadata sidecar is sent before the body, whose hash is not known yet.
+/// - content_addressed output is placed by the hash of the body.
+/// - exchange outputs (warc, json_lines, sqlite, postgres, archive and stdout) write the body in a record.
+/// - `{{content_hash}}` is bound before the body is known.
+pub fn stream_body_limitations(persist_methods: &[PersistMethod]) -> Vec<String> {
+    let mut errors = vec![];
+    if persist_methods
+        .iter()
+        .any(|method| method.get_sidecar() == Some(&MetadataSidecar::ObjectMetadata))
+    {
+        errors.push("object_metadata sidecar is not supported with stream_body".to_owned());
+    }
+    if persist_methods
+        .iter()
+        .any(|method| method.get_content_addressing().is_some())
+    {
+        errors.push("content_addressed output is not supported with stream_body".to_owned());
+    }
+    if persist_methods.iter().any(|method| method.needs_exchange()) {
+        errors.push(
+            "warc, json_lines, sqlite, postgres, archive and stdout output are not supported with stream_body"
+                .to_owned(),
+        );
+    }
+    if persist_methods
+        .iter()
+        .any(|method| uses_late_bound_var(method, "content_hash"))
+    {
+        errors.push("{{content_hash}} in output is not supported with stream_body".to_owned());
+    }
+    errors
+}
+
+/// writes a streamed response body to all persist methods of a task.
+/// a failed persist method is skipped for the rest of the body,
+/// and writing fails only when all persist methods have failed.
+pub struct PersistSink<'a, App: Persist> {
+    app: &'a App,
+    persist_methods: &'a [PersistMethod],
+    /// persist methods whose late bound vars are bound on start.
+    bound_methods: Vec<PersistMethod>,
+    writers: Vec<Result<Box<dyn PersistWriter>, PersistError>>,
+    /// hash of the streamed body, for metadata sidecars.
+    hasher: Sha256,
+}
+
+impl<'a, App: Persist> PersistSink<'a, App> {
+    pub fn new(app: &'a App, persist_methods: &'a [PersistMethod]) -> PersistSink<'a, App> {
+        PersistSink {
+            app,
+            persist_methods,
+            bound_methods: vec![],
+            writers: vec![],
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// complete all writers, and write metadata sidecars of completed ones.
+    /// results are in the order of persist methods.
+    pub fn finish(
+        self,
+        request: &CrawlerRequest,
+        result: &CrawlerResult,
+    ) -> Vec<Result<(), PersistError>> {
+        let app = self.app;
+        let metadata = SidecarMetadata::new(request, result, to_hex(&self.hasher.finish()));
+        self.writers
+            .into_iter()
+            .zip(&self.bound_methods)
+            .map(|(writer, bound_method)| {
+                writer
+                    .and_then(|writer| writer.finish())
+                    .and_then(|placement| {
+                        persist_sidecar_json(app, bound_method, &placement, &metadata)
+                    })
+            })
+            .collect()
+    }
+
+    fn all_failed_error(&self) -> IOError {
+        let details: Vec<String> = self
+            .writers
+            .iter()
+            .flat_map(|writer| writer.as_ref().err())
+            .map(|err| err.to_string())
+            .collect();
+        IOError::other(format!(
+            "all persist methods failed: {}",
+            details.join(", ")
+        ))
+    }
+}
+
+impl<'a, App: Persist> BodySink for PersistSink<'a, App> {
+    /// `content_hash` is not bound, because writers are opened before the body is known.
+    fn start(&mut self, head: &ResponseHead) -> IOResult<()> {
+        self.bound_methods =
+            bind_late_bound_vars(self.persist_methods, &late_bound_values(head, None));
+        self.writers = self
+            .bound_methods
+            .iter()
+            .map(|persist_method| self.app.open_persist_writer(persist_method, head.mime))
+            .collect();
+
+        if self.writers.iter().all(|writer| writer.is_err()) {
+            Err(self.all_failed_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a, App: Persist> Write for PersistSink<'a, App> {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.hasher.update(buf);
+        for (writer, persist_method) in self.writers.iter_mut().zip(self.persist_methods) {
+            let write_result = match writer {
+                Ok(writer) => writer.write_all(buf),
+                Err(_) => continue,
+            };
+            if let Err(e) = write_result {
+                *writer = Err(persist_method.to_error(e.to_string()));
+            }
+        }
+
+        if self.writers.iter().all(|writer| writer.is_err()) {
+            Err(self.all_failed_error())
+        } else {
+            Ok(buf.len())
+        }
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        for (writer, persist_method) in self.writers.iter_mut().zip(self.persist_methods) {
+            let flush_result = match writer {
+                Ok(writer) => writer.flush(),
+                Err(_) => continue,
+            };
+            if let Err(e) = flush_result {
+                *writer = Err(persist_method.to_error(e.to_string()));
+            }
+        }
+        Ok(())
+    }
+}
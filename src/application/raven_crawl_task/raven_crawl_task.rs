@@ -1,13 +1,23 @@
+use crate::application::raven_crawl_task::late_bound_vars::{
+    bind_late_bound_vars, late_bound_values,
+};
+use crate::application::raven_crawl_task::persist_sink::PersistSink;
 use crate::application::raven_crawl_task::task_result::CrawlerTaskResult;
 use crate::{
     application::core_types::{
-        crawler::{Crawler, CrawlerRequest, CrawlerResult},
-        persist::{Persist, PersistError, PersistMethod},
+        crawler::{AsyncCrawler, Crawler, CrawlerError, CrawlerRequest, CrawlerResult},
+        persist::{
+            sidecar::persist_with_sidecar_async, AsyncPersist, Persist, PersistError, PersistMethod,
+        },
     },
-    application::raven_crawl_task::{CrawlTaskError, CrawlTaskSuccess},
+    application::raven_crawl_task::{CrawlTaskError, CrawlTaskSuccess, TaskFailed, TaskSuccess},
 };
 use chrono::Local;
+use futures::future::{self, Either, Future};
 use serde_derive::Serialize;
+use std::mem::take;
+use std::sync::Arc;
+use tokio_threadpool::blocking;
 
 #[derive(Debug, PartialEq, Serialize, Clone)]
 pub struct RavenCrawlTask {
@@ -16,10 +26,7 @@ pub struct RavenCrawlTask {
 }
 
 impl RavenCrawlTask {
-    pub fn execute_in<App>(
-        self,
-        app: &App,
-    ) -> Result<CrawlerTaskResult<CrawlTaskSuccess>, CrawlerTaskResult<CrawlTaskError>>
+    pub fn execute_in<App>(self, app: &App) -> Result<TaskSuccess, TaskFailed>
     where
         App: Persist + Crawler,
     {
@@ -27,59 +34,220 @@ impl RavenCrawlTask {
         let result = self.execute_task(app);
         let total_duration_millis = Local::now().timestamp_millis() - start_timestamp;
 
-        match result {
-            Ok(success) => Ok(CrawlerTaskResult {
-                task: self,
-                total_duration_millis,
-                result: success,
-            }),
-            Err(err) => Err(CrawlerTaskResult {
-                task: self,
-                total_duration_millis,
-                result: err,
-            }),
-        }
+        to_task_result(self, total_duration_millis, result)
     }
 
     fn execute_task<App>(&self, app: &App) -> Result<CrawlTaskSuccess, CrawlTaskError>
     where
         App: Persist + Crawler,
     {
+        if self.request.stream_body {
+            return self.execute_streaming_task(app);
+        }
+
         let crawler_result: CrawlerResult = app.crawl(&self.request)?;
 
+        let persist_methods = bind_late_bound_vars(
+            &self.persist_methods,
+            &late_bound_values(&crawler_result.head(), Some(&crawler_result.response_body)),
+        );
         let persist_start = Local::now().timestamp_millis();
         let mut persist_results = vec![];
-        for persist_method in &self.persist_methods {
-            let result = app.persist_data(
-                persist_method,
-                &crawler_result.response_body,
-                &crawler_result.response_content_type,
-            );
+        for persist_method in &persist_methods {
+            let result = app.persist_exchange(persist_method, &self.request, &crawler_result);
             persist_results.push(result);
         }
         let persist_duration_millis = Local::now().timestamp_millis() - persist_start;
 
-        let all_persist_method_failed = persist_results.iter().all(|result| result.is_err());
+        summarize_persist_results(crawler_result, persist_results, persist_duration_millis)
+    }
 
-        let persist_errors: Vec<PersistError> = persist_results
-            .into_iter()
-            .flat_map(|result| result.err())
-            .collect();
+    /// the response body is written to persist methods while downloading,
+    /// so persist_duration_millis only includes the time to complete the writers.
+    fn execute_streaming_task<App>(&self, app: &App) -> Result<CrawlTaskSuccess, CrawlTaskError>
+    where
+        App: Persist + Crawler,
+    {
+        let mut persist_sink = PersistSink::new(app, &self.persist_methods);
+        let crawler_result: CrawlerResult =
+            app.crawl_streaming(&self.request, &mut persist_sink)?;
+
+        let persist_start = Local::now().timestamp_millis();
+        let persist_results = persist_sink.finish(&self.request, &crawler_result);
+        let persist_duration_millis = Local::now().timestamp_millis() - persist_start;
+
+        summarize_persist_results(crawler_result, persist_results, persist_duration_millis)
+    }
 
-        if all_persist_method_failed {
-            Err(CrawlTaskError::PersistFailed {
-                crawler_result,
-                persist_errors,
-                persist_duration_millis,
+    /// execute a task with stream_body in the async pipeline.
+    /// the body is streamed by the blocking http client to the writers of the persist methods,
+    /// such as S3MultipartWriter, so the whole task runs as a blocking section of the runtime.
+    pub fn execute_streaming_async<App>(
+        self,
+        app: Arc<App>,
+    ) -> impl Future<Item = Result<TaskSuccess, TaskFailed>, Error = ()> + Send
+    where
+        App: Persist + Crawler + Send + Sync + 'static,
+    {
+        let mut task = Some(self);
+        future::poll_fn(move || {
+            blocking(|| {
+                task.take()
+                    .expect("streaming task is executed once")
+                    .execute_in(app.as_ref())
+            })
+        })
+        .map_err(|err| error!("streaming task must run on the tokio thread pool: {}", err))
+    }
+
+    /// crawl stage of the async pipeline.
+    pub fn crawl_async<App>(self, app: &App) -> impl Future<Item = CrawledTask, Error = ()> + Send
+    where
+        App: AsyncCrawler,
+    {
+        let start_timestamp = Local::now().timestamp_millis();
+        app.crawl_async(self.request.clone())
+            .then(move |crawler_result| {
+                Ok(CrawledTask {
+                    task: self,
+                    start_timestamp,
+                    crawler_result,
+                })
             })
+    }
+}
+
+/// task whose crawl stage is completed and which waits for the persist stage.
+#[derive(Debug)]
+pub struct CrawledTask {
+    pub task: RavenCrawlTask,
+    pub start_timestamp: i64,
+    pub crawler_result: Result<CrawlerResult, CrawlerError>,
+}
+
+impl CrawledTask {
+    /// persist stage of the async pipeline.
+    /// all persist methods of the task are executed concurrently.
+    pub fn persist_async<App>(
+        self,
+        app: Arc<App>,
+    ) -> impl Future<Item = Result<TaskSuccess, TaskFailed>, Error = ()> + Send
+    where
+        App: AsyncPersist + Send + Sync + 'static,
+    {
+        let CrawledTask {
+            task,
+            start_timestamp,
+            crawler_result,
+        } = self;
+
+        let mut crawler_result = match crawler_result {
+            Ok(crawler_result) => crawler_result,
+            Err(err) => {
+                let total_duration_millis = Local::now().timestamp_millis() - start_timestamp;
+                return Either::A(future::ok(to_task_result(
+                    task,
+                    total_duration_millis,
+                    Err(err.into()),
+                )));
+            }
+        };
+
+        let persist_start = Local::now().timestamp_millis();
+        let persist_methods = bind_late_bound_vars(
+            &task.persist_methods,
+            &late_bound_values(&crawler_result.head(), Some(&crawler_result.response_body)),
+        );
+        // the exchange outputs are written on other threads, so they share a copy of the result.
+        let exchange = if persist_methods.iter().any(|method| method.needs_exchange()) {
+            Some((
+                Arc::new(task.request.clone()),
+                Arc::new(crawler_result.clone()),
+            ))
         } else {
-            let result = CrawlTaskSuccess {
-                persist_duration_millis,
-                crawler_result,
-                persist_errors,
-            };
+            None
+        };
+        // the body is shared by the other persist methods, and is given back after persisting.
+        let content = Arc::new(take(&mut crawler_result.response_body));
+        let persist_futures: Vec<_> = persist_methods
+            .iter()
+            .map(|persist_method| match &exchange {
+                Some((request, result)) if persist_method.needs_exchange() => app
+                    .persist_exchange_async(persist_method, request.clone(), result.clone())
+                    .then(Ok::<_, ()>),
+                _ => persist_with_sidecar_async(
+                    &app,
+                    persist_method,
+                    &task.request,
+                    &crawler_result,
+                    content.clone(),
+                )
+                .then(Ok::<_, ()>),
+            })
+            .collect();
 
-            Ok(result)
-        }
+        Either::B(
+            future::join_all(persist_futures).map(move |persist_results| {
+                let persist_duration_millis = Local::now().timestamp_millis() - persist_start;
+                crawler_result.response_body =
+                    Arc::try_unwrap(content).unwrap_or_else(|shared| shared.as_ref().clone());
+
+                let result = summarize_persist_results(
+                    crawler_result,
+                    persist_results,
+                    persist_duration_millis,
+                );
+                let total_duration_millis = Local::now().timestamp_millis() - start_timestamp;
+                to_task_result(task, total_duration_millis, result)
+            }),
+        )
+    }
+}
+
+fn summarize_persist_results(
+    crawler_result: CrawlerResult,
+    persist_results: Vec<Result<(), PersistError>>,
+    persist_duration_millis: i64,
+) -> Result<CrawlTaskSuccess, CrawlTaskError> {
+    let all_persist_method_failed = persist_results.iter().all(|result| result.is_err());
+
+    let persist_errors: Vec<PersistError> = persist_results
+        .into_iter()
+        .flat_map(|result| result.err())
+        .collect();
+
+    if all_persist_method_failed {
+        Err(CrawlTaskError::PersistFailed {
+            crawler_result,
+            persist_errors,
+            persist_duration_millis,
+        })
+    } else {
+        let result = CrawlTaskSuccess {
+            persist_duration_millis,
+            crawler_result,
+            persist_errors,
+        };
+
+        Ok(result)
+    }
+}
+
+fn to_task_result(
+    task: RavenCrawlTask,
+    total_duration_millis: i64,
+    result: Result<CrawlTaskSuccess, CrawlTaskError>,
+) -> Result<TaskSuccess, TaskFailed> {
+    match result {
+        Ok(success) => Ok(CrawlerTaskResult {
+            task,
+            total_duration_millis,
+            result: success,
+        }),
+        Err(err) => Err(CrawlerTaskResult {
+            task,
+            total_duration_millis,
+            result: err,
+        }),
     }
 }
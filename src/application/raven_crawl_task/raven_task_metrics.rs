@@ -75,6 +75,9 @@ impl RavenCrawlTaskMetrics {
                         Some(crawler_result.mills_takes_to_complete_to_request)
                     }
                     OtherError { .. } => None,
+                    ProxyError { .. } => None,
+                    BodyTooLarge { .. } => None,
+                    TooManyRedirects { .. } => None,
                 },
                 CrawlTaskError::PersistFailed { crawler_result, .. } => {
                     Some(crawler_result.mills_takes_to_complete_to_request)
@@ -186,6 +189,12 @@ impl<'a> EsDocument<'a> for RavenCrawlTaskMetrics {
 
 #[test]
 fn mapping_json_test() {
-    /// if MAPPING_JSON is malformed, this test causes panic.
-    println!("{}", RavenCrawlTaskMetrics::elastic_search_template());
+    // if MAPPING_JSON is malformed, this test causes panic.
+    let template = RavenCrawlTaskMetrics::elastic_search_template();
+
+    assert_eq!(template["index_patterns"][0], "raven-task-metrics-*");
+    let properties =
+        &template["mappings"][RavenCrawlTaskMetrics::elastic_search_typename()]["properties"];
+    assert_eq!(properties["total_duration"]["type"], "integer");
+    assert_eq!(properties["name"]["type"], "keyword");
 }
@@ -1,10 +1,11 @@
 use std::borrow::Cow;
 use std::fmt;
+use std::mem::replace;
 use std::str::FromStr;
 
 use encoding_rs::*;
 use serde::de::{Deserialize, Deserializer, Error, Unexpected, Visitor};
-use serde_derive::Serialize;
+use serde_derive::{Deserialize, Serialize};
 
 use self::Charset::*;
 use std::fmt::{Display, Formatter};
@@ -97,11 +98,31 @@ impl Charset {
         }
     }
 
+    /// charset for a label written in html or http header, such as "Shift_JIS", "sjis" or "utf8".
+    /// labels are resolved as the WHATWG encoding standard defines.
+    pub fn from_label(label: &str) -> Option<Charset> {
+        Encoding::for_label(label.as_bytes())
+            .and_then(|encoding| Charset::from_str(encoding.name()).ok())
+    }
+
     pub fn convert_to(&self, other: &Charset, target: &[u8]) -> Vec<u8> {
         let utf8: Cow<str> = self.get_encoding().decode(target).0;
         other.get_encoding().encode(utf8.as_ref()).0.to_vec()
     }
 
+    /// convert handling sequences which can not be converted by the mode.
+    /// returns the converted bytes and byte offsets of such sequences in the target.
+    pub fn convert_with(
+        &self,
+        other: &Charset,
+        target: &[u8],
+        mode: &ConversionMode,
+    ) -> (Vec<u8>, Vec<usize>) {
+        let mut converter = CharsetConverter::new(self, other, mode);
+        let converted = converter.convert(target, true);
+        (converted, converter.invalid_offsets)
+    }
+
     fn _to_string(&self) -> String {
         match self {
             Big5 => "big5".to_owned(),
@@ -203,6 +224,154 @@ impl Display for Charset {
     }
 }
 
+/// how malformed sequences of the input and characters unmappable to the output are handled.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversionMode {
+    /// fail if any sequence can not be converted.
+    Strict,
+    /// replace malformed sequences with U+FFFD and unmappable characters with "?".
+    #[default]
+    Lenient,
+    /// replace unmappable characters with html numeric character references such as "&#8364;".
+    NcrFallback,
+}
+
+/// converts charset chunk by chunk, recording byte offsets of sequences which can not be converted.
+pub struct CharsetConverter {
+    decoder: Decoder,
+    encoder: Encoder,
+    input_encoding: &'static Encoding,
+    mode: ConversionMode,
+    /// size of the input consumed by the decoder so far.
+    read_size: usize,
+    invalid_offsets: Vec<usize>,
+}
+
+impl CharsetConverter {
+    pub fn new(from: &Charset, to: &Charset, mode: &ConversionMode) -> CharsetConverter {
+        CharsetConverter {
+            decoder: from.get_encoding().new_decoder_with_bom_removal(),
+            encoder: to.get_encoding().new_encoder(),
+            input_encoding: from.get_encoding(),
+            mode: mode.clone(),
+            read_size: 0,
+            invalid_offsets: vec![],
+        }
+    }
+
+    /// byte offsets in the input of malformed sequences and unmappable characters.
+    pub fn invalid_offsets(&self) -> &[usize] {
+        &self.invalid_offsets
+    }
+
+    /// `last` must be true only for the last chunk, to flush bytes kept in the decoder and encoder.
+    pub fn convert(&mut self, src: &[u8], last: bool) -> Vec<u8> {
+        // the decoder skips BOM, so decoded text of the first chunk starts after it.
+        let mut skipped_length = match Encoding::for_bom(src) {
+            Some((encoding, bom_length))
+                if self.read_size == 0 && encoding == self.input_encoding =>
+            {
+                bom_length
+            }
+            _ => 0,
+        };
+
+        let mut converted: Vec<u8> = vec![];
+        let mut remaining = src;
+        loop {
+            let mut decoded = String::with_capacity(
+                self.decoder
+                    .max_utf8_buffer_length_without_replacement(remaining.len())
+                    .unwrap_or(remaining.len() * 3 + 16),
+            );
+            let segment_offset = self.read_size + replace(&mut skipped_length, 0);
+            let (result, read) =
+                self.decoder
+                    .decode_to_string_without_replacement(remaining, &mut decoded, last);
+            remaining = &remaining[read..];
+            self.read_size += read;
+
+            let finished = result == DecoderResult::InputEmpty;
+            self.encode(&decoded, segment_offset, last && finished, &mut converted);
+            match result {
+                DecoderResult::InputEmpty => return converted,
+                DecoderResult::OutputFull => continue,
+                DecoderResult::Malformed(bad_length, extra_length) => {
+                    let offset = self
+                        .read_size
+                        .saturating_sub(bad_length as usize + extra_length as usize);
+                    self.invalid_offsets.push(offset);
+                    self.replace('\u{FFFD}', &mut converted);
+                }
+            }
+        }
+    }
+
+    fn encode(&mut self, decoded: &str, segment_offset: usize, last: bool, dst: &mut Vec<u8>) {
+        let mut remaining = decoded;
+        let mut input_offset = segment_offset;
+        loop {
+            dst.reserve(
+                self.encoder
+                    .max_buffer_length_from_utf8_without_replacement(remaining.len())
+                    .unwrap_or(remaining.len() * 4 + 16),
+            );
+            let (result, read) = self
+                .encoder
+                .encode_from_utf8_to_vec_without_replacement(remaining, dst, last);
+            match result {
+                EncoderResult::InputEmpty => return,
+                EncoderResult::OutputFull => {
+                    input_offset += encoded_length(self.input_encoding, &remaining[..read]);
+                }
+                EncoderResult::Unmappable(unmappable) => {
+                    let mapped = &remaining[..read - unmappable.len_utf8()];
+                    input_offset += encoded_length(self.input_encoding, mapped);
+                    self.invalid_offsets.push(input_offset);
+                    input_offset += encoded_length(self.input_encoding, &unmappable.to_string());
+                    self.replace(unmappable, dst);
+                }
+            }
+            remaining = &remaining[read..];
+        }
+    }
+
+    /// write the replacement of a character which can not be converted.
+    fn replace(&mut self, character: char, dst: &mut Vec<u8>) {
+        let character_string = character.to_string();
+        let fallback = match self.mode {
+            ConversionMode::NcrFallback => format!("&#{};", character as u32),
+            ConversionMode::Strict | ConversionMode::Lenient => "?".to_owned(),
+        };
+        // U+FFFD is written as it is if the output charset is unicode.
+        let replacement = if character == '\u{FFFD}' {
+            &character_string
+        } else {
+            &fallback
+        };
+        dst.reserve(fallback.len() + 8);
+        let (result, _) =
+            self.encoder
+                .encode_from_utf8_to_vec_without_replacement(replacement, dst, false);
+        if let EncoderResult::Unmappable(_) = result {
+            // the fallback is ascii, which is always mappable.
+            let _ = self
+                .encoder
+                .encode_from_utf8_to_vec_without_replacement(&fallback, dst, false);
+        }
+    }
+}
+
+/// size of the text in the input charset, to locate unmappable characters in the input.
+fn encoded_length(encoding: &'static Encoding, text: &str) -> usize {
+    if encoding == UTF_16BE || encoding == UTF_16LE {
+        text.encode_utf16().count() * 2
+    } else {
+        encoding.encode(text).0.len()
+    }
+}
+
 struct CharsetVisitor;
 impl<'de> Deserialize<'de> for Charset {
     fn deserialize<D>(deserializer: D) -> Result<Charset, D::Error>
@@ -232,3 +401,42 @@ impl<'de> Visitor<'de> for CharsetVisitor {
         }
     }
 }
+
+#[test]
+fn convert_with_test() {
+    let sjis = [
+        &Utf8.convert_to(&ShiftJis, "日本".as_bytes())[..2],
+        b"\xff",
+        &Utf8.convert_to(&ShiftJis, "語".as_bytes()),
+    ]
+    .concat();
+    let utf16: Vec<u8> = "a😀b"
+        .encode_utf16()
+        .flat_map(|unit| unit.to_le_bytes().to_vec())
+        .collect();
+
+    assert_eq!(
+        ShiftJis.convert_with(&Utf8, &sjis, &ConversionMode::Lenient),
+        ("日\u{FFFD}語".as_bytes().to_vec(), vec![2])
+    );
+    assert_eq!(
+        Utf8.convert_with(&ShiftJis, "a😀b".as_bytes(), &ConversionMode::Lenient),
+        (b"a?b".to_vec(), vec![1])
+    );
+    assert_eq!(
+        Utf8.convert_with(&ShiftJis, "a😀b".as_bytes(), &ConversionMode::NcrFallback),
+        (b"a&#128512;b".to_vec(), vec![1])
+    );
+    assert_eq!(
+        Utf16le.convert_with(&ShiftJis, &utf16, &ConversionMode::Strict),
+        (b"a?b".to_vec(), vec![2])
+    );
+    assert_eq!(
+        Utf8.convert_with(
+            &ShiftJis,
+            b"\xef\xbb\xbfa\xf0\x9f\x98\x80",
+            &ConversionMode::Strict
+        ),
+        (b"a?".to_vec(), vec![4])
+    );
+}
@@ -0,0 +1,195 @@
+use crate::charset::Charset;
+use crate::charset::Charset::*;
+use encoding_rs::{DecoderResult, Encoding};
+use serde_derive::Serialize;
+
+/// length of the beginning of a body which is inspected to detect charset.
+pub const SNIFF_LENGTH: usize = 8 * 1024;
+
+/// html meta and xml declaration must appear within this length, same as browsers.
+const PRESCAN_LENGTH: usize = 1024;
+
+/// where the charset of a response comes from.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CharsetSource {
+    /// `input` of the encoding setting.
+    Configured,
+    /// byte order mark at the beginning of the body.
+    Bom,
+    /// charset parameter of Content-Type header.
+    Header,
+    /// `<meta charset>` or `<meta http-equiv="Content-Type">` of html.
+    HtmlMeta,
+    /// `<?xml encoding?>` declaration.
+    XmlDeclaration,
+    /// guessed from the byte sequence of the body.
+    Statistical,
+}
+
+/// detect charset declared in the body, trying BOM, html meta and xml declaration in this order.
+/// if nothing is declared and `statistical` is true, charset is guessed from the bytes.
+pub fn sniff_charset(body: &[u8], statistical: bool) -> Option<(Charset, CharsetSource)> {
+    let sniff_target = &body[..body.len().min(SNIFF_LENGTH)];
+    sniff_bom(sniff_target)
+        .map(|charset| (charset, CharsetSource::Bom))
+        .or_else(|| sniff_xml_declaration(sniff_target).map(|c| (c, CharsetSource::XmlDeclaration)))
+        .or_else(|| sniff_html_meta(sniff_target).map(|c| (c, CharsetSource::HtmlMeta)))
+        .or_else(|| {
+            if statistical {
+                detect_statistically(sniff_target).map(|c| (c, CharsetSource::Statistical))
+            } else {
+                None
+            }
+        })
+}
+
+pub fn sniff_bom(body: &[u8]) -> Option<Charset> {
+    Encoding::for_bom(body).and_then(|(encoding, _)| Charset::from_label(encoding.name()))
+}
+
+/// charset of `<?xml version="1.0" encoding="..."?>` at the beginning of the body.
+pub fn sniff_xml_declaration(body: &[u8]) -> Option<Charset> {
+    let prescan = ascii_lowercase_prescan(body);
+    if !prescan.starts_with("<?xml") {
+        return None;
+    }
+    let declaration = &prescan[..prescan.find("?>")?];
+    let value = attribute_value(declaration, "encoding=")?;
+    Charset::from_label(value).map(utf16_to_utf8)
+}
+
+/// charset of `<meta charset="...">` or `<meta http-equiv="Content-Type" content="...; charset=...">`.
+pub fn sniff_html_meta(body: &[u8]) -> Option<Charset> {
+    let prescan = ascii_lowercase_prescan(body);
+    let mut rest: &str = &prescan;
+    while let Some(meta_start) = rest.find("<meta") {
+        let meta = &rest[meta_start..];
+        let meta_end = meta.find('>').unwrap_or(meta.len());
+        let tag = &meta[..meta_end];
+        if let Some(charset) = attribute_value(tag, "charset=").and_then(Charset::from_label) {
+            // a page can not be utf-16 if its meta is readable as ascii.
+            return Some(utf16_to_utf8(charset));
+        }
+        rest = &meta[meta_end..];
+    }
+    None
+}
+
+/// guess japanese charsets from the bytes, when nothing is declared.
+/// returns None for ascii only body, or if no candidate can decode it.
+pub fn detect_statistically(body: &[u8]) -> Option<Charset> {
+    if body.is_ascii() {
+        if body
+            .windows(3)
+            .any(|bytes| bytes == b"\x1b$B" || bytes == b"\x1b$@")
+        {
+            return Some(Iso2022Jp);
+        }
+        return None;
+    }
+
+    if decode_without_error(Utf8, body).is_some() {
+        return Some(Utf8);
+    }
+
+    vec![ShiftJis, EucJp]
+        .into_iter()
+        .flat_map(|charset| {
+            decode_without_error(charset.clone(), body)
+                .map(|decoded| (japanese_score(&decoded), charset))
+        })
+        .max_by_key(|(score, charset)| (*score, *charset == ShiftJis))
+        .map(|(_, charset)| charset)
+}
+
+/// decode without replacement.
+/// a character cut off at the end is not an error, because body may be truncated to sniff.
+fn decode_without_error(charset: Charset, body: &[u8]) -> Option<String> {
+    let mut decoder = charset.get_encoding().new_decoder_without_bom_handling();
+    let mut decoded = String::with_capacity(
+        decoder
+            .max_utf8_buffer_length_without_replacement(body.len())
+            .unwrap_or(body.len() * 3),
+    );
+    match decoder.decode_to_string_without_replacement(body, &mut decoded, false) {
+        (DecoderResult::InputEmpty, _) => Some(decoded),
+        _ => None,
+    }
+}
+
+/// kana and kanji count up, and half-width kana which appears by decoding with wrong charset counts down.
+fn japanese_score(decoded: &str) -> i64 {
+    decoded
+        .chars()
+        .map(|c| match c as u32 {
+            0x3040..=0x30FF | 0x4E00..=0x9FFF => 1,
+            0xFF61..=0xFF9F => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+fn ascii_lowercase_prescan(body: &[u8]) -> String {
+    let prescan = &body[..body.len().min(PRESCAN_LENGTH)];
+    String::from_utf8_lossy(prescan).to_ascii_lowercase()
+}
+
+/// value after `name` in the tag, with quotes and surrounding spaces removed.
+fn attribute_value<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let value_start = tag.find(name)? + name.len();
+    let value = tag[value_start..].trim_start();
+    let value = value.trim_start_matches(&['"', '\''][..]);
+    let value_end = value
+        .find(|c: char| c == '"' || c == '\'' || c == ';' || c == '/' || c.is_whitespace())
+        .unwrap_or(value.len());
+    Some(&value[..value_end]).filter(|value| !value.is_empty())
+}
+
+fn utf16_to_utf8(charset: Charset) -> Charset {
+    match charset {
+        Utf16be | Utf16le => Utf8,
+        other => other,
+    }
+}
+
+#[test]
+fn sniff_charset_test() {
+    let sjis_text = Utf8.convert_to(&ShiftJis, "日本語のページです。".as_bytes());
+    let euc_text = Utf8.convert_to(&EucJp, "日本語のページです。".as_bytes());
+
+    let html_meta = b"<html><head><META http-equiv=\"Content-Type\" content=\"text/html; charset=x-sjis\"></head>";
+    let html5_meta = b"<!doctype html><meta name=\"viewport\"><meta charset='EUC-JP'>";
+    let xml = b"<?xml version=\"1.0\" encoding=\"Shift_JIS\"?><root/>";
+
+    assert_eq!(
+        sniff_charset(b"\xef\xbb\xbf<html>", false),
+        Some((Utf8, CharsetSource::Bom))
+    );
+    assert_eq!(
+        sniff_charset(html_meta, false),
+        Some((ShiftJis, CharsetSource::HtmlMeta))
+    );
+    assert_eq!(
+        sniff_charset(html5_meta, false),
+        Some((EucJp, CharsetSource::HtmlMeta))
+    );
+    assert_eq!(
+        sniff_charset(xml, false),
+        Some((ShiftJis, CharsetSource::XmlDeclaration))
+    );
+    assert_eq!(sniff_charset(&sjis_text, false), None);
+    assert_eq!(
+        sniff_charset(&sjis_text, true),
+        Some((ShiftJis, CharsetSource::Statistical))
+    );
+    assert_eq!(
+        sniff_charset(&euc_text, true),
+        Some((EucJp, CharsetSource::Statistical))
+    );
+    assert_eq!(
+        sniff_charset("日本語".as_bytes(), true),
+        Some((Utf8, CharsetSource::Statistical))
+    );
+    assert_eq!(sniff_charset(b"plain ascii", true), None);
+}
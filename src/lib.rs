@@ -1,10 +1,14 @@
+extern crate base64;
+extern crate brotli_decompressor;
 extern crate chrono;
 extern crate combine;
+extern crate flate2;
 extern crate futures;
 extern crate futures_cpupool;
 #[macro_use]
 extern crate log;
 extern crate log4rs;
+extern crate postgres;
 extern crate rusoto_core;
 extern crate rusoto_s3;
 extern crate serde;
@@ -14,14 +18,24 @@ extern crate serde_yaml;
 extern crate lazy_static;
 extern crate hostname as _hostname;
 extern crate rand;
+extern crate rusqlite;
 extern crate serde_json;
 extern crate slack_hook;
+extern crate tar;
+extern crate tokio;
+extern crate tokio_threadpool;
+extern crate url;
+extern crate zip;
+extern crate zstd;
 
 #[macro_use]
 pub mod macros;
 pub mod application;
 pub mod charset;
+pub mod charset_sniffing;
 pub mod es_api;
 pub mod hostname;
 pub mod mime;
+pub mod mime_sniffing;
 pub mod serde_dateformat;
+pub mod url_encoding;
@@ -1,3 +1,4 @@
+use std::mem::discriminant;
 use std::str::FromStr;
 use std::string::ToString;
 
@@ -8,17 +9,31 @@ use self::TextMime::*;
 
 use serde_derive::Serialize;
 
-#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+/// content type of a response.
+/// the string form is `type/subtype; name=value; charset=...`, which keeps parameters
+/// as they are written, so that it can be sent as Content-Type again.
+/// only the type and parameter names are lowercased, since they are case-insensitive.
+#[derive(Debug, Eq, Clone, Serialize)]
 pub enum Mime {
     ApplicationOctetStream,
     ApplicationPdf,
+    ApplicationZip,
+    ApplicationGzip,
+    ApplicationProtobuf,
     ImageJpeg,
     ImageGif,
     ImagePng,
+    ImageWebp,
     Text {
         text_type: TextMime,
         charset: Option<Charset>,
+        /// parameters such as `profile` or `version`, in the order of appearance.
+        /// values are kept as they are written, including quotes.
+        /// the first `charset` parameter is also kept here, so that it is written at the same
+        /// position, as it is written if its value is still the charset, or as the charset if not.
+        params: Vec<(String, String)>,
     },
+    /// any other type, or a non-text type with parameters such as `multipart/form-data; boundary=...`.
     Other {
         mime_type_string: String,
     },
@@ -31,11 +46,20 @@ pub enum TextMime {
     ApplicationXml,
     ApplicationStreamJson,
     ApplicationXHtmlXml,
+    ApplicationJavaScript,
+    ApplicationYaml,
+    ApplicationNdJson,
+    ImageSvgXml,
     TextPlain,
     TextEventStream,
     TextMarkDown,
     TextXml,
-    TextOther { text_mime_type_string: String },
+    TextCsv,
+    TextJavaScript,
+    /// other textual types, including `+json` and `+xml` structured syntax suffixes.
+    TextOther {
+        text_mime_type_string: String,
+    },
 }
 
 impl Mime {
@@ -62,6 +86,70 @@ impl Mime {
         }
     }
 
+    /// `type/subtype` without parameters.
+    pub fn essence(&self) -> String {
+        match self {
+            Text { text_type, .. } => text_type.essence().to_owned(),
+            other => {
+                let mime_string = other.to_string();
+                match mime_string.find(';') {
+                    Some(position) => mime_string[..position].to_owned(),
+                    None => mime_string,
+                }
+            }
+        }
+    }
+
+    /// file extension for the type, without a dot.
+    pub fn extension(&self) -> &str {
+        match self {
+            ApplicationOctetStream | Other { .. } => "bin",
+            ApplicationPdf => "pdf",
+            ApplicationZip => "zip",
+            ApplicationGzip => "gz",
+            ApplicationProtobuf => "pb",
+            ImageJpeg => "jpg",
+            ImageGif => "gif",
+            ImagePng => "png",
+            ImageWebp => "webp",
+            Text { text_type, .. } => match text_type {
+                ApplicationJson | ApplicationStreamJson => "json",
+                ApplicationXml | TextXml => "xml",
+                ApplicationXHtmlXml => "xhtml",
+                ApplicationJavaScript | TextJavaScript => "js",
+                ApplicationYaml => "yaml",
+                ApplicationNdJson => "ndjson",
+                ImageSvgXml => "svg",
+                TextMarkDown => "md",
+                TextCsv => "csv",
+                ApplicationFormUrlencoded | TextPlain | TextEventStream => "txt",
+                TextOther {
+                    text_mime_type_string,
+                } => match text_mime_type_string.as_str() {
+                    "text/html" => "html",
+                    other if other.ends_with("+json") || other.contains("ndjson") => "json",
+                    other if other.ends_with("+xml") => "xml",
+                    other if other.contains("yaml") => "yaml",
+                    other if other.contains("javascript") => "js",
+                    _ => "txt",
+                },
+            },
+        }
+    }
+
+    /// value of a parameter except charset, without quotes.
+    pub fn get_param(&self, name: &str) -> Option<String> {
+        let params = match self {
+            Text { params, .. } => params.clone(),
+            Other { mime_type_string } => parse_params(mime_type_string),
+            _ => vec![],
+        };
+        params
+            .into_iter()
+            .find(|(param_name, _)| param_name == name && param_name != "charset")
+            .map(|(_, value)| unquote(&value).to_owned())
+    }
+
     pub fn has_same_charset(&self, target: &Charset) -> bool {
         match self.get_charset() {
             Some(own) => own == target,
@@ -71,61 +159,125 @@ impl Mime {
     }
 }
 
+impl TextMime {
+    fn essence(&self) -> &str {
+        match self {
+            ApplicationFormUrlencoded => "application/x-www-form-urlencoded",
+            ApplicationJson => "application/json",
+            ApplicationXml => "application/xml",
+            ApplicationStreamJson => "application/stream+json",
+            ApplicationXHtmlXml => "application/xhtml+xml",
+            ApplicationJavaScript => "application/javascript",
+            ApplicationYaml => "application/yaml",
+            ApplicationNdJson => "application/x-ndjson",
+            ImageSvgXml => "image/svg+xml",
+            TextPlain => "text/plain",
+            TextEventStream => "text/event-stream",
+            TextMarkDown => "text/markdown",
+            TextXml => "text/xml",
+            TextCsv => "text/csv",
+            TextJavaScript => "text/javascript",
+            TextOther {
+                text_mime_type_string,
+            } => text_mime_type_string,
+        }
+    }
+}
+
+/// mimes are equal if they have the same type, charset and parameters,
+/// no matter how the charset is written.
+impl PartialEq for Mime {
+    fn eq(&self, other: &Mime) -> bool {
+        match (self, other) {
+            (
+                Text {
+                    text_type,
+                    charset,
+                    params,
+                },
+                Text {
+                    text_type: other_text_type,
+                    charset: other_charset,
+                    params: other_params,
+                },
+            ) => {
+                text_type == other_text_type
+                    && charset == other_charset
+                    && params_except_charset(params, charset)
+                        == params_except_charset(other_params, other_charset)
+            }
+            (
+                Other { mime_type_string },
+                Other {
+                    mime_type_string: other_mime_type_string,
+                },
+            ) => mime_type_string == other_mime_type_string,
+            _ => discriminant(self) == discriminant(other),
+        }
+    }
+}
+
+/// parameters except the one written for the charset.
+/// an unknown charset is not the charset of the mime, and it is compared as a parameter.
+fn params_except_charset<'a>(
+    params: &'a [(String, String)],
+    charset: &Option<Charset>,
+) -> Vec<&'a (String, String)> {
+    params
+        .iter()
+        .filter(|(name, _)| charset.is_none() || name != "charset")
+        .collect()
+}
+
 impl ToString for Mime {
     fn to_string(&self) -> String {
         match self {
             Text {
                 text_type,
-                charset: Some(charset),
-            } => match text_type {
-                ApplicationFormUrlencoded => format!(
-                    "application/x-www-form-urlencoded; charset={}",
-                    charset.to_string()
-                ),
-                ApplicationJson => format!("application/json; charset={}", charset.to_string()),
-                ApplicationXml => format!("application/xml; charset={}", charset.to_string()),
-                ApplicationStreamJson => {
-                    format!("application/stream+json; charset={}", charset.to_string())
+                charset,
+                params,
+            } => {
+                let mut mime_string = text_type.essence().to_owned();
+                let has_charset_param = params.iter().any(|(name, _)| name == "charset");
+                match charset {
+                    Some(charset) if !has_charset_param => {
+                        mime_string.push_str(&format!("; charset={}", charset.to_string()))
+                    }
+                    _ => (),
                 }
-                ApplicationXHtmlXml => {
-                    format!("application/xhtml+xml; charset={}", charset.to_string())
+                for (name, value) in params {
+                    let value = match charset {
+                        Some(charset)
+                            if name == "charset"
+                                && Charset::from_label(unquote(value)).as_ref()
+                                    != Some(charset) =>
+                        {
+                            charset.to_string()
+                        }
+                        _ => value.to_owned(),
+                    };
+                    mime_string.push_str(&format!("; {}={}", name, value));
                 }
-                TextPlain => format!("text/plain; charset={}", charset.to_string()),
-                TextEventStream => format!("text/event-stream; charset={}", charset.to_string()),
-                TextMarkDown => format!("text/markdown; charset={}", charset.to_string()),
-                TextXml => format!("text/xml; charset={}", charset.to_string()),
-                TextOther {
-                    text_mime_type_string,
-                } => format!("{}; charset={}", text_mime_type_string, charset.to_string()),
-            },
-
-            Text {
-                text_type,
-                charset: None,
-            } => match text_type {
-                ApplicationFormUrlencoded => "application/x-www-form-urlencoded".to_owned(),
-                ApplicationJson => "application/json".to_owned(),
-                ApplicationXml => "application/xml".to_owned(),
-                ApplicationStreamJson => "application/stream+json".to_owned(),
-                ApplicationXHtmlXml => "application/xhtml+xml".to_owned(),
-                TextPlain => "text/plain".to_owned(),
-                TextEventStream => "text/event-stream".to_owned(),
-                TextMarkDown => "text/markdown".to_owned(),
-                TextXml => "text/xml".to_owned(),
-                TextOther {
-                    text_mime_type_string,
-                } => text_mime_type_string.to_owned(),
-            },
+                mime_string
+            }
 
             ApplicationOctetStream => "application/octet-stream".to_owned(),
 
             ApplicationPdf => "application/pdf".to_owned(),
 
-            ImageJpeg => "img/jpeg".to_owned(),
+            ApplicationZip => "application/zip".to_owned(),
+
+            ApplicationGzip => "application/gzip".to_owned(),
+
+            ApplicationProtobuf => "application/x-protobuf".to_owned(),
 
-            ImageGif => "img/gif".to_owned(),
+            ImageJpeg => "image/jpeg".to_owned(),
 
-            ImagePng => "img/png".to_owned(),
+            ImageGif => "image/gif".to_owned(),
+
+            ImagePng => "image/png".to_owned(),
+
+            ImageWebp => "image/webp".to_owned(),
 
             Other { mime_type_string } => mime_type_string.to_owned(),
         }
@@ -136,77 +288,248 @@ impl FromStr for Mime {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let lower_case = s.to_lowercase();
-        let split_by_semicolon: Vec<&str> = lower_case.trim().split(";").collect();
-
-        let main_type: &str = split_by_semicolon
-            .first()
-            .ok_or("mime type is empty".to_owned())?
-            .trim();
-
-        let charset: Option<Charset> = split_by_semicolon
-            .iter()
-            .filter(|param| param.contains("charset="))
-            .collect::<Vec<_>>()
-            .first()
-            .and_then(|charset| Charset::from_str(charset).ok());
-
-        match main_type {
-            "img/jpeg" => Ok(ImageJpeg),
-            "img/gif" => Ok(ImageGif),
-            "img/png" => Ok(ImagePng),
-            "application/octet-stream" => Ok(ApplicationOctetStream),
-            "application/pdf" => Ok(ApplicationPdf),
-            "application/x-www-form-urlencoded" => Ok(Text {
-                charset,
-                text_type: ApplicationFormUrlencoded,
-            }),
-            "application/json" => Ok(Text {
-                charset,
-                text_type: ApplicationJson,
-            }),
-            "application/xml" => Ok(Text {
-                charset,
-                text_type: ApplicationXml,
-            }),
-            "application/stream+json" => Ok(Text {
-                charset,
-                text_type: ApplicationStreamJson,
-            }),
-            "application/xhtml+xml" => Ok(Text {
-                charset,
-                text_type: ApplicationXHtmlXml,
-            }),
-            "text/plain" => Ok(Text {
-                charset,
-                text_type: TextPlain,
-            }),
-            "text/event-stream" => Ok(Text {
-                charset,
-                text_type: TextEventStream,
-            }),
-            "text/markdown" => Ok(Text {
-                charset,
-                text_type: TextMarkDown,
+        let main_type: String = s
+            .split(';')
+            .next()
+            .map(|main_type| main_type.trim().to_lowercase())
+            .filter(|main_type| !main_type.is_empty())
+            .ok_or("mime type is empty".to_owned())?;
+
+        // only the first charset is used, and the others are dropped.
+        let mut charset: Option<Charset> = None;
+        let mut params: Vec<(String, String)> = vec![];
+        for (name, value) in parse_params(s) {
+            if name == "charset" {
+                if params.iter().any(|(param_name, _)| param_name == "charset") {
+                    continue;
+                }
+                charset = Charset::from_label(unquote(&value));
+            }
+            params.push((name, value));
+        }
+
+        let text_type = match main_type.as_str() {
+            "application/x-www-form-urlencoded" => Some(ApplicationFormUrlencoded),
+            "application/json" => Some(ApplicationJson),
+            "application/xml" => Some(ApplicationXml),
+            "application/stream+json" => Some(ApplicationStreamJson),
+            "application/xhtml+xml" => Some(ApplicationXHtmlXml),
+            "application/javascript" => Some(ApplicationJavaScript),
+            "application/yaml" => Some(ApplicationYaml),
+            "application/x-ndjson" => Some(ApplicationNdJson),
+            "image/svg+xml" => Some(ImageSvgXml),
+            "text/plain" => Some(TextPlain),
+            "text/event-stream" => Some(TextEventStream),
+            "text/markdown" => Some(TextMarkDown),
+            "text/xml" => Some(TextXml),
+            "text/csv" => Some(TextCsv),
+            "text/javascript" => Some(TextJavaScript),
+            other if is_textual(other) => Some(TextOther {
+                text_mime_type_string: other.to_owned(),
             }),
-            "text/xml" => Ok(Text {
+            _ => None,
+        };
+        if let Some(text_type) = text_type {
+            return Ok(Text {
+                text_type,
                 charset,
-                text_type: TextXml,
-            }),
-            other => {
-                if other.contains("text") {
-                    Ok(Text {
-                        charset,
-                        text_type: TextOther {
-                            text_mime_type_string: other.to_owned(),
-                        },
-                    })
-                } else {
-                    Ok(Other {
-                        mime_type_string: other.to_owned(),
-                    })
-                }
+                params,
+            });
+        }
+
+        // parameters of binary types are kept only in the string form.
+        let binary_type = match main_type.as_str() {
+            _ if !parse_params(s).is_empty() => None,
+            "image/jpeg" | "img/jpeg" => Some(ImageJpeg),
+            "image/gif" | "img/gif" => Some(ImageGif),
+            "image/png" | "img/png" => Some(ImagePng),
+            "image/webp" => Some(ImageWebp),
+            "application/octet-stream" => Some(ApplicationOctetStream),
+            "application/pdf" => Some(ApplicationPdf),
+            "application/zip" => Some(ApplicationZip),
+            "application/gzip" => Some(ApplicationGzip),
+            "application/x-protobuf" => Some(ApplicationProtobuf),
+            _ => None,
+        };
+        Ok(binary_type.unwrap_or_else(|| {
+            let mut mime_type_string = main_type;
+            for (name, value) in parse_params(s) {
+                mime_type_string.push_str(&format!("; {}={}", name, value));
+            }
+            Other { mime_type_string }
+        }))
+    }
+}
+
+/// application types which are text, other than the ones listed in TextMime.
+const TEXTUAL_APPLICATION_TYPES: [&str; 12] = [
+    "application/ecmascript",
+    "application/graphql",
+    "application/jsonl",
+    "application/ndjson",
+    "application/rtf",
+    "application/sql",
+    "application/toml",
+    "application/x-javascript",
+    "application/x-json",
+    "application/x-sh",
+    "application/x-yaml",
+    "application/yml",
+];
+
+/// text types which are not listed in TextMime: `text/*`, the listed application types,
+/// and types with `+json`, `+xml` or `+yaml` structured syntax suffixes such as `application/ld+json`.
+fn is_textual(main_type: &str) -> bool {
+    main_type.starts_with("text/")
+        || TEXTUAL_APPLICATION_TYPES.contains(&main_type)
+        || main_type.ends_with("+json")
+        || main_type.ends_with("+xml")
+        || main_type.ends_with("+yaml")
+}
+
+/// parameters after the first `;`. names are lowercased, and values are kept as they are written.
+/// a quoted value can contain `;`.
+fn parse_params(mime: &str) -> Vec<(String, String)> {
+    let mut params = vec![];
+    let mut rest = match mime.find(';') {
+        Some(position) => &mime[position + 1..],
+        None => return params,
+    };
+
+    while !rest.is_empty() {
+        let param_end = param_end(rest);
+        let param = &rest[..param_end];
+        let mut name_value = param.splitn(2, '=');
+        if let (Some(name), Some(value)) = (name_value.next(), name_value.next()) {
+            let name = name.trim().to_lowercase();
+            let value = value.trim();
+            if !name.is_empty() && !value.is_empty() {
+                params.push((name, value.to_owned()));
             }
         }
+        rest = rest.get(param_end + 1..).unwrap_or("");
+    }
+    params
+}
+
+/// position of `;` which ends the first parameter, skipping ones in quotes.
+fn param_end(params: &str) -> usize {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (position, character) in params.char_indices() {
+        match character {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => return position,
+            _ => (),
+        }
+    }
+    params.len()
+}
+
+fn unquote(value: &str) -> &str {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+#[test]
+fn mime_roundtrip_test() {
+    let mime_strings = vec![
+        "text/html; charset=utf-8",
+        "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"",
+        "application/vnd.api+json; charset=shift_jis; version=1.0",
+        "image/svg+xml",
+        "text/csv; charset=utf-8; header=present",
+        "text/plain; format=flowed; charset=\"UTF-8\"",
+        "text/plain; charset=unknown",
+        "application/x-yaml",
+        "application/x-ndjson",
+        "multipart/form-data; boundary=\"a;B=c\"",
+        "application/zip",
+        "image/webp",
+        "application/x-protobuf",
+        "image/jpeg",
+    ];
+
+    for mime_string in mime_strings {
+        let mime = Mime::from_str(mime_string).unwrap();
+        assert_eq!(mime.to_string(), mime_string);
+        assert_eq!(Mime::from_str(&mime.to_string()), Ok(mime));
     }
 }
+
+#[test]
+fn mime_from_str_test() {
+    assert_eq!(
+        Mime::from_str("Application/Problem+JSON;Charset=\"Shift_JIS\";Version=2"),
+        Ok(Text {
+            text_type: TextOther {
+                text_mime_type_string: "application/problem+json".to_owned(),
+            },
+            charset: Some(Charset::ShiftJis),
+            params: vec![("version".to_owned(), "2".to_owned())],
+        })
+    );
+    assert_eq!(Mime::from_str("application/gzip"), Ok(ApplicationGzip));
+    assert_eq!(
+        Mime::from_str("text/plain; charset=unknown").map(|mime| mime.to_string()),
+        Ok("text/plain; charset=unknown".to_owned())
+    );
+
+    assert_eq!(
+        Mime::from_str("text/html; charset=utf-8")
+            .unwrap()
+            .extension(),
+        "html"
+    );
+    assert_eq!(
+        Mime::from_str("application/ld+json").unwrap().extension(),
+        "json"
+    );
+    assert_eq!(Mime::from_str("image/jpeg").unwrap().extension(), "jpg");
+
+    // opendocument text is a zip file.
+    let odt = Mime::from_str("application/vnd.oasis.opendocument.text").unwrap();
+    assert!(!odt.is_text());
+    assert_eq!(odt.extension(), "bin");
+
+    let multipart = Mime::from_str("multipart/form-data; boundary=\"a;B=c\"").unwrap();
+    assert_eq!(multipart.get_param("boundary"), Some("a;B=c".to_owned()));
+    assert!(!multipart.is_text());
+}
+
+#[test]
+fn mime_set_charset_test() {
+    let mut declared = Mime::from_str("text/html; level=1; charset=Shift_JIS").unwrap();
+    let mut unknown = Mime::from_str("text/plain; charset=unknown; format=flowed").unwrap();
+    let mut duplicated = Mime::from_str("text/plain; charset=EUC-JP; charset=utf-8").unwrap();
+    let mut absent = Mime::from_str("application/json").unwrap();
+
+    declared.set_charset_when_text_mime(Charset::Utf8);
+    unknown.set_charset_when_text_mime(Charset::Utf8);
+    duplicated.set_charset_when_text_mime(Charset::Utf8);
+    absent.set_charset_when_text_mime(Charset::Utf8);
+
+    assert_eq!(declared.to_string(), "text/html; level=1; charset=utf-8");
+    assert_eq!(declared.get_param("charset"), None);
+    assert_eq!(
+        unknown.to_string(),
+        "text/plain; charset=utf-8; format=flowed"
+    );
+    assert_eq!(duplicated.to_string(), "text/plain; charset=utf-8");
+    assert_eq!(absent.to_string(), "application/json; charset=utf-8");
+    assert_eq!(
+        Mime::from_str(&declared.to_string()),
+        Ok(Text {
+            text_type: TextOther {
+                text_mime_type_string: "text/html".to_owned(),
+            },
+            charset: Some(Charset::Utf8),
+            params: vec![("level".to_owned(), "1".to_owned())],
+        })
+    );
+}
@@ -0,0 +1,102 @@
+use crate::mime::Mime;
+use crate::mime::Mime::*;
+use crate::mime::TextMime::*;
+
+/// detect content type from the magic bytes at the beginning of the body.
+/// returns None if the body does not look like any known type.
+pub fn sniff_mime(body: &[u8]) -> Option<Mime> {
+    sniff_binary(body).or_else(|| sniff_text(body))
+}
+
+fn sniff_binary(body: &[u8]) -> Option<Mime> {
+    let signatures: [(&[u8], Mime); 8] = [
+        (b"%PDF-", ApplicationPdf),
+        (b"\x89PNG\r\n\x1a\n", ImagePng),
+        (b"\xff\xd8\xff", ImageJpeg),
+        (b"GIF87a", ImageGif),
+        (b"GIF89a", ImageGif),
+        (b"PK\x03\x04", ApplicationZip),
+        (b"PK\x05\x06", ApplicationZip),
+        (b"\x1f\x8b\x08", ApplicationGzip),
+    ];
+    signatures
+        .iter()
+        .find(|(signature, _)| body.starts_with(signature))
+        .map(|(_, mime)| mime.clone())
+}
+
+fn sniff_text(body: &[u8]) -> Option<Mime> {
+    let body = body.strip_prefix(b"\xef\xbb\xbf").unwrap_or(body);
+    let start = body.iter().position(|byte| !byte.is_ascii_whitespace())?;
+    let text = &body[start..];
+    let head = String::from_utf8_lossy(&text[..text.len().min(64)]).to_ascii_lowercase();
+
+    let html_tags = [
+        "<!doctype html",
+        "<html",
+        "<head",
+        "<body",
+        "<script",
+        "<title",
+    ];
+    let text_type = if html_tags.iter().any(|tag| head.starts_with(tag)) {
+        TextOther {
+            text_mime_type_string: "text/html".to_owned(),
+        }
+    } else if head.starts_with("<?xml") {
+        ApplicationXml
+    } else if looks_like_json(text) {
+        ApplicationJson
+    } else {
+        return None;
+    };
+
+    Some(Text {
+        text_type,
+        charset: None,
+        params: vec![],
+    })
+}
+
+/// an object or an array whose first value starts like json.
+/// the body may be truncated, so it is not parsed to the end.
+fn looks_like_json(text: &[u8]) -> bool {
+    let mut non_whitespaces = text.iter().filter(|byte| !byte.is_ascii_whitespace());
+    match (non_whitespaces.next(), non_whitespaces.next()) {
+        (Some(b'{'), Some(b'"')) | (Some(b'{'), Some(b'}')) => true,
+        (Some(b'['), Some(next)) => match next {
+            b'{' | b'[' | b'"' | b']' | b'-' | b't' | b'f' | b'n' => true,
+            digit => digit.is_ascii_digit(),
+        },
+        _ => false,
+    }
+}
+
+#[test]
+fn sniff_mime_test() {
+    assert_eq!(sniff_mime(b"%PDF-1.7\n"), Some(ApplicationPdf));
+    assert_eq!(sniff_mime(b"\x89PNG\r\n\x1a\n\0\0"), Some(ImagePng));
+    assert_eq!(sniff_mime(b"\xff\xd8\xff\xe0"), Some(ImageJpeg));
+    assert_eq!(sniff_mime(b"GIF89a"), Some(ImageGif));
+    assert_eq!(sniff_mime(b"PK\x03\x04\x14\0"), Some(ApplicationZip));
+    assert_eq!(sniff_mime(b"\x1f\x8b\x08\0"), Some(ApplicationGzip));
+    assert_eq!(
+        sniff_mime(b"\xef\xbb\xbf\n  <!DOCTYPE html><html>").map(|mime| mime.to_string()),
+        Some("text/html".to_owned())
+    );
+    assert_eq!(
+        sniff_mime(b"<?xml version=\"1.0\"?><root/>").map(|mime| mime.to_string()),
+        Some("application/xml".to_owned())
+    );
+    assert_eq!(
+        sniff_mime(b" {\n  \"key\": 1").map(|mime| mime.to_string()),
+        Some("application/json".to_owned())
+    );
+    assert_eq!(
+        sniff_mime(b"[1, 2]").map(|mime| mime.to_string()),
+        Some("application/json".to_owned())
+    );
+    assert_eq!(sniff_mime(b"{not json}"), None);
+    assert_eq!(sniff_mime(b"plain text"), None);
+    assert_eq!(sniff_mime(b""), None);
+}
@@ -0,0 +1,28 @@
+use url::percent_encoding::{utf8_percent_encode, EncodeSet};
+
+/// encodes all characters except unreserved ones of RFC 3986.
+#[derive(Copy, Clone, Debug)]
+pub struct UnreservedEncodeSet;
+
+impl EncodeSet for UnreservedEncodeSet {
+    fn contains(&self, byte: u8) -> bool {
+        !(byte.is_ascii_alphanumeric() || b"-._~".contains(&byte))
+    }
+}
+
+/// percent-encode all characters except unreserved ones,
+/// so that the value can be a path segment or a query parameter as it is.
+pub fn encode_unreserved(value: &str) -> String {
+    utf8_percent_encode(value, UnreservedEncodeSet).collect()
+}
+
+#[test]
+fn encode_unreserved_test() {
+    assert_eq!(encode_unreserved("AZaz09-._~"), "AZaz09-._~");
+    assert_eq!(encode_unreserved("run id"), "run%20id");
+    assert_eq!(
+        encode_unreserved("https://example.com/a?b=c"),
+        "https%3A%2F%2Fexample.com%2Fa%3Fb%3Dc"
+    );
+    assert_eq!(encode_unreserved("日本"), "%E6%97%A5%E6%9C%AC");
+}
@@ -0,0 +1,97 @@
+extern crate flate2;
+extern crate raven;
+extern crate serde_json;
+extern crate tar;
+extern crate zip;
+
+mod common;
+
+use common::{create_request, create_task, start_local_server, TestApp, TestResponse};
+use flate2::read::GzDecoder;
+use raven::application::core_types::persist::{
+    archive::{finish_archives, ArchiveFormat},
+    PersistMethod,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{remove_file, File};
+use std::io::Read;
+use std::path::Path;
+use std::thread;
+use tar::Archive;
+use zip::ZipArchive;
+
+/// start a http server which returns the path as body.
+/// a large body, so that writes of concurrent tasks would interleave without the lock.
+fn start_server() -> String {
+    start_local_server(|request| TestResponse::text(request.path.repeat(10000)))
+}
+
+#[test]
+fn it_should_archive_bodies_of_parallel_tasks() {
+    // arrange
+    let address = start_server();
+    let tar_path = "/var/tmp/application/archive/run.tar.gz";
+    let zip_path = "/var/tmp/application/archive/run.zip";
+    let _ = remove_file(tar_path);
+    let _ = remove_file(zip_path);
+    let archive = |path: &str, format: ArchiveFormat, n: usize| PersistMethod::Archive {
+        path: path.to_owned(),
+        entry_name: format!("/pages/{}.txt", n),
+        format,
+        metadata: format == ArchiveFormat::TarGz,
+    };
+
+    // act
+    let handles: Vec<_> = (0..20)
+        .map(|n| {
+            let task = create_task(
+                create_request(&format!("{}/page/{}", address, n)),
+                vec![
+                    archive(tar_path, ArchiveFormat::TarGz, n),
+                    archive(zip_path, ArchiveFormat::Zip, n),
+                ],
+            );
+            thread::spawn(move || task.execute_in(&TestApp::default()).unwrap())
+        })
+        .collect();
+    for handle in handles {
+        assert_eq!(handle.join().unwrap().result.persist_errors, vec![]);
+    }
+    // archives are not completed until the run finishes.
+    assert!(!Path::new(tar_path).exists());
+    let finished = finish_archives();
+
+    // assert
+    assert_eq!(finished, Ok(()));
+
+    let mut tar = Archive::new(GzDecoder::new(File::open(tar_path).unwrap()));
+    let mut entries = HashMap::new();
+    for entry in tar.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let mut content = vec![];
+        entry.read_to_end(&mut content).unwrap();
+        entries.insert(entry.path().unwrap().display().to_string(), content);
+    }
+    assert_eq!(entries.len(), 40);
+    for n in 0..20 {
+        let body = &entries[&format!("pages/{}.txt", n)];
+        assert_eq!(body, &format!("/page/{}", n).repeat(10000).into_bytes());
+        let metadata: Value =
+            serde_json::from_slice(&entries[&format!("pages/{}.txt.meta.json", n)]).unwrap();
+        assert_eq!(
+            metadata["request"]["url"],
+            format!("{}/page/{}", address, n)
+        );
+        assert_eq!(metadata["status"], 200);
+    }
+
+    let mut zip = ZipArchive::new(File::open(zip_path).unwrap()).unwrap();
+    assert_eq!(zip.len(), 20);
+    let mut content = String::new();
+    zip.by_name("pages/7.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "/page/7".repeat(10000));
+}
@@ -0,0 +1,106 @@
+extern crate raven;
+extern crate serde_json;
+
+mod common;
+
+use common::{start_echo_server, TestApp};
+use raven::application::command_runner::{
+    config::config::HasConfig, runner::crawl_in_async_pipeline,
+};
+use serde_json::Value;
+use std::fs::{read, read_dir, read_to_string, remove_dir_all};
+use std::sync::Arc;
+
+#[test]
+fn async_pipeline_should_crawl_and_persist_all_tasks() {
+    // arrange
+    // a keep-alive server which returns the request path as body.
+    let address = start_echo_server();
+    let json_lines_directory = "/var/tmp/application/async_pipeline/json_lines";
+    let _ = remove_dir_all(json_lines_directory);
+    let yaml = format!(
+        r#"
+name: "async pipeline test"
+request:
+  url: "{}/{{{{id}}}}"
+  vars:
+    id: "[1..30]"
+  method: Get
+  timeout_in_seconds: 5
+
+output:
+  - local_file:
+      file_path: "/var/tmp/application/async_pipeline/{{{{id}}}}.txt"
+      sidecar: json
+  - local_file:
+      file_path: "/var/tmp/application/async_pipeline/{{{{id}}}}.manifest.json"
+      content_addressed:
+        blob_path: "/var/tmp/application/async_pipeline/blobs"
+  - json_lines:
+      directory: "{}"
+      prefix: "test"
+
+log:
+  file:
+    path: "/var/tmp/application.log"
+    level: "debug"
+
+async_runtime:
+  max_concurrency: 8
+  persist_concurrency: 2
+  channel_size: 4
+"#,
+        address, json_lines_directory
+    );
+    let app = Arc::new(TestApp::from_yaml(&yaml));
+    assert_eq!(app.get_config().validate(), Ok(()));
+    let tasks = app.get_config().create_crawler_tasks().unwrap();
+
+    // act
+    let results = crawl_in_async_pipeline(
+        app.clone(),
+        app.get_config().async_runtime.as_ref().unwrap(),
+        tasks,
+    );
+
+    // assert
+    assert_eq!(results.len(), 30);
+    assert!(results.iter().all(|result| result.is_ok()));
+    for id in 1..=30 {
+        let content = read(format!("/var/tmp/application/async_pipeline/{}.txt", id)).unwrap();
+        assert_eq!(content, format!("/{}", id).into_bytes());
+        let sidecar = read(format!(
+            "/var/tmp/application/async_pipeline/{}.txt.meta.json",
+            id
+        ))
+        .unwrap();
+        assert!(String::from_utf8(sidecar)
+            .unwrap()
+            .contains(&format!("\"final_url\": \"{}/{}\"", address, id)));
+        let manifest = read(format!(
+            "/var/tmp/application/async_pipeline/{}.manifest.json",
+            id
+        ))
+        .unwrap();
+        let manifest: Value = serde_json::from_slice(&manifest).unwrap();
+        let blob = read(manifest["blob"].as_str().unwrap()).unwrap();
+        assert_eq!(blob, format!("/{}", id).into_bytes());
+    }
+
+    // exchange outputs are written on the blocking sections of the runtime.
+    let files: Vec<_> = read_dir(json_lines_directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(files.len(), 1);
+    let mut bodies: Vec<String> = read_to_string(&files[0])
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .map(|record| record["body"].as_str().unwrap().to_owned())
+        .collect();
+    bodies.sort();
+    let mut expected: Vec<String> = (1..=30).map(|id| format!("/{}", id)).collect();
+    expected.sort();
+    assert_eq!(bodies, expected);
+}
@@ -0,0 +1,73 @@
+extern crate raven;
+
+mod common;
+
+use common::{start_echo_server, TestApp};
+use raven::application::command_runner::{
+    config::config::HasConfig, runner::crawl_in_async_pipeline,
+};
+use std::fs::{read, remove_dir_all};
+use std::sync::Arc;
+
+#[test]
+fn async_pipeline_should_stream_body_of_all_tasks() {
+    // arrange
+    let address = start_echo_server();
+    let directory = "/var/tmp/application/async_streaming";
+    let _ = remove_dir_all(directory);
+    let yaml = format!(
+        r#"
+name: "async streaming test"
+request:
+  url: "{}/{{{{id}}}}"
+  vars:
+    id: "[1..10]"
+  method: Get
+  timeout_in_seconds: 5
+  stream_body: true
+
+output:
+  - local_file:
+      file_path: "{}/{{{{id}}}}.txt"
+      sidecar: json
+
+log:
+  file:
+    path: "/var/tmp/application.log"
+    level: "debug"
+
+async_runtime:
+  max_concurrency: 4
+"#,
+        address, directory
+    );
+    let app = Arc::new(TestApp::from_yaml(&yaml));
+    assert_eq!(app.get_config().validate(), Ok(()));
+    let tasks = app.get_config().create_crawler_tasks().unwrap();
+
+    // act
+    let results = crawl_in_async_pipeline(
+        app.clone(),
+        app.get_config().async_runtime.as_ref().unwrap(),
+        tasks,
+    );
+
+    // assert
+    assert_eq!(results.len(), 10);
+    for result in &results {
+        let success = result.as_ref().unwrap();
+        assert_eq!(success.result.persist_errors, vec![]);
+        assert_eq!(
+            success.result.crawler_result.response_body,
+            Vec::<u8>::new()
+        );
+    }
+    for id in 1..=10 {
+        let content = read(format!("{}/{}.txt", directory, id)).unwrap();
+        assert_eq!(content, format!("/{}", id).into_bytes());
+        let sidecar = read(format!("{}/{}.txt.meta.json", directory, id)).unwrap();
+        assert!(String::from_utf8(sidecar)
+            .unwrap()
+            .contains(&format!("\"final_url\": \"{}/{}\"", address, id)));
+    }
+}
@@ -0,0 +1,112 @@
+extern crate raven;
+
+mod common;
+
+use common::{create_task, local_file, start_local_server, TestApp, TestResponse};
+use raven::application::core_types::crawler::{
+    encoding::Encoding, Crawler, CrawlerError, CrawlerRequest,
+};
+use raven::application::raven_crawl_task::CrawlTaskError;
+use raven::charset::{Charset, ConversionMode};
+
+/// start a http server which returns shift_jis text including malformed bytes.
+fn start_server() -> String {
+    start_local_server(|_| {
+        TestResponse::ok(malformed_body()).header("Content-Type", "text/plain; charset=Shift_JIS")
+    })
+}
+
+/// "壊れた" + 0xFF + "文字" + 0xFF, whose malformed bytes are at 6 and 11.
+fn malformed_body() -> Vec<u8> {
+    let mut body = Charset::Utf8.convert_to(&Charset::ShiftJis, "壊れた".as_bytes());
+    body.push(0xFF);
+    body.extend(Charset::Utf8.convert_to(&Charset::ShiftJis, "文字".as_bytes()));
+    body.push(0xFF);
+    body
+}
+
+fn create_request(url: &str, conversion_mode: ConversionMode) -> CrawlerRequest {
+    CrawlerRequest {
+        encoding_setting: Some(Encoding {
+            input: None,
+            output: Charset::Utf8,
+            sniff: true,
+            statistical_detection: false,
+            conversion_mode,
+        }),
+        ..common::create_request(url)
+    }
+}
+
+#[test]
+fn it_should_count_replacements_in_lenient_mode() {
+    // arrange
+    let address = start_server();
+    let request = create_request(&address, ConversionMode::Lenient);
+
+    // act
+    let result = TestApp::default().crawl(&request).unwrap();
+
+    // assert
+    assert_eq!(result.replacement_count, 2);
+    assert_eq!(
+        String::from_utf8(result.response_body).unwrap(),
+        "壊れた\u{FFFD}文字\u{FFFD}"
+    );
+}
+
+#[test]
+fn it_should_fail_with_offsets_in_strict_mode() {
+    // arrange
+    let address = start_server();
+    let request = create_request(&address, ConversionMode::Strict);
+
+    // act
+    let result = TestApp::default().crawl(&request);
+
+    // assert
+    match result {
+        Err(CrawlerError::CharsetConversionError {
+            error_detail,
+            invalid_offsets,
+            ..
+        }) => {
+            assert_eq!(invalid_offsets, vec![6, 11]);
+            assert_eq!(
+                error_detail,
+                "2 malformed or unmappable sequences at byte offsets [6, 11]"
+            );
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn it_should_fail_with_offsets_in_strict_mode_when_streaming() {
+    // arrange
+    let address = start_server();
+    let mut request = create_request(&address, ConversionMode::Strict);
+    request.stream_body = true;
+    let task = create_task(
+        request,
+        vec![local_file(
+            "/var/tmp/application/charset_conversion/strict.txt",
+        )],
+    );
+
+    // act
+    let result = task.execute_in(&TestApp::default());
+
+    // assert
+    match result.unwrap_err().result {
+        CrawlTaskError::CrawlerFailed(CrawlerError::CharsetConversionError {
+            invalid_offsets,
+            crawler_result,
+            ..
+        }) => {
+            assert_eq!(invalid_offsets, vec![6, 11]);
+            assert_eq!(crawler_result.replacement_count, 2);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
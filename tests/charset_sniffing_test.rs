@@ -0,0 +1,174 @@
+extern crate raven;
+
+mod common;
+
+use common::{create_task, local_file, start_local_server, TestApp, TestResponse};
+use raven::application::core_types::crawler::{
+    encoding::Encoding, Crawler, CrawlerError, CrawlerRequest,
+};
+use raven::charset::{Charset, ConversionMode};
+use raven::charset_sniffing::CharsetSource;
+use raven::mime::{Mime, TextMime};
+use std::fs::read;
+
+static JAPANESE_TEXT: &'static str = "文字コードはページの中で宣言されている。";
+
+/// start a http server which returns bodies declaring their charset only in themselves.
+fn start_server() -> String {
+    start_local_server(|request| match request.path.as_str() {
+        "/meta" => TestResponse::ok(html_body()).header("Content-Type", "text/html"),
+        "/meta_without_content_type" => TestResponse::ok(html_body()),
+        "/bom" => {
+            TestResponse::ok(bom_body()).header("Content-Type", "text/plain; charset=Shift_JIS")
+        }
+        _ => TestResponse::ok(xml_body()),
+    })
+}
+
+fn html_body() -> Vec<u8> {
+    let html = format!(
+        "<html><head><meta charset=\"Shift_JIS\"></head><body>{}</body></html>",
+        JAPANESE_TEXT
+    );
+    Charset::Utf8.convert_to(&Charset::ShiftJis, html.as_bytes())
+}
+
+fn xml_body() -> Vec<u8> {
+    let xml = format!(
+        "<?xml version=\"1.0\" encoding=\"EUC-JP\"?><text>{}</text>",
+        JAPANESE_TEXT
+    );
+    Charset::Utf8.convert_to(&Charset::EucJp, xml.as_bytes())
+}
+
+fn bom_body() -> Vec<u8> {
+    let mut body = b"\xef\xbb\xbf".to_vec();
+    body.extend_from_slice(JAPANESE_TEXT.as_bytes());
+    body
+}
+
+fn create_request(url: &str) -> CrawlerRequest {
+    CrawlerRequest {
+        encoding_setting: Some(Encoding {
+            input: None,
+            output: Charset::Utf8,
+            sniff: true,
+            statistical_detection: false,
+            conversion_mode: ConversionMode::Lenient,
+        }),
+        ..common::create_request(url)
+    }
+}
+
+#[test]
+fn it_should_convert_charset_declared_in_html_meta() {
+    // arrange
+    let address = start_server();
+    let request = create_request(&format!("{}/meta", address));
+
+    // act
+    let result = TestApp::default().crawl(&request).unwrap();
+
+    // assert
+    assert_eq!(result.detected_charset, Some(Charset::ShiftJis));
+    assert_eq!(result.charset_source, Some(CharsetSource::HtmlMeta));
+    assert_eq!(
+        result.response_content_type,
+        Mime::Text {
+            text_type: TextMime::TextOther {
+                text_mime_type_string: "text/html".to_owned()
+            },
+            charset: Some(Charset::Utf8),
+            params: vec![],
+        }
+    );
+    assert!(String::from_utf8(result.response_body)
+        .unwrap()
+        .contains(JAPANESE_TEXT));
+}
+
+#[test]
+fn it_should_prefer_bom_to_header() {
+    // arrange
+    let address = start_server();
+    let request = create_request(&format!("{}/bom", address));
+
+    // act
+    let result = TestApp::default().crawl(&request).unwrap();
+
+    // assert
+    assert_eq!(result.detected_charset, Some(Charset::Utf8));
+    assert_eq!(result.charset_source, Some(CharsetSource::Bom));
+}
+
+#[test]
+fn it_should_not_sniff_if_disabled() {
+    // arrange
+    let address = start_server();
+    let mut request = create_request(&format!("{}/meta", address));
+    if let Some(encoding) = request.encoding_setting.as_mut() {
+        encoding.sniff = false;
+    }
+
+    // act
+    let result = TestApp::default().crawl(&request);
+
+    // assert
+    match result {
+        Err(CrawlerError::CharsetConversionError { crawler_result, .. }) => {
+            assert_eq!(crawler_result.detected_charset, None);
+            assert_eq!(crawler_result.charset_source, None);
+            assert_eq!(crawler_result.response_body, html_body());
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn it_should_not_sniff_without_encoding_setting() {
+    // arrange
+    let address = start_server();
+    let request = common::create_request(&format!("{}/meta_without_content_type", address));
+
+    // act
+    let result = TestApp::default().crawl(&request).unwrap();
+
+    // assert
+    assert_eq!(result.response_content_type, Mime::ApplicationOctetStream);
+    assert_eq!(result.detected_charset, None);
+    assert_eq!(result.charset_source, None);
+    assert_eq!(result.response_body, html_body());
+}
+
+#[test]
+fn it_should_type_body_without_content_type_by_xml_declaration_when_streaming() {
+    // arrange
+    let address = start_server();
+    let mut request = create_request(&format!("{}/xml", address));
+    request.stream_body = true;
+    let task = create_task(
+        request,
+        vec![local_file("/var/tmp/application/charset_sniffing/xml.xml")],
+    );
+
+    // act
+    let result = task
+        .execute_in(&TestApp::default())
+        .unwrap()
+        .result
+        .crawler_result;
+
+    // assert
+    assert_eq!(result.detected_charset, Some(Charset::EucJp));
+    assert_eq!(result.charset_source, Some(CharsetSource::XmlDeclaration));
+    assert_eq!(
+        result.response_content_type,
+        Mime::Text {
+            text_type: TextMime::TextXml,
+            charset: Some(Charset::Utf8),
+            params: vec![],
+        }
+    );
+    let written = read("/var/tmp/application/charset_sniffing/xml.xml").unwrap();
+    assert!(String::from_utf8(written).unwrap().contains(JAPANESE_TEXT));
+}
@@ -0,0 +1,272 @@
+//! fixtures shared by integration tests.
+//! each test crate uses a part of them.
+#![allow(dead_code)]
+
+use raven::application::command_runner::{
+    boundary::CommandLineRaven,
+    config::config::{HasConfig, RavenConfig},
+};
+use raven::application::core_types::{
+    crawler::{AsyncCrawler, Crawler, CrawlerRequest},
+    log::elastic_search::{BulkInsertToEs, EsDocument},
+    logger::LogLevel,
+    notify::{Notify, NotifyError},
+    persist::{AsyncPersist, Persist, PersistMethod},
+};
+use raven::application::raven_crawl_task::RavenCrawlTask;
+use rs_es::error::EsError;
+use std::io::Error as IOError;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// a request received by the local server.
+#[derive(Debug, Clone)]
+pub struct TestRequest {
+    pub method: String,
+    pub path: String,
+    /// header names are lowercased.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl TestRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers
+            .iter()
+            .find(|(header_name, _)| *header_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// a response returned by the local server. Content-Length is added when it is sent.
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    pub fn new(status: &str, body: impl Into<Vec<u8>>) -> TestResponse {
+        TestResponse {
+            status: status.to_owned(),
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    pub fn ok(body: impl Into<Vec<u8>>) -> TestResponse {
+        TestResponse::new("200 OK", body)
+    }
+
+    /// a 200 response of utf-8 text.
+    pub fn text(body: impl Into<Vec<u8>>) -> TestResponse {
+        TestResponse::ok(body).header("Content-Type", "text/plain; charset=utf-8")
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> TestResponse {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = format!("HTTP/1.1 {}\r\n", self.status).into_bytes();
+        for (name, value) in &self.headers {
+            bytes.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        bytes.extend_from_slice(format!("Content-Length: {}\r\n\r\n", self.body.len()).as_bytes());
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+/// start a keep-alive http server on localhost, which responds to requests by the handler.
+/// returns the address such as `http://127.0.0.1:8080`.
+pub fn start_local_server<F>(handler: F) -> String
+where
+    F: Fn(&TestRequest) -> TestResponse + Send + Sync + 'static,
+{
+    start_counting_server(handler).0
+}
+
+/// start a local server which returns the request path as utf-8 text, with `X-Test: raven` header.
+pub fn start_echo_server() -> String {
+    start_local_server(|request| TestResponse::text(request.path.clone()).header("X-Test", "raven"))
+}
+
+/// start a local server like start_local_server, which also counts accepted connections.
+pub fn start_counting_server<F>(handler: F) -> (String, Arc<AtomicUsize>)
+where
+    F: Fn(&TestRequest) -> TestResponse + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    let connection_count = Arc::new(AtomicUsize::new(0));
+    let counter = connection_count.clone();
+    let handler = Arc::new(handler);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            counter.fetch_add(1, Ordering::SeqCst);
+            if let Ok(stream) = stream {
+                let handler = handler.clone();
+                thread::spawn(move || serve_connection(stream, handler.as_ref()));
+            }
+        }
+    });
+
+    (address, connection_count)
+}
+
+fn serve_connection<F>(stream: TcpStream, handler: &F)
+where
+    F: Fn(&TestRequest) -> TestResponse,
+{
+    let mut writer = stream.try_clone().unwrap();
+    let mut reader = BufReader::new(stream);
+    while let Some(request) = read_request(&mut reader) {
+        let response = handler(&request);
+        if writer.write_all(&response.to_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+/// read a request line, headers until an empty line, and the body of Content-Length.
+fn read_request(reader: &mut BufReader<TcpStream>) -> Option<TestRequest> {
+    let mut request_line = String::new();
+    let mut headers = vec![];
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => return None,
+            Ok(_) if line == "\r\n" => break,
+            Ok(_) if request_line.is_empty() => request_line = line.trim_end().to_owned(),
+            Ok(_) => {
+                let mut header = line.trim_end().splitn(2, ':');
+                let name = header.next().unwrap_or("").trim().to_lowercase();
+                let value = header.next().unwrap_or("").trim().to_owned();
+                headers.push((name, value));
+            }
+        }
+    }
+
+    let mut request_line = request_line.split(' ');
+    let mut request = TestRequest {
+        method: request_line.next().unwrap_or("").to_owned(),
+        path: request_line.next().unwrap_or("").to_owned(),
+        headers,
+        body: vec![],
+    };
+    let content_length = request
+        .header("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    request.body = vec![0; content_length];
+    reader.read_exact(&mut request.body).ok()?;
+    Some(request)
+}
+
+/// the application of integration tests.
+/// notifications and metrics are discarded.
+pub struct TestApp {
+    config: RavenConfig,
+}
+
+impl TestApp {
+    pub fn from_yaml(yaml: &str) -> TestApp {
+        TestApp {
+            config: serde_yaml::from_str(yaml).unwrap(),
+        }
+    }
+}
+
+/// an application whose config is not used, for tests which crawl or persist directly.
+impl Default for TestApp {
+    fn default() -> TestApp {
+        TestApp::from_yaml(
+            r#"
+name: "test"
+request:
+  url: "http://localhost/"
+  method: Get
+  timeout_in_seconds: 5
+output: []
+log:
+  file:
+    path: "/var/tmp/application.log"
+    level: "debug"
+"#,
+        )
+    }
+}
+
+impl HasConfig for TestApp {
+    fn get_config(&self) -> &RavenConfig {
+        &self.config
+    }
+}
+
+impl Crawler for TestApp {}
+
+impl Persist for TestApp {}
+
+impl AsyncCrawler for TestApp {}
+
+impl AsyncPersist for TestApp {}
+
+impl Notify for TestApp {
+    fn notify(&self, _: LogLevel, _: &str, _: &str) -> Result<(), NotifyError> {
+        Ok(())
+    }
+}
+
+impl BulkInsertToEs for TestApp {
+    fn bulk_insert<'a, T>(&self, _: &[T]) -> Result<(), EsError>
+    where
+        T: EsDocument<'a>,
+    {
+        Ok(())
+    }
+
+    fn create_index_template<'a, T>(&self, _: &str) -> Result<(), IOError>
+    where
+        T: EsDocument<'a>,
+    {
+        Ok(())
+    }
+}
+
+impl CommandLineRaven for TestApp {}
+
+/// a get request of the url with a timeout of 5 seconds.
+pub fn create_request(url: &str) -> CrawlerRequest {
+    CrawlerRequest {
+        url: url.to_owned(),
+        timeout: 5,
+        ..Default::default()
+    }
+}
+
+pub fn create_task(request: CrawlerRequest, persist_methods: Vec<PersistMethod>) -> RavenCrawlTask {
+    RavenCrawlTask {
+        request,
+        persist_methods,
+    }
+}
+
+/// a local file output with default options.
+pub fn local_file(file_path: &str) -> PersistMethod {
+    PersistMethod::LocalFile {
+        file_path: file_path.to_owned(),
+        file_options: Default::default(),
+        compression: None,
+        sidecar: None,
+        content_addressed: None,
+    }
+}
@@ -0,0 +1,230 @@
+extern crate flate2;
+extern crate raven;
+extern crate zstd;
+
+mod common;
+
+use common::{create_task, local_file, start_local_server, TestApp, TestResponse};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use raven::application::core_types::{
+    crawler::{
+        compression::{CompressionSetting, ContentCoding},
+        encoding::Encoding,
+        Crawler, CrawlerRequest,
+    },
+    persist::{
+        compression::{OutputCodec, OutputCompression},
+        PersistMethod,
+    },
+};
+use raven::charset::{Charset, ConversionMode};
+use std::fs::read;
+use std::io::{Read, Write};
+
+static JAPANESE_TEXT: &'static str = "圧縮されたレスポンスを展開してから文字コードを変換する。";
+
+/// start a http server which returns shift_jis text compressed with gzip for "/gzip"
+/// and zstd for "/zstd". received Accept-Encoding is returned in X-Accept-Encoding header.
+fn start_server() -> String {
+    start_local_server(|request| {
+        let (content_encoding, body) = match request.path.as_str() {
+            "/zstd" => ("zstd", zstd_body()),
+            _ => ("gzip", gzip_body()),
+        };
+        TestResponse::ok(body)
+            .header("Content-Type", "text/plain; charset=Shift_JIS")
+            .header("Content-Encoding", content_encoding)
+            .header(
+                "X-Accept-Encoding",
+                request.header("accept-encoding").unwrap_or(""),
+            )
+    })
+}
+
+fn sjis_body() -> Vec<u8> {
+    Charset::Utf8.convert_to(&Charset::ShiftJis, JAPANESE_TEXT.repeat(100).as_bytes())
+}
+
+fn gzip_body() -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(&sjis_body()).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn zstd_body() -> Vec<u8> {
+    zstd::stream::encode_all(&sjis_body()[..], 3).unwrap()
+}
+
+fn create_request(url: &str, compression: CompressionSetting) -> CrawlerRequest {
+    CrawlerRequest {
+        compression: Some(compression),
+        ..common::create_request(url)
+    }
+}
+
+fn shift_jis_to_utf8() -> Option<Encoding> {
+    Some(Encoding {
+        input: Some(Charset::ShiftJis),
+        output: Charset::Utf8,
+        sniff: true,
+        statistical_detection: false,
+        conversion_mode: ConversionMode::Lenient,
+    })
+}
+
+#[test]
+fn it_should_decode_body_before_charset_conversion() {
+    // arrange
+    let address = start_server();
+    let mut request = create_request(
+        &format!("{}/gzip", address),
+        CompressionSetting {
+            accept_encoding: vec![ContentCoding::Gzip, ContentCoding::Zstd],
+            store_raw: false,
+        },
+    );
+    request.encoding_setting = shift_jis_to_utf8();
+
+    // act
+    let result = TestApp::default().crawl(&request).unwrap();
+
+    // assert
+    assert_eq!(
+        result.response_header.get("x-accept-encoding").unwrap(),
+        "gzip, zstd"
+    );
+    assert_eq!(result.response_body, JAPANESE_TEXT.repeat(100).into_bytes());
+    assert_eq!(result.raw_body_size, gzip_body().len() as u64);
+    assert_eq!(result.decoded_body_size, sjis_body().len() as u64);
+}
+
+#[test]
+fn it_should_keep_raw_body_if_store_raw_is_enabled() {
+    // arrange
+    let address = start_server();
+    let request = create_request(
+        &format!("{}/zstd", address),
+        CompressionSetting {
+            accept_encoding: vec![ContentCoding::Zstd],
+            store_raw: true,
+        },
+    );
+
+    // act
+    let result = TestApp::default().crawl(&request).unwrap();
+
+    // assert
+    assert_eq!(result.response_body, zstd_body());
+    assert_eq!(result.raw_body_size, zstd_body().len() as u64);
+    assert_eq!(result.decoded_body_size, zstd_body().len() as u64);
+}
+
+#[test]
+fn it_should_decode_body_while_streaming() {
+    // arrange
+    let address = start_server();
+    let file_path = "/var/tmp/application/compression/zstd.txt";
+    let mut request = create_request(
+        &format!("{}/zstd", address),
+        CompressionSetting {
+            accept_encoding: vec![ContentCoding::Zstd],
+            store_raw: false,
+        },
+    );
+    request.encoding_setting = shift_jis_to_utf8();
+    request.stream_body = true;
+    let task = create_task(request, vec![local_file(file_path)]);
+
+    // act
+    let result = task.execute_in(&TestApp::default()).unwrap();
+
+    // assert
+    let crawler_result = &result.result.crawler_result;
+    assert_eq!(crawler_result.raw_body_size, zstd_body().len() as u64);
+    assert_eq!(crawler_result.decoded_body_size, sjis_body().len() as u64);
+    assert_eq!(
+        read(file_path).unwrap(),
+        JAPANESE_TEXT.repeat(100).into_bytes()
+    );
+}
+
+#[test]
+fn it_should_compress_persisted_output() {
+    // arrange
+    let address = start_server();
+    let file_path = "/var/tmp/application/compression/persisted.txt";
+    let mut request = create_request(
+        &format!("{}/gzip", address),
+        CompressionSetting {
+            accept_encoding: vec![ContentCoding::Gzip],
+            store_raw: false,
+        },
+    );
+    request.encoding_setting = shift_jis_to_utf8();
+    let task = create_task(
+        request,
+        vec![PersistMethod::LocalFile {
+            file_path: file_path.to_owned(),
+            file_options: Default::default(),
+            compression: Some(OutputCompression {
+                codec: OutputCodec::Gzip,
+                level: Some(9),
+                append_extension: true,
+            }),
+            sidecar: None,
+            content_addressed: None,
+        }],
+    );
+
+    // act
+    let result = task.execute_in(&TestApp::default()).unwrap();
+
+    // assert
+    let mut persisted = String::new();
+    GzDecoder::new(read(format!("{}.gz", file_path)).unwrap().as_slice())
+        .read_to_string(&mut persisted)
+        .unwrap();
+    assert_eq!(result.result.persist_errors, vec![]);
+    assert_eq!(persisted, JAPANESE_TEXT.repeat(100));
+}
+
+#[test]
+fn it_should_compress_streamed_output() {
+    // arrange
+    let address = start_server();
+    let file_path = "/var/tmp/application/compression/streamed.txt";
+    let mut request = create_request(
+        &format!("{}/zstd", address),
+        CompressionSetting {
+            accept_encoding: vec![ContentCoding::Zstd],
+            store_raw: false,
+        },
+    );
+    request.stream_body = true;
+    let task = create_task(
+        request,
+        vec![PersistMethod::LocalFile {
+            file_path: file_path.to_owned(),
+            file_options: Default::default(),
+            compression: Some(OutputCompression {
+                codec: OutputCodec::Zstd,
+                level: None,
+                append_extension: false,
+            }),
+            sidecar: None,
+            content_addressed: None,
+        }],
+    );
+
+    // act
+    let result = task.execute_in(&TestApp::default()).unwrap();
+
+    // assert
+    assert_eq!(result.result.persist_errors, vec![]);
+    assert_eq!(
+        zstd::decode_all(read(file_path).unwrap().as_slice()).unwrap(),
+        sjis_body()
+    );
+}
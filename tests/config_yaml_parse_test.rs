@@ -3,15 +3,31 @@ extern crate serde_yaml;
 
 use raven::{
     application::{
+        command_runner::config::async_runtime::AsyncRuntimeConfig,
         command_runner::config::config::RavenConfig,
         command_runner::config::log::{EsConfig, FileLogConfig, LogConfig},
         command_runner::config::notify_method::NotifyMethod,
+        core_types::crawler::compression::{CompressionSetting, ContentCoding},
+        core_types::crawler::content_sniffing::ContentSniffing,
+        core_types::crawler::proxy::{ProxyAuth, ProxyPoolSetting, ProxySelection, ProxySetting},
+        core_types::crawler::redirect::{RedirectPolicy, RedirectSetting},
         core_types::crawler::request::Method::{Get, Post},
+        core_types::crawler::tls::{ClientIdentity, TlsSetting},
         core_types::logger::LogLevel,
         core_types::logger::LogLevel::{Debug, Warn},
+        core_types::persist::archive::ArchiveFormat,
+        core_types::persist::compression::{OutputCodec, OutputCompression},
+        core_types::persist::content_addressed::ContentAddressing,
+        core_types::persist::local_file::{LocalFileOptions, OverwritePolicy},
+        core_types::persist::s3::{
+            S3Acl, S3Credentials, S3ObjectOptions, S3ServerSideEncryption, S3StorageClass,
+            S3UploadSetting,
+        },
+        core_types::persist::sidecar::MetadataSidecar,
+        core_types::persist::stdout::StdoutFormat,
         core_types::persist::PersistMethod,
     },
-    charset::Charset,
+    charset::{Charset, ConversionMode},
 };
 
 static FULL_PARAMETER_YAML: &'static str = r#"
@@ -40,9 +56,51 @@ request:
   encoding:
     input: "UTF-8"
     output: "UTF-8"
+    sniff: true
+    statistical_detection: true
+    conversion_mode: ncr_fallback
+  proxy:
+    http: "http://proxy.corp:8080"
+    https: "http://proxy.corp:8443"
+    auth:
+      username: "raven"
+      password: "secret"
+    no_proxy:
+      - "localhost"
+      - ".internal.corp"
+    pool:
+      urls:
+        - "http://egress1:3128"
+        - "http://egress2:3128"
+      selection: random
+      max_consecutive_failures: 5
+  tls:
+    root_certificates:
+      - "/etc/raven/private_ca.pem"
+    client_identity:
+      pkcs12:
+        path: "/etc/raven/client.p12"
+        password: "secret"
+    danger_accept_invalid_certs: true
+  max_body_size: 10485760
+  stream_body: true
+  compression:
+    accept_encoding:
+      - gzip
+      - br
+    store_raw: true
+  redirect:
+    policy: same_host
+    max_hops: 5
+    sensitive_headers:
+      - X-Api-Key
+  content_sniffing: always
 
 max_threads : 10
 
+async_runtime:
+  max_concurrency: 50
+  channel_size: 20
 
 notify:
   - slack:
@@ -54,11 +112,73 @@ notify:
 output:
   - local_file:
       file_path: "/var/application/%Y/%m/%d/{{id}}.html"
+      overwrite: versioned
+      fsync: true
+      mode: "640"
+      group: 1000
+      sidecar: json
+      content_addressed:
+        blob_path: "/var/application/blobs"
 
   - amazon_s3:
       region: "ap-nothereast-1"
       bucket_name: "test_bucket"
       object_key: "test_key"
+      endpoint: "http://minio.local:9000"
+      credentials:
+        static:
+          access_key_id: "raven"
+          secret_access_key: "secret"
+      storage_class: STANDARD_IA
+      acl: bucket-owner-full-control
+      server_side_encryption:
+        aws_kms:
+          key_id: "arn:aws:kms:ap-northeast-1:123456789012:key/raven"
+      tags:
+        crawler: "raven"
+        run_id: "%Y%m%d"
+      metadata:
+        source_url: "{{final_url}}"
+        status: "{{status}}"
+      upload:
+        timeout_in_seconds: 30
+        max_retry: 3
+        multipart_threshold: 104857600
+      compression:
+        codec: zstd
+        level: 19
+        append_extension: true
+      sidecar: object_metadata
+
+  - warc:
+      directory: "/var/archive/%Y/%m/%d"
+      max_file_size: 104857600
+
+  - json_lines:
+      directory: "/var/jsonl/%Y/%m/%d"
+      prefix: "run"
+      compression:
+        codec: gzip
+        append_extension: true
+
+  - sqlite:
+      path: "/var/raven/crawl.db"
+      table: "pages"
+
+  - postgres:
+      url: "postgresql://raven@localhost/crawl"
+      table: "pages"
+      key: "{{id}}"
+      batch_size: 50
+
+  - archive:
+      path: "/var/archive/%Y%m%d.zip"
+      entry_name: "{{id}}.html"
+      format: zip
+      metadata: true
+
+  - stdout:
+      format: json_lines
 
 log:
   file:
@@ -104,9 +224,62 @@ fn it_should_success_to_parse_when_full_parameter_exists() {
     let encoding = parsed.request.encoding.unwrap();
     assert_eq!(encoding.input, Some(Charset::Utf8));
     assert_eq!(encoding.output, Charset::Utf8);
+    assert!(encoding.sniff);
+    assert!(encoding.statistical_detection);
+    assert_eq!(encoding.conversion_mode, ConversionMode::NcrFallback);
+
+    let expected_proxy = ProxySetting {
+        http: Some("http://proxy.corp:8080".to_owned()),
+        https: Some("http://proxy.corp:8443".to_owned()),
+        auth: Some(ProxyAuth {
+            username: "raven".to_owned(),
+            password: "secret".to_owned(),
+        }),
+        no_proxy: vec!["localhost".to_owned(), ".internal.corp".to_owned()],
+        pool: Some(ProxyPoolSetting {
+            urls: vec![
+                "http://egress1:3128".to_owned(),
+                "http://egress2:3128".to_owned(),
+            ],
+            selection: ProxySelection::Random,
+            max_consecutive_failures: 5,
+        }),
+    };
+    assert_eq!(parsed.request.proxy, Some(expected_proxy));
+
+    let expected_tls = TlsSetting {
+        root_certificates: vec!["/etc/raven/private_ca.pem".to_owned()],
+        client_identity: Some(ClientIdentity::Pkcs12 {
+            path: "/etc/raven/client.p12".to_owned(),
+            password: "secret".to_owned(),
+        }),
+        danger_accept_invalid_certs: true,
+    };
+    assert_eq!(parsed.request.tls, Some(expected_tls));
+    assert_eq!(parsed.request.max_body_size, Some(10485760));
+    assert_eq!(parsed.request.stream_body, true);
+    let expected_compression = CompressionSetting {
+        accept_encoding: vec![ContentCoding::Gzip, ContentCoding::Br],
+        store_raw: true,
+    };
+    assert_eq!(parsed.request.compression, Some(expected_compression));
+    let expected_redirect = RedirectSetting {
+        policy: RedirectPolicy::SameHost,
+        max_hops: 5,
+        sensitive_headers: vec!["X-Api-Key".to_owned()],
+    };
+    assert_eq!(parsed.request.redirect, Some(expected_redirect));
+    assert_eq!(parsed.request.content_sniffing, ContentSniffing::Always);
 
     assert_eq!(parsed.max_threads, 10);
 
+    let expected_async_runtime = AsyncRuntimeConfig {
+        max_concurrency: 50,
+        persist_concurrency: 10,
+        channel_size: 20,
+    };
+    assert_eq!(parsed.async_runtime, Some(expected_async_runtime));
+
     let notify = &parsed.notify;
     assert_eq!(notify.len(), 1);
     let expected_notify = NotifyMethod::Slack {
@@ -118,17 +291,116 @@ fn it_should_success_to_parse_when_full_parameter_exists() {
     assert_eq!(notify[0], expected_notify);
 
     let output = &parsed.output;
-    assert_eq!(output.len(), 2);
+    assert_eq!(output.len(), 8);
     let expected_local = PersistMethod::LocalFile {
         file_path: "/var/application/%Y/%m/%d/{{id}}.html".to_owned(),
+        file_options: LocalFileOptions {
+            overwrite: OverwritePolicy::Versioned,
+            atomic: true,
+            fsync: true,
+            mode: Some("640".to_owned()),
+            owner: None,
+            group: Some(1000),
+        },
+        compression: None,
+        sidecar: Some(MetadataSidecar::Json),
+        content_addressed: Some(ContentAddressing {
+            blob_path: "/var/application/blobs".to_owned(),
+            blob_bucket: None,
+        }),
     };
     let expected_s3 = PersistMethod::AmazonS3 {
         region: "ap-nothereast-1".to_owned(),
         bucket_name: "test_bucket".to_owned(),
         object_key: "test_key".to_owned(),
+        endpoint: Some("http://minio.local:9000".to_owned()),
+        credentials: Some(S3Credentials::Static {
+            access_key_id: "raven".to_owned(),
+            secret_access_key: "secret".to_owned(),
+            session_token: None,
+        }),
+        object_options: S3ObjectOptions {
+            storage_class: Some(S3StorageClass::StandardIa),
+            acl: Some(S3Acl::BucketOwnerFullControl),
+            server_side_encryption: Some(S3ServerSideEncryption::AwsKms {
+                key_id: Some("arn:aws:kms:ap-northeast-1:123456789012:key/raven".to_owned()),
+            }),
+            tags: vec![
+                ("crawler".to_owned(), "raven".to_owned()),
+                ("run_id".to_owned(), "%Y%m%d".to_owned()),
+            ]
+            .into_iter()
+            .collect(),
+            metadata: vec![
+                ("source_url".to_owned(), "{{final_url}}".to_owned()),
+                ("status".to_owned(), "{{status}}".to_owned()),
+            ]
+            .into_iter()
+            .collect(),
+        },
+        upload: S3UploadSetting {
+            timeout_in_seconds: 30,
+            part_timeout_in_seconds: 60,
+            max_retry: 3,
+            backoff_millis: 100,
+            multipart_threshold: 104857600,
+            part_size: 8 * 1024 * 1024,
+            verify_checksum: true,
+        },
+        compression: Some(OutputCompression {
+            codec: OutputCodec::Zstd,
+            level: Some(19),
+            append_extension: true,
+        }),
+        sidecar: Some(MetadataSidecar::ObjectMetadata),
+        content_addressed: None,
     };
     assert_eq!(output[0], expected_local);
     assert_eq!(output[1], expected_s3);
+    let expected_warc = PersistMethod::Warc {
+        directory: "/var/archive/%Y/%m/%d".to_owned(),
+        prefix: "raven".to_owned(),
+        max_file_size: 104857600,
+    };
+    assert_eq!(output[2], expected_warc);
+    let expected_json_lines = PersistMethod::JsonLines {
+        directory: "/var/jsonl/%Y/%m/%d".to_owned(),
+        prefix: "run".to_owned(),
+        max_file_size: 1024 * 1024 * 1024,
+        compression: Some(OutputCompression {
+            codec: OutputCodec::Gzip,
+            level: None,
+            append_extension: true,
+        }),
+    };
+    assert_eq!(output[3], expected_json_lines);
+    let expected_sqlite = PersistMethod::Sqlite {
+        path: "/var/raven/crawl.db".to_owned(),
+        table: "pages".to_owned(),
+        key: "{{final_url}}".to_owned(),
+    };
+    assert_eq!(output[4], expected_sqlite);
+    let expected_postgres = PersistMethod::Postgres {
+        url: "postgresql://raven@localhost/crawl".to_owned(),
+        table: "pages".to_owned(),
+        key: "{{id}}".to_owned(),
+        batch_size: 50,
+        max_connections: 4,
+        max_retry: 3,
+    };
+    assert_eq!(output[5], expected_postgres);
+    let expected_archive = PersistMethod::Archive {
+        path: "/var/archive/%Y%m%d.zip".to_owned(),
+        entry_name: "{{id}}.html".to_owned(),
+        format: ArchiveFormat::Zip,
+        metadata: true,
+    };
+    assert_eq!(output[6], expected_archive);
+    let expected_stdout = PersistMethod::Stdout {
+        format: StdoutFormat::JsonLines,
+        delimiter: "\n".to_owned(),
+    };
+    assert_eq!(output[7], expected_stdout);
 
     let expected_log_config = LogConfig {
         file: FileLogConfig {
@@ -178,7 +450,15 @@ fn it_should_success_to_parse_when_only_required_param_exists() {
     assert_eq!(parsed.request.timeout_in_seconds, 1);
     assert_eq!(parsed.request.max_retry, 0);
     assert_eq!(parsed.request.encoding, None);
+    assert_eq!(parsed.request.proxy, None);
+    assert_eq!(parsed.request.tls, None);
+    assert_eq!(parsed.request.max_body_size, None);
+    assert_eq!(parsed.request.stream_body, false);
+    assert_eq!(parsed.request.compression, None);
+    assert_eq!(parsed.request.redirect, None);
+    assert_eq!(parsed.request.content_sniffing, ContentSniffing::Off);
     assert_eq!(parsed.max_threads, 1);
+    assert_eq!(parsed.async_runtime, None);
 
     let notify = &parsed.notify;
     assert_eq!(notify.len(), 0);
@@ -187,6 +467,10 @@ fn it_should_success_to_parse_when_only_required_param_exists() {
     assert_eq!(output.len(), 1);
     let expected_local = PersistMethod::LocalFile {
         file_path: "/var/application/%Y/%m/%d/{{id}}.html".to_owned(),
+        file_options: Default::default(),
+        compression: None,
+        sidecar: None,
+        content_addressed: None,
     };
     assert_eq!(output[0], expected_local);
 
@@ -0,0 +1,108 @@
+extern crate flate2;
+extern crate raven;
+extern crate serde_json;
+
+mod common;
+
+use common::{create_request, create_task, start_local_server, TestApp, TestResponse};
+use flate2::read::GzDecoder;
+use raven::application::core_types::persist::{
+    compression::{OutputCodec, OutputCompression},
+    content_addressed::{BlobManifest, ContentAddressing},
+    sidecar::MetadataSidecar,
+    PersistMethod,
+};
+use raven::application::raven_crawl_task::RavenCrawlTask;
+use std::fs::{read, read_dir, remove_dir_all, File};
+use std::io::Read;
+
+/// start a http server which returns the same body for paths under /same,
+/// and the path as body for others.
+fn start_server() -> String {
+    start_local_server(|request| {
+        let body = if request.path.starts_with("/same/") {
+            "<html>same page</html>".to_owned()
+        } else {
+            request.path.clone()
+        };
+        TestResponse::ok(body).header("Content-Type", "text/html; charset=utf-8")
+    })
+}
+
+fn create_content_addressed_task(url: &str, dir: &str, name: &str) -> RavenCrawlTask {
+    create_task(
+        create_request(url),
+        vec![PersistMethod::LocalFile {
+            file_path: format!("{}/pages/{}.html", dir, name),
+            file_options: Default::default(),
+            compression: Some(OutputCompression {
+                codec: OutputCodec::Gzip,
+                level: None,
+                append_extension: true,
+            }),
+            sidecar: Some(MetadataSidecar::Json),
+            content_addressed: Some(ContentAddressing {
+                blob_path: format!("{}/blobs", dir),
+                blob_bucket: None,
+            }),
+        }],
+    )
+}
+
+fn read_manifest(path: &str) -> BlobManifest {
+    serde_json::from_slice(&read(path).unwrap()).unwrap()
+}
+
+fn count_blobs(dir: &str) -> usize {
+    read_dir(format!("{}/blobs", dir))
+        .unwrap()
+        .map(|shard| read_dir(shard.unwrap().path()).unwrap().count())
+        .sum()
+}
+
+#[test]
+fn it_should_store_identical_bodies_once() {
+    // arrange
+    let address = start_server();
+    let dir = "/var/tmp/application/content_addressed";
+    let _ = remove_dir_all(dir);
+    let tasks = vec![
+        create_content_addressed_task(&format!("{}/same/20190501", address), dir, "20190501"),
+        create_content_addressed_task(&format!("{}/same/20190502", address), dir, "20190502"),
+        create_content_addressed_task(&format!("{}/other", address), dir, "other"),
+    ];
+
+    // act
+    for task in tasks {
+        let result = task.execute_in(&TestApp::default()).unwrap();
+        assert_eq!(result.result.persist_errors, vec![]);
+    }
+
+    // assert
+    let first = read_manifest(&format!("{}/pages/20190501.html", dir));
+    let second = read_manifest(&format!("{}/pages/20190502.html", dir));
+    let other = read_manifest(&format!("{}/pages/other.html", dir));
+    assert_eq!(first, second);
+    assert_ne!(first.blob, other.blob);
+    assert_eq!(count_blobs(dir), 2);
+
+    // sha256 of "<html>same page</html>".
+    let content_hash = "f4c73db3648fa6bb12c61c63fc988a6c0271291b8ad5911e1ddf8cef1096e6a3";
+    assert_eq!(first.content_hash, content_hash);
+    assert_eq!(first.blob, format!("{}/blobs/f4/{}.gz", dir, content_hash));
+    assert_eq!(first.bucket, None);
+    assert_eq!(first.size, 22);
+    assert_eq!(first.content_type, "text/html; charset=utf-8");
+
+    let mut body = String::new();
+    GzDecoder::new(File::open(&first.blob).unwrap())
+        .read_to_string(&mut body)
+        .unwrap();
+    assert_eq!(body, "<html>same page</html>");
+
+    // the sidecar is written next to the manifest.
+    let sidecar = read(format!("{}/pages/20190502.html.meta.json", dir)).unwrap();
+    assert!(String::from_utf8(sidecar)
+        .unwrap()
+        .contains(&format!("\"content_hash\": \"{}\"", first.content_hash)));
+}
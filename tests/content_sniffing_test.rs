@@ -0,0 +1,88 @@
+extern crate raven;
+
+mod common;
+
+use common::{start_local_server, TestApp, TestResponse};
+use raven::application::core_types::crawler::{
+    content_sniffing::ContentSniffing, Crawler, CrawlerRequest,
+};
+use raven::charset::Charset;
+use raven::mime::{Mime, TextMime};
+
+/// start a http server which returns bodies with missing or wrong Content-Type.
+fn start_server() -> String {
+    start_local_server(|request| match request.path.as_str() {
+        "/pdf" => TestResponse::ok(&b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n"[..])
+            .header("Content-Type", "application/octet-stream"),
+        "/json" => TestResponse::ok("{\"key\": \"value\"}"),
+        _ => TestResponse::text("<!DOCTYPE html><html><body>html</body></html>"),
+    })
+}
+
+fn create_request(url: &str, content_sniffing: ContentSniffing) -> CrawlerRequest {
+    CrawlerRequest {
+        content_sniffing,
+        ..common::create_request(url)
+    }
+}
+
+#[test]
+fn it_should_sniff_missing_or_octet_stream_content_type() {
+    // arrange
+    let address = start_server();
+    let pdf_request = create_request(&format!("{}/pdf", address), ContentSniffing::Fallback);
+    let json_request = create_request(&format!("{}/json", address), ContentSniffing::Fallback);
+    let html_request = create_request(&format!("{}/html", address), ContentSniffing::Fallback);
+
+    // act
+    let pdf_result = TestApp::default().crawl(&pdf_request).unwrap();
+    let json_result = TestApp::default().crawl(&json_request).unwrap();
+    let html_result = TestApp::default().crawl(&html_request).unwrap();
+
+    // assert
+    assert_eq!(pdf_result.response_content_type, Mime::ApplicationPdf);
+    assert_eq!(
+        json_result.response_content_type.to_string(),
+        "application/json"
+    );
+    // a declared content type is trusted.
+    assert_eq!(
+        html_result.response_content_type.to_string(),
+        "text/plain; charset=utf-8"
+    );
+}
+
+#[test]
+fn it_should_replace_wrong_content_type_when_always() {
+    // arrange
+    let address = start_server();
+    let request = create_request(&format!("{}/html", address), ContentSniffing::Always);
+
+    // act
+    let result = TestApp::default().crawl(&request).unwrap();
+
+    // assert
+    assert_eq!(
+        result.response_content_type,
+        Mime::Text {
+            text_type: TextMime::TextOther {
+                text_mime_type_string: "text/html".to_owned(),
+            },
+            charset: Some(Charset::Utf8),
+            params: vec![],
+        }
+    );
+}
+
+#[test]
+fn it_should_not_sniff_when_off() {
+    // arrange
+    let address = start_server();
+    let request = create_request(&format!("{}/pdf", address), ContentSniffing::Off);
+
+    // act
+    let result = TestApp::default().crawl(&request).unwrap();
+
+    // assert
+    assert_eq!(result.response_content_type, Mime::ApplicationOctetStream);
+}
@@ -0,0 +1,41 @@
+extern crate raven;
+
+mod common;
+
+use common::{create_request, start_counting_server, TestResponse};
+use raven::application::core_types::crawler::crawler::{
+    crawler_cached_client_impl, crawler_default_impl, HttpClientCache,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// start a keep-alive http server which counts accepted connections.
+fn start_server() -> (String, Arc<AtomicUsize>) {
+    start_counting_server(|_| TestResponse::text("ok"))
+}
+
+#[test]
+fn cached_client_should_reuse_connections() {
+    let request_num = 50;
+
+    // without cache: a new client (and connection) for every request
+    let (url, connection_count) = start_server();
+    let request = create_request(&url);
+    for _ in 0..request_num {
+        assert_eq!(crawler_default_impl(&request).unwrap().response_body, b"ok");
+    }
+    let default_connections = connection_count.load(Ordering::SeqCst);
+
+    // with cache: one client shared by all requests
+    let (url, connection_count) = start_server();
+    let request = create_request(&url);
+    let client_cache = HttpClientCache::new();
+    for _ in 0..request_num {
+        let result = crawler_cached_client_impl(&request, &client_cache).unwrap();
+        assert_eq!(result.response_body, b"ok");
+    }
+    let cached_connections = connection_count.load(Ordering::SeqCst);
+
+    assert_eq!(default_connections, request_num);
+    assert_eq!(cached_connections, 1);
+}
@@ -0,0 +1,142 @@
+extern crate flate2;
+extern crate raven;
+extern crate serde_json;
+
+mod common;
+
+use common::{create_request, create_task, start_local_server, TestApp, TestResponse};
+use flate2::read::MultiGzDecoder;
+use raven::application::core_types::persist::{
+    compression::{OutputCodec, OutputCompression},
+    json_lines::finish_json_lines,
+    PersistMethod,
+};
+use serde_json::Value;
+use std::fs::{read_dir, read_to_string, remove_dir_all, File};
+use std::io::Read;
+use std::thread;
+
+/// start a http server which returns json for "/text/{n}" and binary for "/binary".
+fn start_server() -> String {
+    start_local_server(|request| {
+        if request.path == "/binary" {
+            TestResponse::ok(vec![0xff, 0xfe, 0x00, 0x01])
+                .header("Content-Type", "application/octet-stream")
+        } else {
+            TestResponse::ok(format!("{{\"path\":\"{}\"}}", request.path))
+                .header("Content-Type", "application/json; charset=utf-8")
+        }
+    })
+}
+
+fn list_files(directory: &str) -> Vec<String> {
+    let mut files: Vec<String> = read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().to_str().unwrap().to_owned())
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn it_should_append_records_of_parallel_tasks_to_shared_file() {
+    // arrange
+    let address = start_server();
+    let directory = "/var/tmp/application/json_lines/parallel";
+    let _ = remove_dir_all(directory);
+    let persist_method = PersistMethod::JsonLines {
+        directory: directory.to_owned(),
+        prefix: "test".to_owned(),
+        max_file_size: 1024 * 1024,
+        compression: None,
+    };
+
+    // act
+    let handles: Vec<_> = (0..20)
+        .map(|n| {
+            let task = create_task(
+                create_request(&format!("{}/text/{}", address, n)),
+                vec![persist_method.clone()],
+            );
+            thread::spawn(move || task.execute_in(&TestApp::default()).unwrap())
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // assert
+    let files = list_files(directory);
+    assert_eq!(files.len(), 1);
+    assert!(files[0].ends_with(".jsonl"));
+
+    let content = read_to_string(&files[0]).unwrap();
+    let mut paths: Vec<String> = content
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .map(|record| {
+            assert_eq!(record["status"], 200);
+            assert_eq!(record["body_encoding"], "text");
+            assert_eq!(record["content_type"], "application/json; charset=utf-8");
+            assert_eq!(record["final_url"], record["url"]);
+            assert!(record["crawl_date"].is_string());
+            record["body"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    paths.sort();
+    let mut expected: Vec<String> = (0..20)
+        .map(|n| format!("{{\"path\":\"/text/{}\"}}", n))
+        .collect();
+    expected.sort();
+    assert_eq!(paths, expected);
+}
+
+#[test]
+fn it_should_write_compressed_records_to_rotating_files() {
+    // arrange
+    let address = start_server();
+    let directory = "/var/tmp/application/json_lines/compressed";
+    let _ = remove_dir_all(directory);
+    let persist_method = PersistMethod::JsonLines {
+        directory: directory.to_owned(),
+        prefix: "test".to_owned(),
+        max_file_size: 1,
+        compression: Some(OutputCompression {
+            codec: OutputCodec::Gzip,
+            level: None,
+            append_extension: true,
+        }),
+    };
+
+    // act
+    for path in &["/text/0", "/binary"] {
+        create_task(
+            create_request(&format!("{}{}", address, path)),
+            vec![persist_method.clone()],
+        )
+        .execute_in(&TestApp::default())
+        .unwrap();
+    }
+    finish_json_lines().unwrap();
+
+    // assert: each record is written to its own file, since max_file_size is exceeded.
+    let files = list_files(directory);
+    assert_eq!(files.len(), 2);
+    let records: Vec<Value> = files
+        .iter()
+        .map(|file| {
+            let mut line = String::new();
+            MultiGzDecoder::new(File::open(file).unwrap())
+                .read_to_string(&mut line)
+                .unwrap();
+            assert!(file.ends_with(".jsonl.gz"));
+            assert!(line.ends_with('\n'));
+            serde_json::from_str(&line).unwrap()
+        })
+        .collect();
+
+    assert_eq!(records[0]["body"], "{\"path\":\"/text/0\"}");
+    assert_eq!(records[1]["body"], "//4AAQ==");
+    assert_eq!(records[1]["body_encoding"], "base64");
+    assert_eq!(records[1]["method"], "Get");
+}
@@ -0,0 +1,212 @@
+extern crate postgres;
+extern crate raven;
+
+mod common;
+
+use common::{create_request, create_task, start_echo_server, TestApp};
+use postgres::{Client, NoTls};
+use raven::application::core_types::persist::{PersistError, PersistMethod};
+use raven::application::raven_crawl_task::CrawlTaskError;
+use std::fs::{create_dir_all, remove_dir_all};
+use std::net::TcpListener;
+use std::process::Command;
+use std::thread;
+
+/// a postgres server started by initdb and pg_ctl for the test, stopped on drop.
+struct LocalPostgres {
+    directory: String,
+    url: String,
+}
+
+impl LocalPostgres {
+    /// returns None if postgres is not installed.
+    fn start(name: &str) -> Option<LocalPostgres> {
+        let directory = format!("/var/tmp/application/postgres/{}", name);
+        let _ = remove_dir_all(&directory);
+        create_dir_all(&directory).unwrap();
+        // postgres refuses to run as root.
+        if is_root() {
+            Command::new("chown")
+                .args(["-R", "postgres", &directory])
+                .status()
+                .ok()?;
+        }
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let initialized = run_postgres_command(&[
+            "initdb",
+            "-D",
+            &format!("{}/data", directory),
+            "-U",
+            "postgres",
+            "-A",
+            "trust",
+        ])?;
+        if !initialized {
+            return None;
+        }
+        let started = run_postgres_command(&[
+            "pg_ctl",
+            "-D",
+            &format!("{}/data", directory),
+            "-o",
+            &format!("-p {} -k {} -c listen_addresses=127.0.0.1", port, directory),
+            "-l",
+            &format!("{}/postgres.log", directory),
+            "-w",
+            "start",
+        ])?;
+        if !started {
+            return None;
+        }
+
+        Some(LocalPostgres {
+            directory,
+            url: format!("host=127.0.0.1 port={} user=postgres dbname=postgres", port),
+        })
+    }
+}
+
+impl Drop for LocalPostgres {
+    fn drop(&mut self) {
+        let _ = run_postgres_command(&[
+            "pg_ctl",
+            "-D",
+            &format!("{}/data", self.directory),
+            "-m",
+            "immediate",
+            "stop",
+        ]);
+    }
+}
+
+fn is_root() -> bool {
+    Command::new("id")
+        .arg("-u")
+        .output()
+        .is_ok_and(|output| output.stdout == b"0\n")
+}
+
+/// run a command of postgres, as postgres user if the test runs as root.
+/// returns None if the command is not found.
+fn run_postgres_command(args: &[&str]) -> Option<bool> {
+    let mut command = if is_root() {
+        let mut command = Command::new("runuser");
+        command.args(["-u", "postgres", "--"]).args(args);
+        command
+    } else {
+        let mut command = Command::new(args[0]);
+        command.args(&args[1..]);
+        command
+    };
+    let output = command.output().ok()?;
+    // runuser exits with 127 if the command is not found.
+    if output.status.code() == Some(127) {
+        return None;
+    }
+    Some(output.status.success())
+}
+
+/// needs initdb and pg_ctl of postgres in PATH, and the postgres user if the test runs as root.
+#[test]
+#[ignore]
+fn it_should_upsert_rows_from_parallel_tasks() {
+    // arrange
+    let local_postgres = LocalPostgres::start("upsert").expect("postgres is not installed");
+    let address = start_echo_server();
+    let persist_method = PersistMethod::Postgres {
+        url: local_postgres.url.to_owned(),
+        table: "pages".to_owned(),
+        key: "page_{{final_host}}_{{status}}_{{content_hash}}".to_owned(),
+        batch_size: 4,
+        max_connections: 2,
+        max_retry: 3,
+    };
+
+    // act: "/page/0" is crawled twice, and the row is updated.
+    let handles: Vec<_> = (0..20)
+        .chain(0..1)
+        .map(|n| {
+            let task = create_task(
+                create_request(&format!("{}/page/{}", address, n)),
+                vec![persist_method.clone()],
+            );
+            thread::spawn(move || task.execute_in(&TestApp::default()).unwrap())
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // assert
+    let mut client = Client::connect(&local_postgres.url, NoTls).unwrap();
+    let count: i64 = client
+        .query_one("SELECT COUNT(*) FROM pages", &[])
+        .unwrap()
+        .get(0);
+    assert_eq!(count, 20);
+
+    let row = client
+        .query_one(
+            "SELECT key, url, status, metadata->'headers'->>'x-test', metadata->>'method',
+                content_type, crawl_date::TEXT, body
+                FROM pages WHERE body = $1",
+            &[&b"/page/3".to_vec()],
+        )
+        .unwrap();
+    let key: String = row.get(0);
+    let url: String = row.get(1);
+    let status: i32 = row.get(2);
+    let x_test: String = row.get(3);
+    let method: String = row.get(4);
+    let content_type: String = row.get(5);
+    let crawl_date: String = row.get(6);
+    let body: Vec<u8> = row.get(7);
+    assert!(key.starts_with("page_127.0.0.1_200_"));
+    assert_eq!(url, format!("{}/page/3", address));
+    assert_eq!(status, 200);
+    assert_eq!(x_test, "raven");
+    assert_eq!(method, "Get");
+    assert_eq!(content_type, "text/plain; charset=utf-8");
+    assert!(crawl_date.starts_with("20"));
+    assert_eq!(body, b"/page/3");
+}
+
+#[test]
+fn it_should_fail_after_retrying_to_connect() {
+    // arrange: nothing listens on the port.
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let address = start_echo_server();
+    let persist_method = PersistMethod::Postgres {
+        url: format!("host=127.0.0.1 port={} user=postgres", port),
+        table: "pages".to_owned(),
+        key: "{{final_url}}".to_owned(),
+        batch_size: 1,
+        max_connections: 1,
+        max_retry: 1,
+    };
+
+    // act
+    let task = create_task(
+        create_request(&format!("{}/page/0", address)),
+        vec![persist_method],
+    );
+    let result = task.execute_in(&TestApp::default());
+
+    // assert
+    match result.unwrap_err().result {
+        CrawlTaskError::PersistFailed { persist_errors, .. } => match &persist_errors[..] {
+            [PersistError::FailedToWritePostgres(_)] => {}
+            other => panic!("unexpected errors: {:?}", other),
+        },
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
@@ -0,0 +1,176 @@
+extern crate raven;
+
+mod common;
+
+use common::{start_echo_server, TestApp};
+use raven::application::core_types::crawler::{
+    proxy::{ProxyAuth, ProxySetting},
+    Crawler, CrawlerRequest,
+};
+use std::io::{copy, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// what a socks5 client sent in its handshake.
+#[derive(Debug, Clone, PartialEq)]
+struct SocksHandshake {
+    credentials: Option<(String, String)>,
+    destination: String,
+}
+
+/// start a socks5 proxy which relays connections to their destinations.
+/// returns its address as host:port and the handshakes it has received.
+fn start_socks5_server() -> (String, Arc<Mutex<Vec<SocksHandshake>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let handshakes = Arc::new(Mutex::new(vec![]));
+    let received = handshakes.clone();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Ok(stream) = stream {
+                let received = received.clone();
+                thread::spawn(move || {
+                    if let Some((handshake, upstream)) = accept_socks5(&stream) {
+                        received.lock().unwrap().push(handshake);
+                        relay(stream, upstream);
+                    }
+                });
+            }
+        }
+    });
+
+    (address, handshakes)
+}
+
+fn read_bytes(stream: &mut TcpStream, len: usize) -> Option<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    stream.read_exact(&mut bytes).ok().map(|_| bytes)
+}
+
+fn read_string(stream: &mut TcpStream) -> Option<String> {
+    let len = read_bytes(stream, 1)?[0] as usize;
+    read_bytes(stream, len).and_then(|bytes| String::from_utf8(bytes).ok())
+}
+
+/// negotiate no authentication or username/password authentication, then accept CONNECT.
+fn accept_socks5(stream: &TcpStream) -> Option<(SocksHandshake, TcpStream)> {
+    let mut stream = stream.try_clone().ok()?;
+    let greeting = read_bytes(&mut stream, 2)?;
+    let methods = read_bytes(&mut stream, greeting[1] as usize)?;
+
+    let credentials = if methods.contains(&0x02) {
+        stream.write_all(&[0x05, 0x02]).ok()?;
+        let _version = read_bytes(&mut stream, 1)?;
+        let username = read_string(&mut stream)?;
+        let password = read_string(&mut stream)?;
+        stream.write_all(&[0x01, 0x00]).ok()?;
+        Some((username, password))
+    } else {
+        stream.write_all(&[0x05, 0x00]).ok()?;
+        None
+    };
+
+    let request = read_bytes(&mut stream, 4)?;
+    let host = match request[3] {
+        0x01 => {
+            let ip = read_bytes(&mut stream, 4)?;
+            Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]).to_string()
+        }
+        0x03 => read_string(&mut stream)?,
+        _ => return None,
+    };
+    let port = read_bytes(&mut stream, 2)?;
+    let destination = format!("{}:{}", host, u16::from(port[0]) << 8 | u16::from(port[1]));
+
+    let upstream = TcpStream::connect(&destination).ok()?;
+    stream
+        .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+        .ok()?;
+    Some((
+        SocksHandshake {
+            credentials,
+            destination,
+        },
+        upstream,
+    ))
+}
+
+fn relay(client: TcpStream, upstream: TcpStream) {
+    let mut client_reader = client.try_clone().unwrap();
+    let mut upstream_writer = upstream.try_clone().unwrap();
+    thread::spawn(move || copy(&mut client_reader, &mut upstream_writer));
+    let (mut upstream_reader, mut client_writer) = (upstream, client);
+    let _ = copy(&mut upstream_reader, &mut client_writer);
+}
+
+fn create_request(url: &str, proxy: ProxySetting) -> CrawlerRequest {
+    CrawlerRequest {
+        proxy: Some(proxy),
+        ..common::create_request(url)
+    }
+}
+
+#[test]
+fn it_should_crawl_through_socks5_proxy() {
+    // arrange
+    let address = start_echo_server();
+    let (proxy_address, handshakes) = start_socks5_server();
+    let request = create_request(
+        &format!("{}/socks5", address),
+        ProxySetting {
+            http: Some(format!("socks5://{}", proxy_address)),
+            https: None,
+            auth: None,
+            no_proxy: vec![],
+            pool: None,
+        },
+    );
+
+    // act
+    let result = TestApp::default().crawl(&request).unwrap();
+
+    // assert
+    assert_eq!(result.response_body, b"/socks5".to_vec());
+    let handshakes = handshakes.lock().unwrap();
+    assert_eq!(handshakes.len(), 1);
+    assert_eq!(handshakes[0].credentials, None);
+    assert_eq!(
+        handshakes[0].destination,
+        address.trim_start_matches("http://")
+    );
+}
+
+#[test]
+fn it_should_authenticate_to_socks5h_proxy() {
+    // arrange
+    let address = start_echo_server();
+    let (proxy_address, handshakes) = start_socks5_server();
+    let request = create_request(
+        &format!("{}/socks5h", address.replace("127.0.0.1", "localhost")),
+        ProxySetting {
+            http: Some(format!("socks5h://{}", proxy_address)),
+            https: None,
+            auth: Some(ProxyAuth {
+                username: "raven".to_owned(),
+                password: "secret".to_owned(),
+            }),
+            no_proxy: vec![],
+            pool: None,
+        },
+    );
+
+    // act
+    let result = TestApp::default().crawl(&request).unwrap();
+
+    // assert: the host name is resolved by the proxy.
+    assert_eq!(result.response_body, b"/socks5h".to_vec());
+    let handshakes = handshakes.lock().unwrap();
+    assert_eq!(handshakes.len(), 1);
+    assert_eq!(
+        handshakes[0].credentials,
+        Some(("raven".to_owned(), "secret".to_owned()))
+    );
+    assert!(handshakes[0].destination.starts_with("localhost:"));
+}
@@ -0,0 +1,228 @@
+#[macro_use]
+extern crate raven;
+
+mod common;
+
+use common::{create_task, local_file, start_local_server, TestApp, TestRequest, TestResponse};
+use raven::application::core_types::crawler::{
+    redirect::{RedirectPolicy, RedirectSetting},
+    request::Method,
+    Crawler, CrawlerError, CrawlerRequest,
+};
+use std::fs::read;
+
+/// start a http server which redirects "/start" -> "/middle" -> "/final".
+/// "/final" returns the method of the request as body,
+/// and "/headers" returns the received headers checked by the tests.
+fn start_server() -> String {
+    start_local_server(|request| {
+        let port = request
+            .header("host")
+            .and_then(|host| host.rsplit(':').next())
+            .unwrap_or("")
+            .to_owned();
+        let redirect = |status: &str, location: String| {
+            TestResponse::new(status, "").header("Location", &location)
+        };
+        match request.path.as_str() {
+            "/start" => redirect("302 Found", "/middle".to_owned()),
+            "/middle" => redirect(
+                "301 Moved Permanently",
+                format!("http://127.0.0.1:{}/final", port),
+            ),
+            "/keep_method" => redirect("307 Temporary Redirect", "final".to_owned()),
+            "/other_host" => redirect("302 Found", format!("http://localhost:{}/final", port)),
+            "/loop" => redirect("302 Found", "/loop".to_owned()),
+            "/to_other_host" => redirect("302 Found", format!("http://localhost:{}/headers", port)),
+            "/to_same_host" => redirect("302 Found", "/headers".to_owned()),
+            "/headers" => TestResponse::text(received_headers(request)),
+            _ => TestResponse::text(request.method.clone()),
+        }
+    })
+}
+
+fn received_headers(request: &TestRequest) -> String {
+    ["authorization", "cookie", "x-api-key", "user-agent"]
+        .iter()
+        .filter_map(|name| {
+            request
+                .header(name)
+                .map(|value| format!("{}: {}\n", name, value))
+        })
+        .collect()
+}
+
+fn create_request(url: &str, redirect: Option<RedirectSetting>) -> CrawlerRequest {
+    CrawlerRequest {
+        redirect,
+        ..common::create_request(url)
+    }
+}
+
+fn redirect_setting(policy: RedirectPolicy, max_hops: u8) -> Option<RedirectSetting> {
+    Some(RedirectSetting {
+        policy,
+        max_hops,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn it_should_record_redirect_chain() {
+    // arrange
+    let address = start_server();
+    let mut request = create_request(&format!("{}/start", address), None);
+    request.method = Method::Post;
+    request.body_params = hashmap!("key".to_owned() => "value".to_owned());
+
+    // act
+    let result = TestApp::default().crawl(&request).unwrap();
+
+    // assert
+    assert_eq!(result.response_status, 200);
+    assert_eq!(result.final_url, format!("{}/final", address));
+    assert_eq!(
+        result.redirect_chain,
+        vec![format!("{}/start", address), format!("{}/middle", address)]
+    );
+    // POST becomes GET after 302 and 301.
+    assert_eq!(result.response_body, b"GET");
+}
+
+#[test]
+fn it_should_keep_method_on_307() {
+    // arrange
+    let address = start_server();
+    let mut request = create_request(&format!("{}/keep_method", address), None);
+    request.method = Method::Post;
+    request.body_params = hashmap!("key".to_owned() => "value".to_owned());
+
+    // act
+    let result = TestApp::default().crawl(&request).unwrap();
+
+    // assert
+    assert_eq!(result.final_url, format!("{}/final", address));
+    assert_eq!(result.response_body, b"POST");
+}
+
+#[test]
+fn it_should_drop_credentials_on_redirect_to_another_host() {
+    // arrange
+    let address = start_server();
+    let mut header = hashmap!("Authorization".to_owned() => "Bearer raven".to_owned());
+    header.insert("Cookie".to_owned(), "session=raven".to_owned());
+    header.insert("X-Api-Key".to_owned(), "raven".to_owned());
+    header.insert("User-Agent".to_owned(), "raven".to_owned());
+    let redirect = Some(RedirectSetting {
+        sensitive_headers: vec!["x-api-key".to_owned()],
+        ..Default::default()
+    });
+    let mut other_host_request = create_request(&format!("{}/to_other_host", address), redirect);
+    other_host_request.header = header;
+    let mut same_host_request = other_host_request.clone();
+    same_host_request.url = format!("{}/to_same_host", address);
+
+    // act
+    let other_host_result = TestApp::default().crawl(&other_host_request).unwrap();
+    let same_host_result = TestApp::default().crawl(&same_host_request).unwrap();
+
+    // assert
+    assert!(other_host_result.final_url.starts_with("http://localhost:"));
+    assert_eq!(other_host_result.response_body, b"user-agent: raven\n");
+    assert_eq!(
+        String::from_utf8(same_host_result.response_body).unwrap(),
+        "authorization: Bearer raven\ncookie: session=raven\nx-api-key: raven\nuser-agent: raven\n"
+    );
+}
+
+#[test]
+fn it_should_return_redirect_response_if_not_followed() {
+    // arrange
+    let address = start_server();
+    let none_request = create_request(
+        &format!("{}/start", address),
+        redirect_setting(RedirectPolicy::None, 10),
+    );
+    let same_host_request = create_request(
+        &format!("{}/other_host", address),
+        redirect_setting(RedirectPolicy::SameHost, 10),
+    );
+
+    // act
+    let none_result = TestApp::default().crawl(&none_request).unwrap();
+    let same_host_result = TestApp::default().crawl(&same_host_request).unwrap();
+
+    // assert
+    assert_eq!(none_result.response_status, 302);
+    assert_eq!(none_result.final_url, format!("{}/start", address));
+    assert_eq!(none_result.redirect_chain, Vec::<String>::new());
+    assert_eq!(same_host_result.response_status, 302);
+    assert_eq!(
+        same_host_result.final_url,
+        format!("{}/other_host", address)
+    );
+}
+
+#[test]
+fn it_should_fail_when_redirects_exceed_max_hops() {
+    // arrange
+    let address = start_server();
+    let request = create_request(
+        &format!("{}/loop", address),
+        redirect_setting(RedirectPolicy::Follow, 3),
+    );
+
+    // act
+    let result = TestApp::default().crawl(&request);
+
+    // assert
+    let loop_url = format!("{}/loop", address);
+    assert_eq!(
+        result,
+        Err(CrawlerError::TooManyRedirects {
+            max_hops: 3,
+            redirect_chain: vec![loop_url; 5],
+        })
+    );
+}
+
+#[test]
+fn it_should_embed_final_url_to_output_path() {
+    // arrange
+    let address = start_server();
+    let task = create_task(
+        create_request(&format!("{}/start", address), None),
+        vec![local_file(
+            "/var/tmp/application/redirect/{{final_url}}.txt",
+        )],
+    );
+
+    // act
+    task.execute_in(&TestApp::default()).unwrap();
+
+    // assert
+    let expected_path = format!(
+        "/var/tmp/application/redirect/http%3A%2F%2F127.0.0.1%3A{}%2Ffinal.txt",
+        address.rsplit(':').next().unwrap()
+    );
+    assert_eq!(read(expected_path).unwrap(), b"GET");
+}
+
+#[test]
+fn it_should_embed_response_properties_to_output_path() {
+    // arrange
+    let address = start_server();
+    let task = create_task(
+        create_request(&format!("{}/start", address), None),
+        vec![local_file(
+            "/var/tmp/application/redirect/{{final_host}}/{{status}}/{{charset}}/{{content_hash}}.{{ext}}",
+        )],
+    );
+
+    // act
+    task.execute_in(&TestApp::default()).unwrap();
+
+    // assert
+    let expected_path = "/var/tmp/application/redirect/127.0.0.1/200/utf-8/14e30cd163c732912e048c4c837e15c4e90c062ebb795ab947d57706e2d10dd8.txt";
+    assert_eq!(read(expected_path).unwrap(), b"GET");
+}
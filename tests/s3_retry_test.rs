@@ -0,0 +1,105 @@
+extern crate raven;
+
+mod common;
+
+use common::{start_local_server, TestRequest, TestResponse};
+use raven::application::core_types::persist::s3::{
+    write_to_s3, S3ClientConfig, S3Credentials, S3ObjectOptions, S3UploadSetting,
+    S3WriteFileRequest, S3WriterError,
+};
+use raven::mime::Mime;
+use std::sync::{Arc, Mutex};
+
+/// requests received by the fake s3 server.
+type ReceivedRequests = Arc<Mutex<Vec<TestRequest>>>;
+
+/// start a fake s3 server which responds 503 to the first `failures` requests, then 200.
+fn start_fake_s3(failures: usize) -> (String, ReceivedRequests) {
+    let received: ReceivedRequests = Arc::new(Mutex::new(vec![]));
+    let server_received = received.clone();
+    let address = start_local_server(move |request| {
+        let count = {
+            let mut received = server_received.lock().unwrap();
+            received.push(request.clone());
+            received.len()
+        };
+        if count <= failures {
+            TestResponse::new(
+                "503 Slow Down",
+                "<Error><Code>SlowDown</Code><Message>Please reduce your request rate.</Message></Error>",
+            )
+            .header("Content-Type", "application/xml")
+        } else {
+            TestResponse::ok("").header("ETag", "\"etag\"")
+        }
+    });
+
+    (address, received)
+}
+
+fn put(address: &str, upload_setting: &S3UploadSetting) -> Result<(), S3WriterError> {
+    write_to_s3(S3WriteFileRequest {
+        client_config: S3ClientConfig {
+            region: "us-east-1".to_owned(),
+            endpoint: Some(address.to_owned()),
+            credentials: Some(S3Credentials::Static {
+                access_key_id: "raven".to_owned(),
+                secret_access_key: "secret".to_owned(),
+                session_token: None,
+            }),
+        },
+        bucket_name: "raven-test".to_owned(),
+        object_key: "retry.txt".to_owned(),
+        object_options: &S3ObjectOptions::default(),
+        upload_setting,
+        content_type: &Mime::ApplicationOctetStream,
+        content_encoding: None,
+        content: b"",
+    })
+}
+
+#[test]
+fn it_should_retry_put_on_server_errors() {
+    // arrange
+    let (address, received) = start_fake_s3(2);
+    let upload_setting = S3UploadSetting {
+        backoff_millis: 10,
+        ..Default::default()
+    };
+
+    // act
+    let result = put(&address, &upload_setting);
+
+    // assert
+    assert_eq!(result, Ok(()));
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 3);
+    assert_eq!(received[2].method, "PUT");
+    assert_eq!(received[2].path, "/raven-test/retry.txt");
+    // md5 of empty content.
+    assert_eq!(
+        received[2].header("content-md5"),
+        Some("1B2M2Y8AsgTpgAmY7PhCfg==")
+    );
+}
+
+#[test]
+fn it_should_fail_when_retries_are_exhausted() {
+    // arrange
+    let (address, received) = start_fake_s3(10);
+    let upload_setting = S3UploadSetting {
+        max_retry: 1,
+        backoff_millis: 10,
+        verify_checksum: false,
+        ..Default::default()
+    };
+
+    // act
+    let result = put(&address, &upload_setting);
+
+    // assert
+    assert!(result.is_err());
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 2);
+    assert_eq!(received[1].header("content-md5"), None);
+}
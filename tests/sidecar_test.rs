@@ -0,0 +1,166 @@
+extern crate raven;
+extern crate serde_json;
+
+mod common;
+
+use common::{create_request, create_task, start_echo_server, TestApp};
+use raven::application::core_types::{
+    crawler::CrawlerRequest,
+    persist::{
+        compression::{OutputCodec, OutputCompression},
+        local_file::{LocalFileOptions, OverwritePolicy},
+        sidecar::MetadataSidecar,
+        PersistMethod,
+    },
+};
+use raven::application::raven_crawl_task::RavenCrawlTask;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{read, remove_dir_all, remove_file};
+use std::path::Path;
+
+fn create_sidecar_task(url: &str, file_path: &str, stream_body: bool) -> RavenCrawlTask {
+    create_sidecar_task_with(url, file_path, stream_body, OverwritePolicy::Overwrite)
+}
+
+fn create_sidecar_task_with(
+    url: &str,
+    file_path: &str,
+    stream_body: bool,
+    overwrite: OverwritePolicy,
+) -> RavenCrawlTask {
+    let mut query_params = HashMap::new();
+    query_params.insert("page".to_owned(), "1".to_owned());
+    create_task(
+        CrawlerRequest {
+            query_params,
+            stream_body,
+            ..create_request(url)
+        },
+        vec![PersistMethod::LocalFile {
+            file_path: file_path.to_owned(),
+            file_options: LocalFileOptions {
+                overwrite,
+                ..Default::default()
+            },
+            compression: Some(OutputCompression {
+                codec: OutputCodec::Gzip,
+                level: None,
+                append_extension: true,
+            }),
+            sidecar: Some(MetadataSidecar::Json),
+            content_addressed: None,
+        }],
+    )
+}
+
+fn assert_sidecar(sidecar_path: &str, url: &str) {
+    let metadata: Value = serde_json::from_slice(&read(sidecar_path).unwrap()).unwrap();
+    assert_eq!(metadata["request"]["url"], url);
+    assert_eq!(metadata["request"]["query_params"]["page"], "1");
+    assert_eq!(metadata["final_url"], format!("{}?page=1", url));
+    assert_eq!(metadata["status"], 200);
+    assert_eq!(metadata["headers"]["x-test"], "raven");
+    assert_eq!(metadata["content_type"], "text/plain; charset=utf-8");
+    assert!(metadata["crawl_date"].as_str().unwrap().starts_with("20"));
+    assert_eq!(metadata["retry_count"], 0);
+    // sha256 of "/page?page=1", the body before compression.
+    assert_eq!(
+        metadata["content_hash"],
+        "cdfec058cb5a4559033e451b95f8040aa05f746d891b65293f0cb83d99f18e6d"
+    );
+}
+
+#[test]
+fn it_should_write_json_sidecar_next_to_body() {
+    // arrange
+    let address = start_echo_server();
+    let dir = "/var/tmp/application/sidecar/buffered";
+    let _ = remove_dir_all(dir);
+    let url = format!("{}/page", address);
+    let task = create_sidecar_task(&url, &format!("{}/page.txt", dir), false);
+
+    // act
+    let result = task.execute_in(&TestApp::default()).unwrap();
+
+    // assert
+    assert_eq!(result.result.persist_errors, vec![]);
+    assert!(read(format!("{}/page.txt.gz", dir)).is_ok());
+    assert_sidecar(&format!("{}/page.txt.gz.meta.json", dir), &url);
+}
+
+#[test]
+fn it_should_write_json_sidecar_of_streamed_body() {
+    // arrange
+    let address = start_echo_server();
+    let dir = "/var/tmp/application/sidecar/streamed";
+    let _ = remove_dir_all(dir);
+    let url = format!("{}/page", address);
+    let task = create_sidecar_task(&url, &format!("{}/page.txt", dir), true);
+
+    // act
+    let result = task.execute_in(&TestApp::default()).unwrap();
+
+    // assert
+    assert_eq!(result.result.persist_errors, vec![]);
+    assert!(read(format!("{}/page.txt.gz", dir)).is_ok());
+    assert_sidecar(&format!("{}/page.txt.gz.meta.json", dir), &url);
+}
+
+#[test]
+fn it_should_write_json_sidecar_next_to_suffixed_body() {
+    for stream_body in [false, true] {
+        // arrange
+        let address = start_echo_server();
+        let dir = format!("/var/tmp/application/sidecar/suffixed/{}", stream_body);
+        let _ = remove_dir_all(&dir);
+        let url = format!("{}/page", address);
+        let file_path = format!("{}/page.txt", dir);
+        let task = || {
+            create_sidecar_task_with(
+                &url,
+                &file_path,
+                stream_body,
+                OverwritePolicy::SuffixWithCounter,
+            )
+        };
+
+        // act
+        let first = task().execute_in(&TestApp::default()).unwrap();
+        let second = task().execute_in(&TestApp::default()).unwrap();
+
+        // assert
+        assert_eq!(first.result.persist_errors, vec![]);
+        assert_eq!(second.result.persist_errors, vec![]);
+        assert_sidecar(&format!("{}/page.txt.gz.meta.json", dir), &url);
+        assert!(read(format!("{}/page.txt_1.gz", dir)).is_ok());
+        assert_sidecar(&format!("{}/page.txt_1.gz.meta.json", dir), &url);
+        assert!(!Path::new(&format!("{}/page.txt.gz_1.meta.json", dir)).exists());
+    }
+}
+
+#[test]
+fn it_should_not_write_json_sidecar_of_skipped_body() {
+    for stream_body in [false, true] {
+        // arrange
+        let address = start_echo_server();
+        let dir = format!("/var/tmp/application/sidecar/skipped/{}", stream_body);
+        let _ = remove_dir_all(&dir);
+        let url = format!("{}/page", address);
+        let file_path = format!("{}/page.txt", dir);
+        let sidecar_path = format!("{}/page.txt.gz.meta.json", dir);
+        let task = || {
+            create_sidecar_task_with(&url, &file_path, stream_body, OverwritePolicy::SkipIfExists)
+        };
+        task().execute_in(&TestApp::default()).unwrap();
+        remove_file(&sidecar_path).unwrap();
+
+        // act
+        let result = task().execute_in(&TestApp::default()).unwrap();
+
+        // assert
+        assert_eq!(result.result.persist_errors, vec![]);
+        assert!(read(format!("{}/page.txt.gz", dir)).is_ok());
+        assert!(!Path::new(&sidecar_path).exists());
+    }
+}
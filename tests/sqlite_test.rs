@@ -0,0 +1,87 @@
+extern crate raven;
+extern crate rusqlite;
+
+mod common;
+
+use common::{create_request, create_task, start_echo_server, TestApp};
+use raven::application::core_types::persist::PersistMethod;
+use raven::application::raven_crawl_task::RavenCrawlTask;
+use rusqlite::{Connection, NO_PARAMS};
+use std::fs::{create_dir_all, remove_file};
+use std::thread;
+
+fn create_sqlite_task(url: &str, path: &str) -> RavenCrawlTask {
+    create_task(
+        create_request(url),
+        vec![PersistMethod::Sqlite {
+            path: path.to_owned(),
+            table: "pages".to_owned(),
+            key: "page_{{final_host}}_{{status}}_{{content_hash}}".to_owned(),
+        }],
+    )
+}
+
+#[test]
+fn it_should_upsert_rows_from_parallel_tasks() {
+    // arrange
+    let address = start_echo_server();
+    let path = "/var/tmp/application/sqlite/crawl.db";
+    create_dir_all("/var/tmp/application/sqlite").unwrap();
+    let _ = remove_file(path);
+
+    // act: "/page/0" is crawled twice, and the row is replaced.
+    let urls: Vec<String> = (0..10)
+        .chain(0..1)
+        .map(|n| format!("{}/page/{}", address, n))
+        .collect();
+    let handles: Vec<_> = urls
+        .into_iter()
+        .map(|url| {
+            let task = create_sqlite_task(&url, path);
+            thread::spawn(move || task.execute_in(&TestApp::default()).unwrap())
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // assert
+    let connection = Connection::open(path).unwrap();
+    let count: i64 = connection
+        .query_row("SELECT COUNT(*) FROM pages", NO_PARAMS, |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 10);
+
+    let (key, url, status, headers, content_type, crawl_date, body): (
+        String,
+        String,
+        i64,
+        String,
+        String,
+        String,
+        Vec<u8>,
+    ) = connection
+        .query_row(
+            "SELECT key, url, status, headers, content_type, crawl_date, body FROM pages WHERE body = ?1",
+            &[&b"/page/3".to_vec()],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                ))
+            },
+        )
+        .unwrap();
+    assert!(key.starts_with("page_127.0.0.1_200_"));
+    assert_eq!(url, format!("{}/page/3", address));
+    assert_eq!(status, 200);
+    assert!(headers.contains("\"x-test\":\"raven\""));
+    assert_eq!(content_type, "text/plain; charset=utf-8");
+    assert!(crawl_date.contains('T'));
+    assert_eq!(body, b"/page/3");
+}
@@ -0,0 +1,101 @@
+extern crate serde_json;
+
+mod common;
+
+use common::{start_local_server, TestResponse};
+use serde_json::Value;
+use std::fs::write;
+use std::process::Command;
+
+/// start a http server which returns the path repeated as body.
+/// a large body, so that writes of concurrent tasks would interleave without the lock.
+fn start_server() -> String {
+    start_local_server(|request| TestResponse::text(request.path.repeat(10000)))
+}
+
+/// run raven with the output and returns what it writes to stdout.
+fn run_raven(name: &str, address: &str, output: &str) -> Vec<u8> {
+    let config_path = format!("/var/tmp/application/stdout_{}.yaml", name);
+    let config = format!(
+        r#"
+name: "stdout test"
+request:
+  url: "{}/{{{{id}}}}"
+  vars:
+    id: "[1..20]"
+  method: Get
+  timeout_in_seconds: 5
+
+output:
+  - stdout:
+{}
+
+max_threads: 8
+
+log:
+  file:
+    path: "/var/tmp/application.log"
+    level: "debug"
+"#,
+        address, output
+    );
+    std::fs::create_dir_all("/var/tmp/application").unwrap();
+    write(&config_path, config).unwrap();
+
+    let result = Command::new(env!("CARGO_BIN_EXE_raven"))
+        .arg(&config_path)
+        .output()
+        .unwrap();
+    assert!(result.status.success());
+    result.stdout
+}
+
+#[test]
+fn it_should_write_json_lines_records_to_stdout() {
+    // arrange
+    let address = start_server();
+
+    // act
+    let stdout = run_raven("json_lines", &address, "      format: json_lines");
+
+    // assert
+    let mut ids: Vec<u32> = String::from_utf8(stdout)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let record: Value = serde_json::from_str(line).unwrap();
+            let id: u32 = record["url"]
+                .as_str()
+                .unwrap()
+                .rsplit('/')
+                .next()
+                .unwrap()
+                .parse()
+                .unwrap();
+            assert_eq!(record["body"], format!("/{}", id).repeat(10000));
+            id
+        })
+        .collect();
+    ids.sort();
+    assert_eq!(ids, (1..=20).collect::<Vec<u32>>());
+}
+
+#[test]
+fn it_should_write_delimited_bodies_to_stdout() {
+    // arrange
+    let address = start_server();
+
+    // act
+    let stdout = run_raven("raw", &address, "      delimiter: \"\\0\"");
+
+    // assert
+    let stdout = String::from_utf8(stdout).unwrap();
+    assert!(stdout.ends_with('\0'));
+    let mut bodies: Vec<&str> = stdout.trim_end_matches('\0').split('\0').collect();
+    bodies.sort();
+    let mut expected: Vec<String> = (1..=20)
+        .map(|id| format!("/{}", id).repeat(10000))
+        .collect();
+    expected.sort();
+    assert_eq!(bodies, expected);
+}
@@ -0,0 +1,103 @@
+extern crate raven;
+
+mod common;
+
+use common::{create_request, create_task, local_file, start_local_server, TestApp, TestResponse};
+use raven::application::core_types::crawler::{encoding::Encoding, CrawlerError, CrawlerRequest};
+use raven::application::raven_crawl_task::{CrawlTaskError, RavenCrawlTask};
+use raven::charset::{Charset, ConversionMode};
+use std::fs::read;
+
+static JAPANESE_TEXT: &'static str = "大きなレスポンスをストリーミングで保存する。";
+
+/// start a http server which returns a large body for "/large" and shift_jis text for "/sjis".
+fn start_server() -> String {
+    start_local_server(|request| match request.path.as_str() {
+        "/large" => {
+            TestResponse::ok(large_body()).header("Content-Type", "application/octet-stream")
+        }
+        _ => {
+            TestResponse::ok(Charset::Utf8.convert_to(&Charset::ShiftJis, JAPANESE_TEXT.as_bytes()))
+                .header("Content-Type", "text/plain; charset=Shift_JIS")
+        }
+    })
+}
+
+fn large_body() -> Vec<u8> {
+    (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect()
+}
+
+fn create_streaming_task(url: &str, file_path: &str) -> RavenCrawlTask {
+    create_task(
+        CrawlerRequest {
+            stream_body: true,
+            ..create_request(url)
+        },
+        vec![local_file(file_path)],
+    )
+}
+
+#[test]
+fn it_should_stream_body_to_local_file() {
+    // arrange
+    let address = start_server();
+    let file_path = "/var/tmp/application/streaming/large.bin";
+    let task = create_streaming_task(&format!("{}/large", address), file_path);
+
+    // act
+    let result = task.execute_in(&TestApp::default()).unwrap();
+
+    // assert
+    assert_eq!(result.result.crawler_result.response_body, Vec::<u8>::new());
+    assert_eq!(result.result.persist_errors, vec![]);
+    assert_eq!(read(file_path).unwrap(), large_body());
+}
+
+#[test]
+fn it_should_convert_charset_while_streaming() {
+    // arrange
+    let address = start_server();
+    let file_path = "/var/tmp/application/streaming/sjis.txt";
+    let mut task = create_streaming_task(&format!("{}/sjis", address), file_path);
+    task.request.encoding_setting = Some(Encoding {
+        input: Some(Charset::ShiftJis),
+        output: Charset::Utf8,
+        sniff: true,
+        statistical_detection: false,
+        conversion_mode: ConversionMode::Lenient,
+    });
+
+    // act
+    let result = task.execute_in(&TestApp::default()).unwrap();
+
+    // assert
+    assert!(result
+        .result
+        .crawler_result
+        .has_same_charset(&Charset::Utf8));
+    assert_eq!(read(file_path).unwrap(), JAPANESE_TEXT.as_bytes());
+}
+
+#[test]
+fn it_should_abort_when_body_exceeds_max_body_size() {
+    // arrange
+    let address = start_server();
+    let file_path = "/var/tmp/application/streaming/too_large.bin";
+    let mut streaming_task = create_streaming_task(&format!("{}/large", address), file_path);
+    streaming_task.request.max_body_size = Some(1024 * 1024);
+    let mut buffering_task = streaming_task.clone();
+    buffering_task.request.stream_body = false;
+
+    // act
+    let streaming_result = streaming_task.execute_in(&TestApp::default());
+    let buffering_result = buffering_task.execute_in(&TestApp::default());
+
+    // assert
+    let expected_error = CrawlTaskError::CrawlerFailed(CrawlerError::BodyTooLarge {
+        max_body_size: 1024 * 1024,
+        received_size: 3 * 1024 * 1024,
+    });
+    assert_eq!(streaming_result.unwrap_err().result, expected_error);
+    assert_eq!(buffering_result.unwrap_err().result, expected_error);
+    assert!(read(file_path).is_err());
+}
@@ -0,0 +1,241 @@
+extern crate flate2;
+#[macro_use]
+extern crate raven;
+
+mod common;
+
+use common::{create_request, create_task, start_local_server, TestApp, TestResponse};
+use flate2::read::MultiGzDecoder;
+use raven::application::core_types::{
+    crawler::{
+        proxy::{ProxyAuth, ProxySetting},
+        CrawlerRequest,
+    },
+    persist::{warc::sha1_digest, PersistMethod},
+};
+use raven::application::raven_crawl_task::RavenCrawlTask;
+use std::collections::HashMap;
+use std::fs::{read_dir, remove_dir_all, File};
+use std::io::{BufRead, BufReader, Read};
+use std::sync::{Arc, Mutex};
+
+static HTML: &'static str = "<html><body>warcに保存する</body></html>";
+
+/// start a http server which returns html for any path.
+fn start_server() -> String {
+    start_local_server(|_| {
+        TestResponse::ok(HTML)
+            .header("Content-Type", "text/html; charset=utf-8")
+            .header("X-Test", "warc")
+    })
+}
+
+fn create_warc_task(url: &str, directory: &str) -> RavenCrawlTask {
+    create_task(
+        CrawlerRequest {
+            header: hashmap!("User-Agent".to_owned() => "raven".to_owned()),
+            query_params: hashmap!("q".to_owned() => "1".to_owned()),
+            ..create_request(url)
+        },
+        vec![PersistMethod::Warc {
+            directory: directory.to_owned(),
+            prefix: "test".to_owned(),
+            max_file_size: 1,
+        }],
+    )
+}
+
+struct ParsedRecord {
+    headers: HashMap<String, String>,
+    block: Vec<u8>,
+}
+
+/// parse all records of a gzipped warc file.
+fn parse_warc_file(path: &str) -> Vec<ParsedRecord> {
+    let mut reader = BufReader::new(MultiGzDecoder::new(File::open(path).unwrap()));
+    let mut records = vec![];
+    loop {
+        let mut version = String::new();
+        if reader.read_line(&mut version).unwrap() == 0 {
+            return records;
+        }
+        assert_eq!(version, "WARC/1.0\r\n");
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            let (name, value) = line.trim_end().split_at(line.find(':').unwrap());
+            headers.insert(name.to_owned(), value[1..].trim().to_owned());
+        }
+
+        let content_length: usize = headers["Content-Length"].parse().unwrap();
+        let mut block = vec![0u8; content_length];
+        reader.read_exact(&mut block).unwrap();
+        let mut separator = [0u8; 4];
+        reader.read_exact(&mut separator).unwrap();
+        assert_eq!(&separator, b"\r\n\r\n");
+
+        records.push(ParsedRecord { headers, block });
+    }
+}
+
+#[test]
+fn it_should_write_request_and_response_records_to_rotating_warc_files() {
+    // arrange
+    let address = start_server();
+    let directory = "/var/tmp/application/warc";
+    let _ = remove_dir_all(directory);
+
+    // act
+    for path in &["first", "second"] {
+        create_warc_task(&format!("{}/{}", address, path), directory)
+            .execute_in(&TestApp::default())
+            .unwrap();
+    }
+
+    // assert: each exchange is written to its own file, since max_file_size is exceeded.
+    let mut files: Vec<String> = read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().to_str().unwrap().to_owned())
+        .collect();
+    files.sort();
+    assert_eq!(files.len(), 2);
+    assert!(files[0].ends_with(".warc.gz"));
+
+    let records = parse_warc_file(&files[0]);
+    let types: Vec<&str> = records
+        .iter()
+        .map(|record| record.headers["WARC-Type"].as_str())
+        .collect();
+    assert_eq!(types, vec!["warcinfo", "request", "response"]);
+
+    let (warcinfo, request, response) = (&records[0], &records[1], &records[2]);
+    let target_uri = format!("{}/first?q=1", address);
+    let host = address.trim_start_matches("http://");
+    assert_eq!(request.headers["WARC-Target-URI"], target_uri);
+    assert_eq!(response.headers["WARC-Target-URI"], target_uri);
+    assert_eq!(
+        request.headers["WARC-Warcinfo-ID"],
+        warcinfo.headers["WARC-Record-ID"]
+    );
+    assert_eq!(
+        response.headers["WARC-Concurrent-To"],
+        request.headers["WARC-Record-ID"]
+    );
+    assert_eq!(
+        response.headers["Content-Type"],
+        "application/http; msgtype=response"
+    );
+    assert_eq!(
+        response.headers["WARC-Payload-Digest"],
+        sha1_digest(HTML.as_bytes())
+    );
+    assert_eq!(
+        response.headers["WARC-Block-Digest"],
+        sha1_digest(&response.block)
+    );
+    assert!(response.headers["WARC-Date"].ends_with('Z'));
+
+    let request_block = String::from_utf8(request.block.clone()).unwrap();
+    assert!(request_block.starts_with(&format!("GET /first?q=1 HTTP/1.1\r\nhost: {}\r\n", host)));
+    assert!(request_block.contains("user-agent: raven\r\n"));
+
+    let response_block = String::from_utf8(response.block.clone()).unwrap();
+    assert!(response_block.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response_block.contains("x-test: warc\r\n"));
+    assert!(response_block.ends_with(&format!(
+        "content-type: text/html; charset=utf-8\r\ncontent-length: {}\r\n\r\n{}",
+        HTML.len(),
+        HTML
+    )));
+
+    let second_records = parse_warc_file(&files[1]);
+    assert_eq!(second_records.len(), 3);
+    assert_eq!(
+        second_records[2].headers["WARC-Target-URI"],
+        format!("{}/second?q=1", address)
+    );
+}
+
+#[test]
+fn it_should_write_headers_sent_with_final_hop_to_request_record() {
+    // arrange
+    // a http proxy, which redirects "/redirect" to "/final" of another origin.
+    let received = Arc::new(Mutex::new(vec![]));
+    let received_by_proxy = received.clone();
+    let proxy_address = start_local_server(move |request| {
+        received_by_proxy.lock().unwrap().push(
+            request
+                .header("proxy-authorization")
+                .map(|value| value.to_owned()),
+        );
+        if request.path.ends_with("/redirect") {
+            let location = request
+                .path
+                .replace("127.0.0.1", "localhost")
+                .replace("/redirect", "/final");
+            TestResponse::new("302 Found", "").header("Location", &location)
+        } else {
+            TestResponse::ok(HTML).header("Content-Type", "text/html; charset=utf-8")
+        }
+    });
+    let directory = "/var/tmp/application/warc_final_hop";
+    let _ = remove_dir_all(directory);
+    let task = create_task(
+        CrawlerRequest {
+            header: hashmap!(
+                "Authorization".to_owned() => "Bearer raven".to_owned(),
+                "User-Agent".to_owned() => "raven".to_owned()
+            ),
+            proxy: Some(ProxySetting {
+                http: Some(proxy_address.clone()),
+                https: None,
+                auth: Some(ProxyAuth {
+                    username: "raven".to_owned(),
+                    password: "secret".to_owned(),
+                }),
+                no_proxy: vec![],
+                pool: None,
+            }),
+            ..create_request(&format!("{}/redirect", proxy_address))
+        },
+        vec![PersistMethod::Warc {
+            directory: directory.to_owned(),
+            prefix: "test".to_owned(),
+            max_file_size: 1024 * 1024,
+        }],
+    );
+
+    // act
+    let result = task.execute_in(&TestApp::default()).unwrap();
+
+    // assert
+    assert_eq!(result.result.persist_errors, vec![]);
+    let files: Vec<String> = read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().to_str().unwrap().to_owned())
+        .collect();
+    let records = parse_warc_file(&files[0]);
+    let request_block = String::from_utf8(records[1].block.clone()).unwrap();
+    let final_host = proxy_address
+        .trim_start_matches("http://")
+        .replace("127.0.0.1", "localhost");
+    // "cmF2ZW46c2VjcmV0" is base64 of "raven:secret".
+    let proxy_authorization = "Basic cmF2ZW46c2VjcmV0";
+    assert_eq!(
+        request_block,
+        format!(
+            "GET /final HTTP/1.1\r\nhost: {}\r\naccept-encoding: gzip\r\n\
+             proxy-authorization: {}\r\nuser-agent: raven\r\n\r\n",
+            final_host, proxy_authorization
+        )
+    );
+    assert_eq!(
+        *received.lock().unwrap(),
+        vec![Some(proxy_authorization.to_owned()); 2]
+    );
+}